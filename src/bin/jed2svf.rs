@@ -0,0 +1,34 @@
+use jtag::*;
+
+fn main() -> Result<(), JTAGError> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() != 3 && args.len() != 4 {
+        eprintln!("Usage: {} in.jed out.svf [xc2cNN.map]", args[0]);
+        std::process::exit(2);
+    }
+
+    let jed = Jed::parse(&std::fs::read(&args[1])?)?;
+    let device = jed
+        .device()
+        .ok_or_else(|| ParseError::new("JED", "no device name"))?;
+
+    let svf = if let Some(device) = Xc2cDevice::from_name(device) {
+        let map = match args.get(3) {
+            Some(map) => Xc2cFuseMap::parse(device, &std::fs::read_to_string(map)?)?,
+            None => Xc2cFuseMap::generate(device),
+        };
+        CoolRunner2::new(&map).program_svf(&jed, false)?
+    } else if let Some(device) = Xc9500XlDevice::from_name(device) {
+        Xc9500Xl::new(device).program_svf(&jed)?
+    } else {
+        check_xc9500_5v(device)?;
+        return Err(JTAGError::InvalidAction(format!(
+            "unsupported device {device}"
+        )));
+    };
+
+    std::fs::write(&args[2], svf.to_string())?;
+    println!("wrote {} statements", svf.commands.len());
+
+    Ok(())
+}
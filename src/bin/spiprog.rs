@@ -0,0 +1,52 @@
+use jtag::*;
+
+fn main() -> Result<(), JTAGError> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() != 4 && !(args.len() == 3 && ["id", "erase"].contains(&args[2].as_str())) {
+        println!(
+            "Usage: {} proxy.bit id|program|verify|read|erase [file.bin]",
+            args[0]
+        );
+        return Ok(());
+    }
+
+    let mut adapter = jtag::drivers::FTDIJTAG::new()?;
+    adapter.reset_to_tlr();
+    let fpga = Series7::detect(&mut adapter)?;
+    println!("found {}", fpga.part().name);
+    let proxy = BitFile::parse(&std::fs::read(&args[1])?)?;
+    fpga.program_bit_file(&mut adapter, &proxy)?;
+
+    let flash = SpiFlash::probe(JtagSpi::new(fpga.bscan(BscanUser::User1)), &mut adapter)?;
+    let info = flash.info();
+    println!(
+        "found flash {:02x}{:02x}{:02x} with {} bytes",
+        info.jedec_id[0], info.jedec_id[1], info.jedec_id[2], info.size
+    );
+    match args[2].as_str() {
+        "id" => {}
+        "program" => {
+            let data = std::fs::read(&args[3])?;
+            flash.program(&mut adapter, 0, &data)?;
+            println!("programmed and verified {} bytes", data.len());
+        }
+        "verify" => {
+            let data = std::fs::read(&args[3])?;
+            flash.verify(&mut adapter, 0, &data)?;
+            println!("verified");
+        }
+        "read" => {
+            std::fs::write(&args[3], flash.read(&mut adapter, 0, info.size)?)?;
+            println!("read {} bytes", info.size);
+        }
+        "erase" => {
+            flash.erase_chip(&mut adapter)?;
+            println!("erased");
+        }
+        x => println!("unknown command {x}"),
+    }
+
+    // reload the design from the flash
+    fpga.clear(&mut adapter)?;
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use jtag::*;
+
+fn main() -> Result<(), JTAGError> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() < 2 {
+        println!("Usage: {} file.jam [action [procedure=0|1]...]", args[0]);
+        return Ok(());
+    }
+
+    let stapl = Stapl::parse(&std::fs::read_to_string(&args[1])?)?;
+    if args.len() == 2 && !stapl.actions.is_empty() {
+        for action in &stapl.actions {
+            println!(
+                "{} {}",
+                action.name,
+                action.description.as_deref().unwrap_or("")
+            );
+            for step in &action.procedures {
+                let kind = match (step.optional, step.recommended) {
+                    (_, true) => " (recommended)",
+                    (true, false) => " (optional)",
+                    _ => "",
+                };
+                println!("    {}{kind}", step.procedure);
+            }
+        }
+        return Ok(());
+    }
+
+    let mut player = StaplPlayer::new(&stapl);
+    for arg in args.iter().skip(3) {
+        let (procedure, enabled) = match arg.split_once('=') {
+            Some((procedure, value)) => (procedure, value != "0"),
+            None => (arg.as_str(), true),
+        };
+        player = player.with_procedure(procedure, enabled);
+    }
+
+    let mut adapter = jtag::drivers::FTDIJTAG::new()?;
+    let code = player.run(&mut adapter, args.get(2).map(|x| x.as_str()));
+    for line in player.output() {
+        println!("{line}");
+    }
+    for (key, value) in player.exports() {
+        println!("{key} = {value}");
+    }
+    let code = code?;
+    println!(
+        "exit code {code} ({})",
+        Stapl::exit_code_description(code).unwrap_or("unknown")
+    );
+    std::process::exit(code);
+}
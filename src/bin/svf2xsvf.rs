@@ -0,0 +1,29 @@
+use jtag::*;
+
+fn main() -> Result<(), JTAGError> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() != 3 && args.len() != 4 {
+        println!("Usage: {} in.svf out.xsvf [repeat]", args[0]);
+        return Ok(());
+    }
+
+    let repeat = match args.get(3) {
+        Some(repeat) => repeat
+            .parse()
+            .map_err(|_| JTAGError::InvalidAction(format!("invalid repeat count {repeat}")))?,
+        None => 32,
+    };
+
+    let svf = Svf::parse(&std::fs::read_to_string(&args[1])?)?;
+    let xsvf = Xsvf::from_svf(&svf, repeat)?;
+    let bytes = xsvf.to_bytes()?;
+    std::fs::write(&args[2], &bytes)?;
+    println!(
+        "wrote {} commands ({} bytes) from {} statements",
+        xsvf.commands.len(),
+        bytes.len(),
+        svf.commands.len()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use jtag::*;
+
+fn main() -> Result<(), JTAGError> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() != 2 {
+        println!("Usage: {} file.svf|file.xsvf", args[0]);
+        return Ok(());
+    }
+
+    let mut adapter = jtag::drivers::FTDIJTAG::new()?;
+    if args[1].to_ascii_lowercase().ends_with(".xsvf") {
+        let xsvf = Xsvf::parse(&std::fs::read(&args[1])?)?;
+        xsvf.play(&mut adapter)?;
+        println!("played {} commands", xsvf.commands.len());
+    } else {
+        let svf = Svf::parse(&std::fs::read_to_string(&args[1])?)?;
+        svf.play(&mut adapter)?;
+        println!("played {} statements", svf.commands.len());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use jtag::*;
+
+fn main() -> Result<(), JTAGError> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let interval_ms = match args.get(1).map(|x| x.parse::<u64>()) {
+        None => None,
+        Some(Ok(x)) => Some(x),
+        Some(Err(_)) => {
+            println!("Usage: {} [interval_ms]", args[0]);
+            return Ok(());
+        }
+    };
+
+    let mut adapter = jtag::drivers::FTDIJTAG::new()?;
+    adapter.reset_to_tlr();
+    let fpga = Series7::detect(&mut adapter)?;
+    println!("found {}", fpga.part().name);
+
+    let xadc = fpga.xadc();
+    xadc.poll(
+        &mut adapter,
+        interval_ms.unwrap_or(0) * 1_000_000,
+        |readings| {
+            println!(
+                "{:.1} C, VCCINT {:.3} V, VCCAUX {:.3} V, VCCBRAM {:.3} V",
+                readings.temperature, readings.vccint, readings.vccaux, readings.vccbram
+            );
+            interval_ms.is_some()
+        },
+    )
+}
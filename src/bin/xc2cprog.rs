@@ -0,0 +1,71 @@
+use bitvec::prelude::*;
+use jtag::*;
+
+fn main() -> Result<(), JTAGError> {
+    let mut args = std::env::args().collect::<Vec<_>>();
+    let secure = args.len() > 2 && args[2] == "--secure";
+    if secure {
+        args.remove(2);
+    }
+    let files = if args.get(1).is_some_and(|x| x == "erase") {
+        0
+    } else {
+        1
+    };
+    if args.len() < 2 + files || args.len() > 3 + files {
+        println!(
+            "Usage: {0} program|verify|read|erase [xc2cNN.map] [file.jed]\n       {0} program --secure xc2cNN.map file.jed",
+            args[0]
+        );
+        return Ok(());
+    }
+    let map = (args.len() == 3 + files).then(|| &args[2]);
+    if secure && (args[1] != "program" || map.is_none()) {
+        return Err(JTAGError::InvalidAction(
+            "--secure is only for program, and needs an ISE map file to locate the security bits"
+                .to_string(),
+        ));
+    }
+    let file = &args[args.len() - 1];
+
+    let mut adapter = jtag::drivers::FTDIJTAG::new()?;
+    let idcode = IdCode(
+        adapter
+            .read_reg(bits![1, 0, 0, 0, 0, 0, 0, 0], 32)?
+            .load_le(),
+    );
+    let device = Xc2cDevice::from_idcode(idcode).ok_or(JTAGError::DeviceNotFound)?;
+    println!("found {}", device.name());
+
+    // The generated map doesn't locate the security bits, so use the ISE
+    // map file if one is given
+    let map = match map {
+        Some(map) => Xc2cFuseMap::parse(device, &std::fs::read_to_string(map)?)?,
+        None => Xc2cFuseMap::generate(device),
+    };
+    let cpld = CoolRunner2::new(&map);
+    match args[1].as_str() {
+        "program" => {
+            let jed = Jed::parse(&std::fs::read(file)?)?;
+            cpld.program(&mut adapter, &jed, secure)?;
+            println!("programmed and verified");
+        }
+        "verify" => {
+            let jed = Jed::parse(&std::fs::read(file)?)?;
+            cpld.verify(&mut adapter, &jed)?;
+            println!("verified");
+        }
+        "read" => {
+            let jed = cpld.read(&mut adapter)?;
+            std::fs::write(file, jed.to_bytes(64))?;
+            println!("read {} fuses", jed.fuses.len());
+        }
+        "erase" => {
+            cpld.erase(&mut adapter)?;
+            println!("erased");
+        }
+        x => println!("unknown command {x}"),
+    }
+
+    Ok(())
+}
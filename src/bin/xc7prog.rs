@@ -0,0 +1,29 @@
+use jtag::*;
+
+fn main() -> Result<(), JTAGError> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() != 2 {
+        println!("Usage: {} file.bit|file.bin", args[0]);
+        return Ok(());
+    }
+
+    let data = std::fs::read(&args[1])?;
+    let mut adapter = jtag::drivers::FTDIJTAG::new()?;
+    adapter.reset_to_tlr();
+    let fpga = Series7::detect(&mut adapter)?;
+    println!("found {}", fpga.part().name);
+
+    if args[1].ends_with(".bit") {
+        let bit = BitFile::parse(&data)?;
+        println!(
+            "{} for {} built {} {}",
+            bit.design, bit.part, bit.date, bit.time
+        );
+        fpga.program_bit_file(&mut adapter, &bit)?;
+    } else {
+        fpga.program(&mut adapter, &data)?;
+    }
+    println!("configured");
+
+    Ok(())
+}
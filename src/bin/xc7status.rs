@@ -0,0 +1,16 @@
+use jtag::*;
+
+fn main() -> Result<(), JTAGError> {
+    let mut adapter = jtag::drivers::FTDIJTAG::new()?;
+    adapter.reset_to_tlr();
+    let fpga = Series7::detect(&mut adapter)?;
+    println!("found {}", fpga.part().name);
+    println!("DNA: {:#x}", fpga.read_dna(&mut adapter)?);
+    println!("USERCODE: {:#010x}", fpga.read_usercode(&mut adapter)?);
+    println!("FUSE_USER: {:#010x}", fpga.read_fuse_user(&mut adapter)?);
+    println!("FUSE_CNTL: {:#010x}", fpga.read_fuse_cntl(&mut adapter)?);
+    println!("{:#?}", fpga.ir_status(&mut adapter)?);
+    println!("{:#?}", fpga.read_registers(&mut adapter)?);
+
+    Ok(())
+}
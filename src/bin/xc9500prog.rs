@@ -0,0 +1,48 @@
+use bitvec::prelude::*;
+use jtag::*;
+
+fn main() -> Result<(), JTAGError> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() != 3 && !(args.len() == 2 && args[1] == "erase") {
+        println!("Usage: {} program|verify|read|erase [file.jed]", args[0]);
+        return Ok(());
+    }
+
+    let mut adapter = jtag::drivers::FTDIJTAG::new()?;
+    let idcode = IdCode(
+        adapter
+            .read_reg(bits![0, 1, 1, 1, 1, 1, 1, 1], 32)?
+            .load_le(),
+    );
+    if let Some(part) = idcode.part() {
+        check_xc9500_5v(part.name)?;
+    }
+    let device = Xc9500XlDevice::from_idcode(idcode).ok_or(JTAGError::DeviceNotFound)?;
+    println!("found {}", device.name());
+
+    let cpld = Xc9500Xl::new(device);
+    match args[1].as_str() {
+        "program" => {
+            let jed = Jed::parse(&std::fs::read(&args[2])?)?;
+            cpld.program(&mut adapter, &jed)?;
+            println!("programmed and verified");
+        }
+        "verify" => {
+            let jed = Jed::parse(&std::fs::read(&args[2])?)?;
+            cpld.verify(&mut adapter, &jed)?;
+            println!("verified");
+        }
+        "read" => {
+            let jed = cpld.read(&mut adapter)?;
+            std::fs::write(&args[2], jed.to_bytes(64))?;
+            println!("read {} fuses", jed.fuses.len());
+        }
+        "erase" => {
+            cpld.erase(&mut adapter, true)?;
+            println!("erased");
+        }
+        x => println!("unknown command {x}"),
+    }
+
+    Ok(())
+}
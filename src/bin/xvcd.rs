@@ -4,15 +4,15 @@ use jtag::*;
 use std::io::{Read, Write};
 use std::net::TcpListener;
 
-fn main() {
+fn main() -> Result<(), JTAGError> {
     println!("Hello world!");
 
-    let mut adapter = jtag::drivers::FTDIJTAG::new();
+    let mut adapter = jtag::drivers::FTDIJTAG::new()?;
 
-    let listener = TcpListener::bind("0.0.0.0:2542").unwrap();
-    let (mut sock, addr) = listener.accept().unwrap();
+    let listener = TcpListener::bind("0.0.0.0:2542")?;
+    let (mut sock, addr) = listener.accept()?;
     println!("connected to {:?}", addr);
-    sock.set_nodelay(true).unwrap();
+    sock.set_nodelay(true)?;
 
     let mut current_state = JTAGState::TestLogicReset;
 
@@ -22,41 +22,41 @@ fn main() {
         let mut tdibuf = [0u8; 512];
         let mut tdobuf;
 
-        let peeklen = sock.peek(&mut cmdbuf[..2]).unwrap();
+        let peeklen = sock.peek(&mut cmdbuf[..2])?;
         if peeklen != 2 {
             println!("bad read");
             break;
         }
         match cmdbuf[..2] {
             [b'g', b'e'] => {
-                sock.read(&mut cmdbuf[..8]).unwrap();
+                sock.read_exact(&mut cmdbuf[..8])?;
                 if cmdbuf[..8] != [b'g', b'e', b't', b'i', b'n', b'f', b'o', b':'] {
                     println!("unknown command! {:x?}", cmdbuf);
                     continue;
                 }
-                sock.write(b"xvcServer_v1.0:5120\n").unwrap();
+                sock.write_all(b"xvcServer_v1.0:5120\n")?;
             }
             [b's', b'h'] => {
-                sock.read(&mut cmdbuf[..6]).unwrap();
+                sock.read_exact(&mut cmdbuf[..6])?;
                 if cmdbuf[..6] != [b's', b'h', b'i', b'f', b't', b':'] {
                     println!("unknown command! {:x?}", cmdbuf);
                     continue;
                 }
-                sock.read(&mut cmdbuf[..4]).unwrap();
+                sock.read_exact(&mut cmdbuf[..4])?;
                 let num_bits = (cmdbuf[0] as usize)
                     | ((cmdbuf[1] as usize) << 8)
                     | ((cmdbuf[2] as usize) << 16)
                     | ((cmdbuf[3] as usize) << 24);
-                let num_bytes = (num_bits + 7) / 8;
+                let num_bytes = num_bits.div_ceil(8);
                 println!("shift {} bits ({} bytes)", num_bits, num_bytes);
                 if num_bytes > 512 {
                     println!("too long!");
                     continue;
                 }
-                sock.read(&mut tmsbuf[..num_bytes]).unwrap();
-                sock.read(&mut tdibuf[..num_bytes]).unwrap();
+                sock.read_exact(&mut tmsbuf[..num_bytes])?;
+                sock.read_exact(&mut tdibuf[..num_bytes])?;
 
-                tdobuf = tdibuf.clone();
+                tdobuf = tdibuf;
 
                 println!("{:x?} {:x?}", &tmsbuf[..num_bytes], &tdibuf[..num_bytes]);
 
@@ -135,7 +135,7 @@ fn main() {
                     });
                     q2.push((shift_start_idx, i));
                 }
-                if accum_states.len() > 0 {
+                if !accum_states.is_empty() {
                     q.push(JTAGAction::GoViaStates(accum_states));
                     q2.push((0, 0));
                 }
@@ -143,7 +143,7 @@ fn main() {
                 println!("q2 {:?}", q2);
                 assert_eq!(q.len(), q2.len());
 
-                let result = adapter.execute_actions(&q);
+                let result = adapter.execute_actions(&q)?;
                 // println!("out {:?}", result);
                 assert_eq!(q2.len(), result.len());
 
@@ -157,11 +157,13 @@ fn main() {
 
                 // println!("{:x?}", tdo_vec);
 
-                sock.write(&tdobuf[..num_bytes]).unwrap();
+                sock.write_all(&tdobuf[..num_bytes])?;
             }
             _ => {
                 println!("unknown command! peeked {:x?}", cmdbuf);
             }
         }
     }
+
+    Ok(())
 }
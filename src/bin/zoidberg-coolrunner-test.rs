@@ -1,67 +1,44 @@
 use bitvec::prelude::*;
 use jtag::JTAGAdapter;
 
-fn main() {
+fn main() -> Result<(), jtag::JTAGError> {
     println!("Hello world!");
 
-    let mut adapter = jtag::drivers::FTDIJTAG::new();
-    adapter.reset_to_tlr();
-    adapter.go_rti();
-
-    let idcode = adapter.shift_dr_inout(bits![0; 32], false);
-
-    let mut idcode_ = 0u32;
-    for (i, bit) in idcode.into_iter().enumerate() {
-        if bit {
-            idcode_ |= 1 << i;
+    let mut adapter = jtag::drivers::FTDIJTAG::new()?;
+    for (i, device) in jtag::discover_chain(&mut adapter)?.iter().enumerate() {
+        match device.idcode {
+            Some(idcode) => println!("device {i}: idcode {idcode}"),
+            None => println!("device {i}: bypass"),
         }
     }
 
-    println!("idcode {idcode_:08X}");
-
-    let idcode2 = adapter.shift_dr_inout(bits![0; 32], false);
-
-    let mut idcode2_ = 0u32;
-    for (i, bit) in idcode2.into_iter().enumerate() {
-        if bit {
-            idcode2_ |= 1 << i;
-        }
-    }
+    adapter.reset_to_tlr();
+    adapter.go_rti();
 
-    println!("idcode2 {idcode2_:08X}");
+    let idcode2 = jtag::IdCode(adapter.shift_dr_inout(bits![0; 32], false)?.load_le());
+    println!("idcode2 {idcode2}");
 
     adapter.go_shiftdr();
-    let idcode3_a = adapter.shift_bits_inout(bits![0; 16], false);
-    let idcode3_b = adapter.shift_bits_inout(bits![0; 16], true);
+    let idcode3_a = adapter.shift_bits_inout(bits![0; 16], false)?;
+    let idcode3_b = adapter.shift_bits_inout(bits![0; 16], true)?;
     adapter.go_rti();
-    adapter.flush();
-
-    let mut idcode3 = 0u32;
-    for (i, bit) in idcode3_a.into_iter().enumerate() {
-        if bit {
-            idcode3 |= 1 << i;
-        }
-    }
-    for (i, bit) in idcode3_b.into_iter().enumerate() {
-        if bit {
-            idcode3 |= 1 << (i + 16);
-        }
-    }
+    adapter.flush()?;
 
-    println!("idcode3 {idcode3:08X}");
+    let mut idcode3 = idcode3_a;
+    idcode3.extend_from_bitslice(&idcode3_b);
+    let idcode3 = jtag::IdCode(idcode3.load_le());
+    println!("idcode3 {idcode3}");
 
     adapter.go_shiftdr();
     adapter.shift_bits_out(bits![0; 15], false);
-    let idcode4_b = adapter.shift_bits_inout(bits![0; 17], true);
+    let idcode4_b = adapter.shift_bits_inout(bits![0; 17], true)?;
     adapter.go_rti();
-    adapter.flush();
+    adapter.flush()?;
 
-    let mut idcode4 = 0u32;
-    for (i, bit) in idcode4_b.into_iter().enumerate() {
-        if bit {
-            idcode4 |= 1 << (i + 15);
-        }
-    }
+    let mut idcode4 = bitvec![0; 15];
+    idcode4.extend_from_bitslice(&idcode4_b);
+    let idcode4 = jtag::IdCode(idcode4.load_le());
+    println!("idcode4 (parial) {idcode4}");
 
-    println!("idcode4 (parial) {idcode4:08X}");
+    Ok(())
 }
@@ -6,7 +6,7 @@ use bitvec::prelude::*;
 /// at a time
 pub trait BitbangJTAGAdapter {
     /// Set the clock speed (i.e. the pulse width of one clock cycle)
-    fn set_clk_speed(&mut self, clk_hz: u64) -> u64;
+    fn set_clk_speed(&mut self, clk_hz: u64) -> Result<u64, JTAGError>;
     /// Shift one bit out on TMS/TDI. Capture the value of TDO.
     ///
     /// Note: This function assumes that the data capture occurs at _the end_
@@ -27,7 +27,7 @@ pub trait BitbangJTAGAdapter {
     /// TDO  ╳ prev val-----╳ new val
     /// capture     ^
     /// </pre>
-    fn shift_one_bit(&mut self, tms: bool, tdi: bool) -> bool;
+    fn shift_one_bit(&mut self, tms: bool, tdi: bool) -> Result<bool, JTAGError>;
 }
 
 /// State required to be stored by a [BitbangJTAGAdapter] in order for
@@ -48,56 +48,53 @@ impl BitbangJTAGAdapterState {
         }
     }
 }
+impl Default for BitbangJTAGAdapterState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 /// Automatically turn [BitbangJTAGAdapter] into a [ChunkShifterJTAGAdapter]
 impl<T: BitbangJTAGAdapter + AsMut<BitbangJTAGAdapterState>> ChunkShifterJTAGAdapter for T {
-    fn delay_ns(&mut self, ns: u64) -> u64 {
+    fn delay_ns(&mut self, ns: u64) -> Result<u64, JTAGError> {
         std::thread::sleep(std::time::Duration::from_nanos(ns));
-        ns
+        Ok(ns)
     }
-    fn set_clk_speed(&mut self, clk_hz: u64) -> u64 {
+    fn set_clk_speed(&mut self, clk_hz: u64) -> Result<u64, JTAGError> {
         BitbangJTAGAdapter::set_clk_speed(self, clk_hz)
     }
 
-    fn shift_tms_chunk(&mut self, tms_chunk: &BitSlice) {
+    fn shift_tms_chunk(&mut self, tms_chunk: &BitSlice) -> Result<(), JTAGError> {
         for tms in tms_chunk {
-            let tdo = self.shift_one_bit(*tms, false);
+            let tdo = self.shift_one_bit(*tms, false)?;
             // XXX this can be optimized maybe
             let state_data: &mut BitbangJTAGAdapterState = self.as_mut();
             state_data.last_tdo = tdo;
         }
+        Ok(())
     }
-    fn shift_tdi_chunk(&mut self, tdi_chunk: &BitSlice, tms_exit: bool) {
-        for (i, tdi) in tdi_chunk.into_iter().enumerate() {
-            let tdo = self.shift_one_bit(
-                if tms_exit && i == tdi_chunk.len() - 1 {
-                    true
-                } else {
-                    false
-                },
-                *tdi,
-            );
+    fn shift_tdi_chunk(&mut self, tdi_chunk: &BitSlice, tms_exit: bool) -> Result<(), JTAGError> {
+        for (i, tdi) in tdi_chunk.iter().enumerate() {
+            let tdo = self.shift_one_bit(tms_exit && i == tdi_chunk.len() - 1, *tdi)?;
             // XXX this can be optimized maybe
             let state_data: &mut BitbangJTAGAdapterState = self.as_mut();
             state_data.last_tdo = tdo;
         }
+        Ok(())
     }
-    fn shift_tditdo_chunk(&mut self, tdi_chunk: &BitSlice, tms_exit: bool) -> BitVec {
+    fn shift_tditdo_chunk(
+        &mut self,
+        tdi_chunk: &BitSlice,
+        tms_exit: bool,
+    ) -> Result<BitVec, JTAGError> {
         let mut ret: BitVec = BitVec::with_capacity(tdi_chunk.len());
-        if tdi_chunk.len() > 0 {
+        if !tdi_chunk.is_empty() {
             let state_data: &mut BitbangJTAGAdapterState = self.as_mut();
             ret.push(state_data.last_tdo);
         }
 
-        for (i, tdi) in tdi_chunk.into_iter().enumerate() {
-            let tdo = self.shift_one_bit(
-                if tms_exit && i == tdi_chunk.len() - 1 {
-                    true
-                } else {
-                    false
-                },
-                *tdi,
-            );
+        for (i, tdi) in tdi_chunk.iter().enumerate() {
+            let tdo = self.shift_one_bit(tms_exit && i == tdi_chunk.len() - 1, *tdi)?;
             // XXX this can be optimized maybe
             let state_data: &mut BitbangJTAGAdapterState = self.as_mut();
             state_data.last_tdo = tdo;
@@ -106,6 +103,6 @@ impl<T: BitbangJTAGAdapter + AsMut<BitbangJTAGAdapterState>> ChunkShifterJTAGAda
                 ret.push(tdo);
             }
         }
-        ret
+        Ok(ret)
     }
 }
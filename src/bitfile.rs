@@ -0,0 +1,166 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+/// The fixed first field of a .bit file
+const MAGIC: &[u8] = &[0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x00];
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash, Default)]
+/// A Xilinx .bit file, i.e. a bitstream with a header describing it
+pub struct BitFile {
+    /// Design name, which may be followed by other information such as
+    /// `;UserID=0XFFFFFFFF;Version=2020.2`
+    pub design: String,
+    /// Part the bitstream is for, including the package (e.g. `7a35tcpg236`)
+    pub part: String,
+    pub date: String,
+    pub time: String,
+    /// The bitstream itself, which is the same as the contents of a .bin file
+    pub data: Vec<u8>,
+}
+
+fn parse_err(msg: impl Into<String>) -> JTAGError {
+    ParseError::new(".bit", msg).into()
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize, what: &str) -> Result<&'a [u8], JTAGError> {
+    if data.len() < len {
+        return Err(parse_err(format!("{what} is truncated")));
+    }
+    let (ret, rest) = data.split_at(len);
+    *data = rest;
+    Ok(ret)
+}
+
+impl BitFile {
+    pub fn parse(data: &[u8]) -> Result<Self, JTAGError> {
+        let mut data = data;
+        let len = take(&mut data, 2, "header")?;
+        let magic = take(
+            &mut data,
+            u16::from_be_bytes([len[0], len[1]]) as usize,
+            "header",
+        )?;
+        if magic != MAGIC {
+            return Err(parse_err("not a .bit file"));
+        }
+        // length of the (missing) value of the first key
+        take(&mut data, 2, "header")?;
+
+        let mut ret = Self::default();
+        loop {
+            let key = take(&mut data, 1, "header")?[0];
+            if key == b'e' {
+                let len = take(&mut data, 4, "header")?;
+                let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+                ret.data = take(&mut data, len, "bitstream")?.to_vec();
+                return Ok(ret);
+            }
+
+            let len = take(&mut data, 2, "header")?;
+            let value = take(
+                &mut data,
+                u16::from_be_bytes([len[0], len[1]]) as usize,
+                "header",
+            )?;
+            // strings are NUL terminated
+            let value = String::from_utf8_lossy(value)
+                .trim_end_matches('\0')
+                .to_string();
+            match key {
+                b'a' => ret.design = value,
+                b'b' => ret.part = value,
+                b'c' => ret.date = value,
+                b'd' => ret.time = value,
+                _ => return Err(parse_err(format!("unknown field {:?}", key as char))),
+            }
+        }
+    }
+
+    /// The bitstream as the bits to shift into the CFG_IN register, i.e.
+    /// with the MSB of each byte first
+    pub fn data_bits(&self) -> BitVec {
+        series7::bitstream_bits(&self.data)
+    }
+
+    /// Check whether the bitstream is for `part` (regardless of package and
+    /// speed grade)
+    pub fn matches_part(&self, part: &KnownPart) -> bool {
+        let name = part.name.to_ascii_lowercase();
+        let name = name.strip_prefix("xc").unwrap_or(&name);
+        let target = self.part.to_ascii_lowercase();
+        let target = target.strip_prefix("xc").unwrap_or(&target);
+        // e.g. 7s6 must not match 7s60
+        target
+            .strip_prefix(name)
+            .is_some_and(|rest| !rest.starts_with(|c: char| c.is_ascii_digit()))
+    }
+
+    /// Check that the bitstream is for the device with the given IDCODE
+    pub fn check_idcode(&self, idcode: IdCode) -> Result<(), JTAGError> {
+        match idcode.part() {
+            Some(part) if self.matches_part(part) => Ok(()),
+            _ => Err(JTAGError::InvalidAction(format!(
+                "bitstream is for {} but the device is {idcode}",
+                self.part
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn field(key: u8, value: &str) -> Vec<u8> {
+        let mut ret = vec![key];
+        ret.extend_from_slice(&(value.len() as u16 + 1).to_be_bytes());
+        ret.extend_from_slice(value.as_bytes());
+        ret.push(0);
+        ret
+    }
+
+    fn test_bit_file(part: &str, data: &[u8]) -> Vec<u8> {
+        let mut ret = vec![
+            0x00, 0x09, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x00,
+        ];
+        ret.extend_from_slice(&[0x00, 0x01]);
+        ret.extend(field(b'a', "top;UserID=0XFFFFFFFF;Version=2020.2"));
+        ret.extend(field(b'b', part));
+        ret.extend(field(b'c', "2023/01/02"));
+        ret.extend(field(b'd', "12:34:56"));
+        ret.push(b'e');
+        ret.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        ret.extend_from_slice(data);
+        ret
+    }
+
+    #[test]
+    fn test_bitfile_parse() {
+        let data = test_bit_file("7a35tcpg236", &[0xaa, 0x99, 0x55, 0x66]);
+        let bit = BitFile::parse(&data).unwrap();
+        assert_eq!(bit.design, "top;UserID=0XFFFFFFFF;Version=2020.2");
+        assert_eq!(bit.part, "7a35tcpg236");
+        assert_eq!(bit.date, "2023/01/02");
+        assert_eq!(bit.time, "12:34:56");
+        assert_eq!(bit.data, [0xaa, 0x99, 0x55, 0x66]);
+        assert_eq!(bit.data_bits().len(), 32);
+        assert!(bit.data_bits()[0]);
+
+        assert!(BitFile::parse(&data[..data.len() - 1]).is_err());
+        assert!(BitFile::parse(&data[1..]).is_err());
+    }
+
+    #[test]
+    fn test_bitfile_check_idcode() {
+        let bit = BitFile::parse(&test_bit_file("7a35tcpg236", &[])).unwrap();
+        bit.check_idcode(IdCode(0x1362_d093)).unwrap();
+        assert!(bit.check_idcode(IdCode(0x0362_c093)).is_err());
+        assert!(bit.check_idcode(IdCode(0x1234_5093)).is_err());
+
+        let bit = BitFile::parse(&test_bit_file("xc7s6ftgb196", &[])).unwrap();
+        bit.check_idcode(IdCode(0x0362_2093)).unwrap();
+        let bit = BitFile::parse(&test_bit_file("7s60ftgb196", &[])).unwrap();
+        assert!(bit.check_idcode(IdCode(0x0362_2093)).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+/// Access to the pins of a device through its boundary scan register
+///
+/// This keeps track of the data which will be shifted into the boundary
+/// scan register (initially every cell's safe value) as well as the data
+/// which was most recently captured from it. The adapter to use is passed
+/// into each method, so this works equally well with a [ScanChainDevice].
+pub struct BoundaryScan<'a> {
+    bsdl: &'a Bsdl,
+    data: BitVec,
+    captured: Option<BitVec>,
+}
+
+fn port_eq(a: &str, b: &str) -> bool {
+    a.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .eq(b
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_ascii_uppercase()))
+}
+
+impl<'a> BoundaryScan<'a> {
+    pub fn new(bsdl: &'a Bsdl) -> Result<Self, JTAGError> {
+        for insn in ["SAMPLE", "EXTEST"] {
+            if bsdl.opcode(insn).is_none() {
+                return Err(JTAGError::InvalidAction(format!(
+                    "{} has no {insn} instruction",
+                    bsdl.entity
+                )));
+            }
+        }
+
+        let mut ret = Self {
+            bsdl,
+            data: BitVec::repeat(false, bsdl.boundary_length),
+            captured: None,
+        };
+        ret.release_all();
+        Ok(ret)
+    }
+
+    /// Data which will be shifted into the boundary scan register
+    pub fn data(&self) -> &BitSlice {
+        &self.data
+    }
+    /// Data which was most recently captured from the boundary scan register
+    pub fn captured(&self) -> Option<&BitSlice> {
+        self.captured.as_deref()
+    }
+
+    /// Reset every cell to its safe value (treating "don't care" as 0),
+    /// which normally disables all outputs
+    pub fn release_all(&mut self) {
+        for cell in &self.bsdl.boundary_cells {
+            self.data.set(cell.number, cell.safe.unwrap_or(false));
+        }
+    }
+
+    fn find_cell(
+        &self,
+        port: &str,
+        functions: &[BoundaryCellFunction],
+    ) -> Result<&'a BoundaryCell, JTAGError> {
+        self.bsdl
+            .boundary_cells
+            .iter()
+            .find(|cell| {
+                functions.contains(&cell.function)
+                    && cell.port.as_deref().is_some_and(|x| port_eq(x, port))
+            })
+            .ok_or_else(|| {
+                JTAGError::InvalidAction(format!(
+                    "{} has no {functions:?} cell for {port}",
+                    self.bsdl.entity
+                ))
+            })
+    }
+
+    /// Set the value that `port` will drive once the data is shifted in while
+    /// in EXTEST. `None` disables the output driver.
+    pub fn set_pin(&mut self, port: &str, value: Option<bool>) -> Result<(), JTAGError> {
+        let cell = self.find_cell(
+            port,
+            &[
+                BoundaryCellFunction::Output2,
+                BoundaryCellFunction::Output3,
+                BoundaryCellFunction::Bidir,
+            ],
+        )?;
+
+        match (cell.control_cell, cell.disable_value) {
+            (Some(control_cell), Some(disable_value)) => {
+                let control = if value.is_some() {
+                    !disable_value
+                } else {
+                    disable_value
+                };
+                self.data.set(control_cell, control);
+            }
+            _ => {
+                if value.is_none() {
+                    return Err(JTAGError::InvalidAction(format!(
+                        "{port} cannot be disabled"
+                    )));
+                }
+            }
+        }
+        if let Some(value) = value {
+            self.data.set(cell.number, value);
+        }
+        Ok(())
+    }
+
+    /// Get the value of `port` from the most recently captured data
+    pub fn get_pin(&self, port: &str) -> Result<bool, JTAGError> {
+        let cell = self.find_cell(
+            port,
+            &[
+                BoundaryCellFunction::Input,
+                BoundaryCellFunction::Clock,
+                BoundaryCellFunction::Bidir,
+                BoundaryCellFunction::ObserveOnly,
+            ],
+        )?;
+        let captured = self.captured.as_ref().ok_or_else(|| {
+            JTAGError::InvalidAction("boundary scan register has not been captured".to_string())
+        })?;
+        Ok(captured[cell.number])
+    }
+
+    /// Select SAMPLE and capture the state of all pins without disturbing
+    /// the device's normal operation. The current data is shifted in at the
+    /// same time (i.e. this also acts as PRELOAD on most devices).
+    pub fn sample<T: JTAGAdapter + ?Sized>(&mut self, adapter: &mut T) -> Result<(), JTAGError> {
+        adapter.shift_ir_out(self.bsdl.opcode("SAMPLE").unwrap(), false);
+        self.captured = Some(adapter.shift_dr_inout(&self.data, false)?);
+        Ok(())
+    }
+
+    /// Select PRELOAD (or SAMPLE if PRELOAD is not separately listed) and
+    /// shift in the current data so that it will be driven as soon as
+    /// EXTEST is selected.
+    ///
+    /// This is a buffered action that returns immediately
+    pub fn preload<T: JTAGAdapter + ?Sized>(&mut self, adapter: &mut T) {
+        let opcode = self
+            .bsdl
+            .opcode("PRELOAD")
+            .or_else(|| self.bsdl.opcode("SAMPLE"))
+            .unwrap();
+        adapter.shift_ir_out(opcode, false);
+        adapter.shift_dr_out(&self.data, false);
+    }
+
+    /// Preload the current data and then select EXTEST, which takes control
+    /// of the device's pins
+    pub fn extest<T: JTAGAdapter + ?Sized>(&mut self, adapter: &mut T) -> Result<(), JTAGError> {
+        self.preload(adapter);
+        adapter.shift_ir_out(self.bsdl.opcode("EXTEST").unwrap(), false);
+        adapter.flush()?;
+        Ok(())
+    }
+
+    /// Shift the current data into the boundary scan register while
+    /// capturing the state of all pins. This assumes that EXTEST has already
+    /// been selected using [extest][Self::extest].
+    pub fn update<T: JTAGAdapter + ?Sized>(&mut self, adapter: &mut T) -> Result<(), JTAGError> {
+        self.captured = Some(adapter.shift_dr_inout(&self.data, false)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    fn test_adapter() -> drivers::SimulatedJTAGAdapter {
+        drivers::SimulatedJTAGAdapter::new(vec![Box::new(
+            drivers::GenericSimulatedDevice::new(4)
+                .with_idcode(0x0123_4093, bits![0, 1, 1, 0])
+                // SAMPLE/PRELOAD
+                .with_register(bits![1, 0, 0, 0], bits![0, 1, 0, 0, 0, 0, 1])
+                // EXTEST
+                .with_register(bits![0, 0, 0, 0], bits![0; 7]),
+        )])
+    }
+
+    #[test]
+    fn test_boundaryscan_sample() {
+        let bsdl = Bsdl::parse(crate::bsdl::tests::TEST_BSDL).unwrap();
+        let mut adapter = test_adapter();
+        let mut bscan = BoundaryScan::new(&bsdl).unwrap();
+
+        assert_eq!(bscan.data(), bits![1, 0, 0, 0, 0, 0, 0]);
+        assert!(bscan.get_pin("CLK").is_err());
+
+        bscan.sample(&mut adapter).unwrap();
+        assert!(bscan.get_pin("CLK").unwrap());
+        assert!(bscan.get_pin("io(1)").unwrap());
+        assert!(!bscan.get_pin("IO(0)").unwrap());
+        assert!(bscan.get_pin("LED").is_err());
+    }
+
+    #[test]
+    fn test_boundaryscan_extest() {
+        let bsdl = Bsdl::parse(crate::bsdl::tests::TEST_BSDL).unwrap();
+        let mut adapter = test_adapter();
+        let mut bscan = BoundaryScan::new(&bsdl).unwrap();
+
+        bscan.set_pin("LED", Some(true)).unwrap();
+        bscan.set_pin("IO(1)", Some(false)).unwrap();
+        assert_eq!(bscan.data(), bits![0, 0, 0, 0, 1, 1, 0]);
+        bscan.set_pin("IO(0)", Some(true)).unwrap();
+        bscan.set_pin("IO(0)", None).unwrap();
+        assert_eq!(bscan.data(), bits![0, 0, 0, 1, 1, 1, 0]);
+        assert!(bscan.set_pin("CLK", Some(true)).is_err());
+
+        bscan.extest(&mut adapter).unwrap();
+        assert_eq!(adapter.current_ir(0), bits![0, 0, 0, 0]);
+        bscan.update(&mut adapter).unwrap();
+        let reg = adapter.read_reg(bits![0, 0, 0, 0], 7).unwrap();
+        assert_eq!(reg, bits![0, 0, 0, 1, 1, 1, 0]);
+    }
+}
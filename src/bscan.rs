@@ -0,0 +1,345 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+/// Default first byte of a frame
+const DEFAULT_HEADER: u8 = 0xa5;
+const IR_LEN: usize = 6;
+
+/// CRC-32 (the same one as Ethernet and zlib)
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn bytes_bits(data: &[u8]) -> impl Iterator<Item = bool> + '_ {
+    data.iter()
+        .flat_map(|x| x.view_bits::<Lsb0>().iter().by_vals())
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// The user instructions connected to BSCANE2 primitives with the matching
+/// JTAG_CHAIN
+pub enum BscanUser {
+    User1,
+    User2,
+    User3,
+    User4,
+}
+
+impl BscanUser {
+    /// Opcode of the instruction on 7-series devices
+    pub fn opcode(self) -> u8 {
+        match self {
+            Self::User1 => 0x02,
+            Self::User2 => 0x03,
+            Self::User3 => 0x22,
+            Self::User4 => 0x23,
+        }
+    }
+
+    /// Opcode of the instruction on UltraScale and UltraScale+ devices
+    pub fn ultrascale_opcode(self) -> u8 {
+        match self {
+            Self::User1 => 0x24,
+            Self::User2 => 0x25,
+            Self::User3 => 0x26,
+            Self::User4 => 0x27,
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// A data register implemented by user logic behind a BSCANE2 primitive of
+/// a Xilinx 7-series or UltraScale FPGA
+///
+/// Raw DR payloads of any length can be exchanged with [Bscan::write],
+/// [Bscan::read] and [Bscan::exchange]. Alternatively, [Bscan::send],
+/// [Bscan::receive] and [Bscan::transfer] wrap byte payloads in frames so
+/// that user logic only needs a shift register and a small state machine.
+///
+/// The user instructions have different opcodes on UltraScale devices, so
+/// [Series7::bscan] should be used to create this for a detected device.
+///
+/// A frame consists of a header byte, the payload length in bytes (16 bits),
+/// the payload and, if enabled, the CRC-32 of the length and payload (32
+/// bits). Every field is shifted LSB first. Frames sent by user logic must
+/// start with the first bit shifted out after Capture-DR.
+pub struct Bscan {
+    user: BscanUser,
+    ultrascale: bool,
+    header: u8,
+    crc: bool,
+}
+
+impl Bscan {
+    pub fn new(user: BscanUser) -> Self {
+        Self {
+            user,
+            ultrascale: false,
+            header: DEFAULT_HEADER,
+            crc: true,
+        }
+    }
+
+    /// Set whether to use the UltraScale opcodes (7-series by default)
+    pub fn with_ultrascale(mut self, ultrascale: bool) -> Self {
+        self.ultrascale = ultrascale;
+        self
+    }
+
+    /// Set the header byte which starts each frame (0xa5 by default)
+    pub fn with_header(mut self, header: u8) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Set whether frames end with a CRC (enabled by default)
+    pub fn with_crc(mut self, crc: bool) -> Self {
+        self.crc = crc;
+        self
+    }
+
+    pub fn user(&self) -> BscanUser {
+        self.user
+    }
+
+    fn insn(&self) -> BitVec {
+        let opcode = if self.ultrascale {
+            self.user.ultrascale_opcode()
+        } else {
+            self.user.opcode()
+        };
+        insn(opcode, IR_LEN)
+    }
+
+    /// Select the user instruction, leaving the TAP in Run-Test/Idle
+    pub fn select<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) {
+        adapter.shift_ir_out(&self.insn(), false);
+    }
+
+    /// Write a DR payload without capturing anything (buffered)
+    pub fn write<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T, dr: &BitSlice) {
+        adapter.write_reg(&self.insn(), dr);
+    }
+
+    /// Read `len` bits from the DR while shifting in zeros
+    pub fn read<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        len: usize,
+    ) -> Result<BitVec, JTAGError> {
+        adapter.read_reg(&self.insn(), len)
+    }
+
+    /// Shift `dr` into the DR and return what was shifted out
+    pub fn exchange<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        dr: &BitSlice,
+    ) -> Result<BitVec, JTAGError> {
+        self.select(adapter);
+        adapter.shift_dr_inout(dr, false)
+    }
+
+    /// Number of bits in a frame with a payload of `len` bytes
+    pub fn frame_len(&self, len: usize) -> usize {
+        8 + 16 + len * 8 + if self.crc { 32 } else { 0 }
+    }
+
+    /// Build a frame containing `payload`
+    pub fn frame(&self, payload: &[u8]) -> Result<BitVec, JTAGError> {
+        let len: u16 = payload.len().try_into().map_err(|_| {
+            JTAGError::InvalidAction(format!("{} byte payload is too long", payload.len()))
+        })?;
+        let mut data = len.to_le_bytes().to_vec();
+        data.extend_from_slice(payload);
+
+        let mut ret = BitVec::with_capacity(self.frame_len(payload.len()));
+        ret.extend(bytes_bits(&[self.header]));
+        ret.extend(bytes_bits(&data));
+        if self.crc {
+            ret.extend(bytes_bits(&crc32(&data).to_le_bytes()));
+        }
+        Ok(ret)
+    }
+
+    /// Extract the payload from a frame, ignoring anything after it
+    pub fn unframe(&self, bits: &BitSlice) -> Result<Vec<u8>, JTAGError> {
+        let bytes = bits
+            .chunks_exact(8)
+            .map(|x| x.load_le::<u8>())
+            .collect::<Vec<_>>();
+        if bytes.len() < 3 {
+            return Err(JTAGError::ProtocolViolation(
+                "BSCAN frame is truncated".to_string(),
+            ));
+        }
+        if bytes[0] != self.header {
+            return Err(JTAGError::ProtocolViolation(format!(
+                "BSCAN frame starts with {:#04x} instead of {:#04x}",
+                bytes[0], self.header
+            )));
+        }
+
+        let len = u16::from_le_bytes([bytes[1], bytes[2]]) as usize;
+        if self.frame_len(len) > bits.len() {
+            return Err(JTAGError::ProtocolViolation(format!(
+                "BSCAN frame of {len} bytes is truncated"
+            )));
+        }
+        let data = &bytes[1..3 + len];
+        if self.crc {
+            let crc = u32::from_le_bytes(bytes[3 + len..7 + len].try_into().unwrap());
+            if crc != crc32(data) {
+                return Err(JTAGError::ProtocolViolation(format!(
+                    "BSCAN frame has CRC {crc:#010x} instead of {:#010x}",
+                    crc32(data)
+                )));
+            }
+        }
+        Ok(data[2..].to_vec())
+    }
+
+    /// Send a frame to user logic
+    pub fn send<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        payload: &[u8],
+    ) -> Result<(), JTAGError> {
+        self.write(adapter, &self.frame(payload)?);
+        adapter.flush()?;
+        Ok(())
+    }
+
+    /// Receive a frame of at most `max_len` bytes from user logic
+    pub fn receive<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        max_len: usize,
+    ) -> Result<Vec<u8>, JTAGError> {
+        let bits = self.read(adapter, self.frame_len(max_len))?;
+        self.unframe(&bits)
+    }
+
+    /// Send a frame and receive the reply
+    pub fn transfer<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        payload: &[u8],
+        max_len: usize,
+    ) -> Result<Vec<u8>, JTAGError> {
+        self.write(adapter, &self.frame(payload)?);
+        self.receive(adapter, max_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    /// User logic behind BSCANE2 primitives: USER1 is a streaming register
+    /// which replies to each frame with the payload reversed, USER2 is a
+    /// plain 32-bit register (which is also USER2 of an UltraScale device)
+    struct SimBscan {
+        request: BitVec,
+        reply: BitVec,
+        user2: BitVec,
+    }
+
+    impl drivers::XilinxSimulatedDevice for SimBscan {
+        fn capture_dr(&mut self, ir: &BitSlice) -> BitVec {
+            self.request.clear();
+            match ir.load_le::<u8>() {
+                0x02 if !self.reply.is_empty() => self.reply.clone(),
+                0x03 | 0x25 => self.user2.clone(),
+                _ => bitvec![0],
+            }
+        }
+        fn shift_dr(&mut self, _ir: &BitSlice, tdi: bool) {
+            self.request.push(tdi);
+        }
+        fn update_dr(&mut self, ir: &BitSlice, dr: &BitSlice) {
+            match ir.load_le::<u8>() {
+                0x02 => {
+                    let bscan = Bscan::new(BscanUser::User1);
+                    if let Ok(mut payload) = bscan.unframe(&self.request) {
+                        payload.reverse();
+                        self.reply = bscan.frame(&payload).unwrap();
+                    }
+                }
+                0x03 | 0x25 => self.user2 = dr.to_bitvec(),
+                _ => {}
+            }
+        }
+    }
+
+    fn sim_bscan() -> drivers::SimulatedJTAGAdapter {
+        drivers::SimulatedJTAGAdapter::new(vec![Box::new(SimBscan {
+            request: BitVec::new(),
+            reply: BitVec::new(),
+            user2: bitvec![0; 32],
+        })])
+    }
+
+    #[test]
+    fn test_bscan_raw() {
+        let mut adapter = sim_bscan();
+        let bscan = Bscan::new(BscanUser::User2);
+        let value = 0x1234_5678u32
+            .view_bits::<Lsb0>()
+            .iter()
+            .by_vals()
+            .collect::<BitVec>();
+        bscan.write(&mut adapter, &value);
+        assert_eq!(
+            bscan.exchange(&mut adapter, &bitvec![1; 32]).unwrap(),
+            value
+        );
+        assert_eq!(bscan.read(&mut adapter, 32).unwrap(), bitvec![1; 32]);
+        assert_eq!(BscanUser::User4.opcode(), 0x23);
+
+        let bscan = bscan.with_ultrascale(true);
+        assert_eq!(
+            bscan.exchange(&mut adapter, &value).unwrap(),
+            bitvec![0; 32]
+        );
+        assert_eq!(bscan.read(&mut adapter, 32).unwrap(), value);
+        assert_eq!(BscanUser::User4.ultrascale_opcode(), 0x27);
+    }
+
+    #[test]
+    fn test_bscan_frames() {
+        let mut adapter = sim_bscan();
+        let bscan = Bscan::new(BscanUser::User1);
+        assert_eq!(
+            bscan.transfer(&mut adapter, &[1, 2, 3], 16).unwrap(),
+            [3, 2, 1]
+        );
+        assert_eq!(crate::bscan::crc32(b"123456789"), 0xcbf4_3926);
+
+        let mut frame = bscan.frame(&[1, 2, 3]).unwrap();
+        assert_eq!(frame.len(), bscan.frame_len(3));
+        let bit = frame[30];
+        frame.set(30, !bit);
+        assert!(bscan.unframe(&frame).is_err());
+        assert!(bscan.with_header(0x5a).unframe(&frame).is_err());
+
+        let bscan = Bscan::new(BscanUser::User1).with_crc(false);
+        let frame = bscan.frame(&[4, 5]).unwrap();
+        assert_eq!(frame.len(), 40);
+        assert_eq!(bscan.unframe(&frame).unwrap(), [4, 5]);
+    }
+}
@@ -0,0 +1,567 @@
+use crate::*;
+
+use bitvec::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// IDCODE with "don't care" bits, as found in a BSDL file
+pub struct IdCodePattern {
+    pub value: u32,
+    pub mask: u32,
+}
+impl IdCodePattern {
+    /// Returns `true` if `idcode` matches this pattern
+    pub fn matches(&self, idcode: IdCode) -> bool {
+        idcode.0 & self.mask == self.value & self.mask
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Direction of a port of a BSDL entity
+pub enum BsdlPortDirection {
+    In,
+    Out,
+    InOut,
+    Buffer,
+    Linkage,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// A port of a BSDL entity
+pub struct BsdlPort {
+    pub name: String,
+    pub direction: BsdlPortDirection,
+    /// Index range if this is a `bit_vector`, in the order it was declared
+    pub range: Option<(i64, i64)>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// An instruction of a BSDL entity
+pub struct BsdlInstruction {
+    pub name: String,
+    /// All opcodes for this instruction, with bit 0 being the bit closest
+    /// to TDO
+    pub opcodes: Vec<BitVec>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Function of a boundary scan cell
+pub enum BoundaryCellFunction {
+    Input,
+    Clock,
+    Output2,
+    Output3,
+    Control,
+    ControlR,
+    Internal,
+    Bidir,
+    ObserveOnly,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// A single cell of the boundary scan register
+pub struct BoundaryCell {
+    /// Cell number, where cell 0 is the closest to TDO
+    pub number: usize,
+    /// Cell type, e.g. `BC_1`
+    pub cell_type: String,
+    /// Port associated with this cell, e.g. `IO(3)`
+    pub port: Option<String>,
+    pub function: BoundaryCellFunction,
+    /// Safe value, or `None` if it doesn't matter
+    pub safe: Option<bool>,
+    /// Cell which controls whether this output is enabled
+    pub control_cell: Option<usize>,
+    /// Value of [control_cell][Self::control_cell] which disables this
+    /// output
+    pub disable_value: Option<bool>,
+    /// State of the output when disabled, e.g. `Z`
+    pub disable_result: Option<String>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+/// Device information parsed from an IEEE 1149.1 BSDL file
+pub struct Bsdl {
+    pub entity: String,
+    pub ports: Vec<BsdlPort>,
+    pub instruction_length: usize,
+    pub instructions: Vec<BsdlInstruction>,
+    pub idcode: Option<IdCodePattern>,
+    pub boundary_length: usize,
+    /// Boundary scan cells, sorted by cell number
+    pub boundary_cells: Vec<BoundaryCell>,
+}
+
+fn parse_err(msg: impl Into<String>) -> JTAGError {
+    ParseError::new("BSDL", msg).into()
+}
+
+// Add the line of the statement being parsed to a parse error
+fn at_line(line: usize) -> impl Fn(JTAGError) -> JTAGError {
+    move |e| match e {
+        JTAGError::Parse(e) => e.at_line(line).into(),
+        e => e,
+    }
+}
+
+// Split on `;` while ignoring anything inside quotes or parentheses, keeping
+// track of the line (relative to the start of `src`) that each statement
+// starts on
+fn split_statements(src: &str) -> Vec<(usize, String)> {
+    let mut ret = Vec::new();
+    let mut cur = String::new();
+    let mut line = 1;
+    let mut start = None;
+    let mut depth = 0usize;
+    let mut in_quotes = false;
+
+    for c in src.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '(' if !in_quotes => depth += 1,
+            ')' if !in_quotes => depth = depth.saturating_sub(1),
+            ';' if !in_quotes && depth == 0 => {
+                ret.push((start.take().unwrap_or(line), cur.trim().to_string()));
+                cur.clear();
+                continue;
+            }
+            _ => {}
+        }
+        if start.is_none() && !c.is_whitespace() {
+            start = Some(line);
+        }
+        if c == '\n' {
+            line += 1;
+        }
+        cur.push(c);
+    }
+    if let Some(start) = start {
+        ret.push((start, cur.trim().to_string()));
+    }
+    ret
+}
+
+// Join a VHDL string concatenation (`"a" & "b"`) into one string
+fn join_strings(value: &str) -> String {
+    value
+        .split('"')
+        .enumerate()
+        .filter(|(i, _)| i % 2 == 1)
+        .map(|(_, s)| s)
+        .collect()
+}
+
+// Split on `,` while ignoring anything inside parentheses
+fn split_commas(s: &str) -> Vec<&str> {
+    let mut ret = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                ret.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    ret.push(s[start..].trim());
+    ret.retain(|x| !x.is_empty());
+    ret
+}
+
+// Split `NAME (contents)` into `NAME` and `contents`
+fn split_paren(s: &str) -> Result<(&str, &str), JTAGError> {
+    let open = s
+        .find('(')
+        .ok_or_else(|| parse_err(format!("expected '(' in {s:?}")))?;
+    let close = s
+        .rfind(')')
+        .ok_or_else(|| parse_err(format!("expected ')' in {s:?}")))?;
+    if close < open {
+        return Err(parse_err(format!("mismatched parentheses in {s:?}")));
+    }
+    Ok((s[..open].trim(), &s[open + 1..close]))
+}
+
+// Convert a BSDL bit pattern (MSB first) into a BitVec (bit 0 closest to TDO)
+fn parse_bits(s: &str) -> Result<BitVec, JTAGError> {
+    s.chars()
+        .rev()
+        .map(|c| match c {
+            '0' => Ok(false),
+            '1' => Ok(true),
+            _ => Err(parse_err(format!("invalid opcode {s:?}"))),
+        })
+        .collect()
+}
+
+fn parse_usize(s: &str) -> Result<usize, JTAGError> {
+    s.trim()
+        .parse()
+        .map_err(|_| parse_err(format!("expected a number, got {s:?}")))
+}
+
+fn parse_ports(s: &str) -> Result<Vec<BsdlPort>, JTAGError> {
+    let mut ret = Vec::new();
+    for (_, decl) in split_statements(s) {
+        let Some((names, ty)) = decl.split_once(':') else {
+            return Err(parse_err(format!("invalid port declaration {decl:?}")));
+        };
+        let ty = ty.trim().to_ascii_lowercase();
+        let (dir, rest) = ty.split_once(char::is_whitespace).unwrap_or((&ty, ""));
+        let direction = match dir {
+            "in" => BsdlPortDirection::In,
+            "out" => BsdlPortDirection::Out,
+            "inout" => BsdlPortDirection::InOut,
+            "buffer" => BsdlPortDirection::Buffer,
+            "linkage" => BsdlPortDirection::Linkage,
+            _ => return Err(parse_err(format!("invalid port direction {dir:?}"))),
+        };
+        let range = if rest.trim_start().starts_with("bit_vector") {
+            let (_, range) = split_paren(rest)?;
+            let words = range.split_whitespace().collect::<Vec<_>>();
+            match words[..] {
+                [a, "to" | "downto", b] => Some((
+                    a.parse()
+                        .map_err(|_| parse_err(format!("invalid range {range:?}")))?,
+                    b.parse()
+                        .map_err(|_| parse_err(format!("invalid range {range:?}")))?,
+                )),
+                _ => return Err(parse_err(format!("invalid range {range:?}"))),
+            }
+        } else {
+            None
+        };
+
+        for name in names.split(',') {
+            ret.push(BsdlPort {
+                name: name.trim().to_string(),
+                direction,
+                range,
+            });
+        }
+    }
+    Ok(ret)
+}
+
+fn parse_instructions(s: &str) -> Result<Vec<BsdlInstruction>, JTAGError> {
+    split_commas(s)
+        .into_iter()
+        .map(|insn| {
+            let (name, opcodes) = split_paren(insn)?;
+            Ok(BsdlInstruction {
+                name: name.to_string(),
+                opcodes: split_commas(opcodes)
+                    .into_iter()
+                    .map(parse_bits)
+                    .collect::<Result<_, _>>()?,
+            })
+        })
+        .collect()
+}
+
+fn parse_idcode(s: &str) -> Result<IdCodePattern, JTAGError> {
+    let bits = s.chars().filter(|c| !c.is_whitespace()).collect::<Vec<_>>();
+    if bits.len() != 32 {
+        return Err(parse_err(format!("IDCODE {s:?} is not 32 bits")));
+    }
+
+    let mut ret = IdCodePattern { value: 0, mask: 0 };
+    for (i, c) in bits.into_iter().rev().enumerate() {
+        match c {
+            '0' => ret.mask |= 1 << i,
+            '1' => {
+                ret.mask |= 1 << i;
+                ret.value |= 1 << i;
+            }
+            'x' | 'X' => {}
+            _ => return Err(parse_err(format!("invalid IDCODE {s:?}"))),
+        }
+    }
+    Ok(ret)
+}
+
+fn parse_boundary_register(s: &str) -> Result<Vec<BoundaryCell>, JTAGError> {
+    let mut ret = split_commas(s)
+        .into_iter()
+        .map(|cell| {
+            let (number, fields) = split_paren(cell)?;
+            let fields = split_commas(fields);
+            if fields.len() != 4 && fields.len() != 7 {
+                return Err(parse_err(format!("invalid boundary cell {cell:?}")));
+            }
+
+            let function = match fields[2].to_ascii_lowercase().as_str() {
+                "input" => BoundaryCellFunction::Input,
+                "clock" => BoundaryCellFunction::Clock,
+                "output2" => BoundaryCellFunction::Output2,
+                "output3" => BoundaryCellFunction::Output3,
+                "control" => BoundaryCellFunction::Control,
+                "controlr" => BoundaryCellFunction::ControlR,
+                "internal" => BoundaryCellFunction::Internal,
+                "bidir" => BoundaryCellFunction::Bidir,
+                "observe_only" => BoundaryCellFunction::ObserveOnly,
+                x => return Err(parse_err(format!("invalid cell function {x:?}"))),
+            };
+            let parse_value = |s: &str| match s {
+                "0" => Ok(Some(false)),
+                "1" => Ok(Some(true)),
+                "x" | "X" => Ok(None),
+                _ => Err(parse_err(format!("invalid value {s:?} in {cell:?}"))),
+            };
+
+            let mut ret = BoundaryCell {
+                number: parse_usize(number)?,
+                cell_type: fields[0].to_string(),
+                port: if fields[1] == "*" {
+                    None
+                } else {
+                    Some(fields[1].replace(' ', ""))
+                },
+                function,
+                safe: parse_value(fields[3])?,
+                control_cell: None,
+                disable_value: None,
+                disable_result: None,
+            };
+            if fields.len() == 7 {
+                ret.control_cell = Some(parse_usize(fields[4])?);
+                ret.disable_value = parse_value(fields[5])?;
+                ret.disable_result = Some(fields[6].to_string());
+            }
+            Ok(ret)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    ret.sort_by_key(|cell| cell.number);
+    Ok(ret)
+}
+
+impl Bsdl {
+    /// Parse the contents of a BSDL file
+    pub fn parse(src: &str) -> Result<Self, JTAGError> {
+        let src = src
+            .lines()
+            .map(|line| line.split_once("--").map_or(line, |x| x.0))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut entity = None;
+        let mut ports = Vec::new();
+        let mut attributes = HashMap::new();
+
+        for (line, stmt) in split_statements(&src) {
+            let words = stmt.split_whitespace().collect::<Vec<_>>();
+            match words.first().map(|x| x.to_ascii_lowercase()).as_deref() {
+                Some("entity") => {
+                    if words.len() < 3 || !words[2].eq_ignore_ascii_case("is") {
+                        return Err(at_line(line)(parse_err(format!("invalid entity {stmt:?}"))));
+                    }
+                    entity = Some(words[1].to_string());
+                }
+                Some("port") => {
+                    let (_, contents) = split_paren(&stmt).map_err(at_line(line))?;
+                    ports = parse_ports(contents).map_err(at_line(line))?;
+                }
+                Some("attribute") => {
+                    // attribute NAME of ENTITY : entity is VALUE
+                    let Some((_, value)) = stmt.split_once(':') else {
+                        continue;
+                    };
+                    let value = value.trim_start();
+                    let Some(value) = value
+                        .get(..9)
+                        .filter(|x| x.eq_ignore_ascii_case("entity is"))
+                        .map(|_| value[9..].trim())
+                    else {
+                        continue;
+                    };
+                    attributes.insert(words[1].to_ascii_uppercase(), (line, value.to_string()));
+                }
+                _ => {}
+            }
+        }
+
+        let entity = entity.ok_or_else(|| parse_err("missing entity"))?;
+        let attribute = |name: &str| {
+            attributes
+                .get(name)
+                .map(|(line, value)| (*line, value.as_str()))
+                .ok_or_else(|| parse_err(format!("missing attribute {name}")))
+        };
+
+        let (line, value) = attribute("INSTRUCTION_LENGTH")?;
+        let instruction_length = parse_usize(value).map_err(at_line(line))?;
+        let (line, value) = attribute("INSTRUCTION_OPCODE")?;
+        let instructions = parse_instructions(&join_strings(value)).map_err(at_line(line))?;
+        for insn in &instructions {
+            if insn.opcodes.iter().any(|x| x.len() != instruction_length) {
+                return Err(at_line(line)(parse_err(format!(
+                    "opcode for {} is not {} bits",
+                    insn.name, instruction_length
+                ))));
+            }
+        }
+        let idcode = attributes
+            .get("IDCODE_REGISTER")
+            .map(|(line, value)| parse_idcode(&join_strings(value)).map_err(at_line(*line)))
+            .transpose()?;
+        let (line, value) = attribute("BOUNDARY_LENGTH")?;
+        let boundary_length = parse_usize(value).map_err(at_line(line))?;
+        let (line, value) = attribute("BOUNDARY_REGISTER")?;
+        let boundary_cells =
+            parse_boundary_register(&join_strings(value)).map_err(at_line(line))?;
+        if boundary_cells.iter().any(|x| x.number >= boundary_length) {
+            return Err(at_line(line)(parse_err(
+                "boundary cell number out of range",
+            )));
+        }
+
+        Ok(Self {
+            entity,
+            ports,
+            instruction_length,
+            instructions,
+            idcode,
+            boundary_length,
+            boundary_cells,
+        })
+    }
+
+    /// Find the (first) opcode for the instruction called `name`
+    pub fn opcode(&self, name: &str) -> Option<&BitSlice> {
+        self.instructions
+            .iter()
+            .find(|insn| insn.name.eq_ignore_ascii_case(name))
+            .and_then(|insn| insn.opcodes.first())
+            .map(|x| x.as_bitslice())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    pub(crate) const TEST_BSDL: &str = r#"
+-- A made-up part for testing
+entity TEST_PART is
+    generic (PHYSICAL_PIN_MAP : string := "QFN16");
+
+    port (
+        TCK, TDI, TMS: in bit;
+        TDO: out bit;
+        CLK: in bit;
+        LED: out bit;
+        IO: inout bit_vector(0 to 1);
+        VCC: linkage bit
+    );
+
+    use STD_1149_1_2001.all;
+
+    attribute COMPONENT_CONFORMANCE of TEST_PART : entity is "STD_1149_1_2001";
+
+    attribute INSTRUCTION_LENGTH of TEST_PART : entity is 4;
+    attribute INSTRUCTION_OPCODE of TEST_PART : entity is
+        "EXTEST  (0000)," &
+        "SAMPLE  (0001)," &
+        "PRELOAD (0001)," &
+        "IDCODE  (0110)," &
+        "BYPASS  (1111, 1110)";
+    attribute INSTRUCTION_CAPTURE of TEST_PART : entity is "0001";
+
+    attribute IDCODE_REGISTER of TEST_PART : entity is
+        "XXXX" &            -- version
+        "0001001000110100" & -- part number
+        "00001001001" &     -- manufacturer
+        "1";
+
+    attribute BOUNDARY_LENGTH of TEST_PART : entity is 7;
+    attribute BOUNDARY_REGISTER of TEST_PART : entity is
+    -- num cell  port   function safe ccell disval rslt
+        "6 (BC_1, CLK,   input,   X)," &
+        "5 (BC_1, LED,   output3, 0, 4, 0, Z)," &
+        "4 (BC_1, *,     control, 0)," &
+        "3 (BC_7, IO(0), bidir,   X, 2, 0, Z)," &
+        "2 (BC_1, *,     control, 0)," &
+        "1 (BC_7, IO(1), bidir,   X, 0, 1, Z)," &
+        "0 (BC_1, *,     controlr, 1)";
+end TEST_PART;
+"#;
+
+    #[test]
+    fn test_bsdl_parse() {
+        let bsdl = Bsdl::parse(TEST_BSDL).unwrap();
+
+        assert_eq!(bsdl.entity, "TEST_PART");
+        assert_eq!(bsdl.ports.len(), 8);
+        assert_eq!(bsdl.ports[0].name, "TCK");
+        assert_eq!(bsdl.ports[3].direction, BsdlPortDirection::Out);
+        assert_eq!(
+            bsdl.ports[6],
+            BsdlPort {
+                name: "IO".to_string(),
+                direction: BsdlPortDirection::InOut,
+                range: Some((0, 1)),
+            }
+        );
+
+        assert_eq!(bsdl.instruction_length, 4);
+        assert_eq!(bsdl.opcode("sample"), Some(bits![1, 0, 0, 0].as_ref()));
+        assert_eq!(bsdl.opcode("IDCODE"), Some(bits![0, 1, 1, 0].as_ref()));
+        assert_eq!(bsdl.instructions[4].opcodes.len(), 2);
+        assert_eq!(bsdl.opcode("INTEST"), None);
+
+        let idcode = bsdl.idcode.unwrap();
+        assert_eq!(idcode.mask, 0x0fff_ffff);
+        assert!(idcode.matches(IdCode(0x3123_4093)));
+        assert!(!idcode.matches(IdCode(0x3123_5093)));
+
+        assert_eq!(bsdl.boundary_length, 7);
+        assert_eq!(bsdl.boundary_cells.len(), 7);
+        assert_eq!(
+            bsdl.boundary_cells[5],
+            BoundaryCell {
+                number: 5,
+                cell_type: "BC_1".to_string(),
+                port: Some("LED".to_string()),
+                function: BoundaryCellFunction::Output3,
+                safe: Some(false),
+                control_cell: Some(4),
+                disable_value: Some(false),
+                disable_result: Some("Z".to_string()),
+            }
+        );
+        assert_eq!(bsdl.boundary_cells[3].port.as_deref(), Some("IO(0)"));
+        assert_eq!(
+            bsdl.boundary_cells[0].function,
+            BoundaryCellFunction::ControlR
+        );
+    }
+
+    #[test]
+    fn test_bsdl_errors() {
+        assert!(matches!(
+            Bsdl::parse("entity FOO is end FOO;"),
+            Err(JTAGError::Parse(ParseError { format: "BSDL", .. }))
+        ));
+        assert!(matches!(
+            Bsdl::parse(&TEST_BSDL.replace("(0110)", "(011)")),
+            Err(JTAGError::Parse(ParseError {
+                location: ParseLocation::Line(20),
+                ..
+            }))
+        ));
+        assert!(matches!(
+            Bsdl::parse(&TEST_BSDL.replace("inout bit_vector", "sideways bit_vector")),
+            Err(JTAGError::Parse(ParseError {
+                location: ParseLocation::Line(6),
+                ..
+            }))
+        ));
+    }
+}
@@ -6,17 +6,17 @@ use bitvec::prelude::*;
 /// at a time (e.g. FTDI MPSSE, Xilinx Platform Cable USB)
 pub trait ChunkShifterJTAGAdapter {
     /// Wait for a given number of nanoseconds
-    fn delay_ns(&mut self, ns: u64) -> u64;
+    fn delay_ns(&mut self, ns: u64) -> Result<u64, JTAGError>;
     /// Set the JTAG clock speed in Hz
-    fn set_clk_speed(&mut self, clk_hz: u64) -> u64;
+    fn set_clk_speed(&mut self, clk_hz: u64) -> Result<u64, JTAGError>;
 
     /// Shift the given bits out on TMS
-    fn shift_tms_chunk(&mut self, tms_chunk: &BitSlice);
+    fn shift_tms_chunk(&mut self, tms_chunk: &BitSlice) -> Result<(), JTAGError>;
     /// Shift the given bits out on TDI. If `tms_exit` is `true`,
     /// the final bit will be shifted with TMS=1 (which would cause a
     /// transition from Shift-IR/DR to Exit1-IR/DR). Otherwise, the final bit
     /// will be shifted with TMS=0.
-    fn shift_tdi_chunk(&mut self, tdi_chunk: &BitSlice, tms_exit: bool);
+    fn shift_tdi_chunk(&mut self, tdi_chunk: &BitSlice, tms_exit: bool) -> Result<(), JTAGError>;
     /// Shift the given bits out on TDI. Capture data on TDO.
     /// If `tms_exit` is `true`,
     /// the final bit will be shifted with TMS=1 (which would cause a
@@ -25,7 +25,11 @@ pub trait ChunkShifterJTAGAdapter {
     ///
     // FIXME: Explain how the timing here is "behind" and different from
     // the bitbang mode
-    fn shift_tditdo_chunk(&mut self, tdi_chunk: &BitSlice, tms_exit: bool) -> BitVec;
+    fn shift_tditdo_chunk(
+        &mut self,
+        tdi_chunk: &BitSlice,
+        tms_exit: bool,
+    ) -> Result<BitVec, JTAGError>;
 }
 
 /// State required to be stored by a [ChunkShifterJTAGAdapter] in order for
@@ -43,34 +47,39 @@ impl ChunkShifterJTAGAdapterState {
         }
     }
 }
+impl Default for ChunkShifterJTAGAdapterState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 /// Automatically turn [ChunkShifterJTAGAdapter] into a [StateTrackingJTAGAdapter]
 impl<T: ChunkShifterJTAGAdapter + AsMut<ChunkShifterJTAGAdapterState>> StateTrackingJTAGAdapter
     for T
 {
-    fn execute_stjtag_action(&mut self, action: &JTAGAction) -> JTAGOutput {
+    fn execute_stjtag_action(&mut self, action: &JTAGAction) -> Result<JTAGOutput, JTAGError> {
         match action {
             JTAGAction::ShiftIR { .. }
             | JTAGAction::ShiftDR { .. }
             | JTAGAction::SetIR { .. }
             | JTAGAction::ReadReg { .. }
-            | JTAGAction::WriteReg { .. } => {
-                unreachable!()
-            }
+            | JTAGAction::WriteReg { .. } => Err(JTAGError::InvalidAction(format!(
+                "{action:?} must be lowered before reaching a chunk shifter"
+            ))),
 
             JTAGAction::DelayNS(ns) => {
-                let actual_ns = self.delay_ns(*ns);
-                JTAGOutput::ActualDelay(actual_ns)
+                let actual_ns = self.delay_ns(*ns)?;
+                Ok(JTAGOutput::ActualDelay(actual_ns))
             }
             JTAGAction::SetClkSpeed(clk_hz) => {
-                let actual_clk = self.set_clk_speed(*clk_hz);
-                JTAGOutput::ActualClkSpeed(actual_clk)
+                let actual_clk = self.set_clk_speed(*clk_hz)?;
+                Ok(JTAGOutput::ActualClkSpeed(actual_clk))
             }
             JTAGAction::ResetToTLR => {
-                self.shift_tms_chunk(bits![1; 5]);
+                self.shift_tms_chunk(bits![1; 5])?;
                 let state_data: &mut ChunkShifterJTAGAdapterState = self.as_mut();
                 state_data.current_state = JTAGState::TestLogicReset;
-                JTAGOutput::NoData
+                Ok(JTAGOutput::NoData)
             }
             JTAGAction::GoViaStates(jtag_states) => {
                 let state_data: &mut ChunkShifterJTAGAdapterState = self.as_mut();
@@ -83,12 +92,12 @@ impl<T: ChunkShifterJTAGAdapter + AsMut<ChunkShifterJTAGAdapterState>> StateTrac
                     prev_state = *jtag_state;
                 }
 
-                self.shift_tms_chunk(&path);
+                self.shift_tms_chunk(&path)?;
 
                 let state_data: &mut ChunkShifterJTAGAdapterState = self.as_mut();
                 state_data.current_state = prev_state;
 
-                JTAGOutput::NoData
+                Ok(JTAGOutput::NoData)
             }
             JTAGAction::ShiftBits {
                 bits_tdi,
@@ -99,11 +108,11 @@ impl<T: ChunkShifterJTAGAdapter + AsMut<ChunkShifterJTAGAdapterState>> StateTrac
                 state_data.current_state = state_data.current_state.transition(*tms_exit);
 
                 if *capture {
-                    let ret = self.shift_tditdo_chunk(bits_tdi, *tms_exit);
-                    JTAGOutput::CapturedBits(ret)
+                    let ret = self.shift_tditdo_chunk(bits_tdi, *tms_exit)?;
+                    Ok(JTAGOutput::CapturedBits(ret))
                 } else {
-                    self.shift_tdi_chunk(bits_tdi, *tms_exit);
-                    JTAGOutput::NoData
+                    self.shift_tdi_chunk(bits_tdi, *tms_exit)?;
+                    Ok(JTAGOutput::NoData)
                 }
             }
         }
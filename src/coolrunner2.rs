@@ -0,0 +1,834 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+const IDCODE: u8 = 0x01;
+const ISC_ENABLE_OTF: u8 = 0xe4;
+const ISC_DISABLE: u8 = 0xc0;
+const ISC_ERASE: u8 = 0xed;
+const ISC_PROGRAM: u8 = 0xea;
+const ISC_READ: u8 = 0xee;
+const BYPASS: u8 = 0xff;
+const IR_LEN: usize = 8;
+
+const ENABLE_DELAY_NS: u64 = 800_000;
+const DISABLE_DELAY_NS: u64 = 100_000;
+const ERASE_DELAY_NS: u64 = 100_000_000;
+const PROGRAM_DELAY_NS: u64 = 10_000_000;
+const READ_DELAY_NS: u64 = 20_000;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// A CoolRunner-II (XC2C) density
+pub enum Xc2cDevice {
+    XC2C32,
+    XC2C32A,
+    XC2C64,
+    XC2C64A,
+    XC2C128,
+    XC2C256,
+    XC2C384,
+    XC2C512,
+}
+
+impl CpldDevice for Xc2cDevice {
+    const ALL: &'static [Self] = &[
+        Self::XC2C32,
+        Self::XC2C32A,
+        Self::XC2C64,
+        Self::XC2C64A,
+        Self::XC2C128,
+        Self::XC2C256,
+        Self::XC2C384,
+        Self::XC2C512,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::XC2C32 => "XC2C32",
+            Self::XC2C32A => "XC2C32A",
+            Self::XC2C64 => "XC2C64",
+            Self::XC2C64A => "XC2C64A",
+            Self::XC2C128 => "XC2C128",
+            Self::XC2C256 => "XC2C256",
+            Self::XC2C384 => "XC2C384",
+            Self::XC2C512 => "XC2C512",
+        }
+    }
+}
+
+impl Xc2cDevice {
+    /// Number of fuses in a JED file for this device
+    pub fn jed_fuses(self) -> usize {
+        match self {
+            Self::XC2C32 => 12274,
+            Self::XC2C32A => 12278,
+            Self::XC2C64 => 25808,
+            Self::XC2C64A => 25812,
+            Self::XC2C128 => 55341,
+            Self::XC2C256 => 123249,
+            Self::XC2C384 => 209357,
+            Self::XC2C512 => 296403,
+        }
+    }
+
+    /// Number of rows in the physical fuse array, including the 2 rows
+    /// after the JED fuses which hold the DONE and security bits
+    pub fn rows(self) -> usize {
+        match self {
+            Self::XC2C32 | Self::XC2C32A => 50,
+            Self::XC2C64 | Self::XC2C64A => 98,
+            Self::XC2C128 => 82,
+            Self::XC2C256 => 98,
+            Self::XC2C384 => 122,
+            Self::XC2C512 => 162,
+        }
+    }
+
+    /// Number of bits in each row of the physical fuse array
+    pub fn row_len(self) -> usize {
+        match self {
+            Self::XC2C32 | Self::XC2C32A => 260,
+            Self::XC2C64 | Self::XC2C64A => 274,
+            Self::XC2C128 => 752,
+            Self::XC2C256 => 1364,
+            Self::XC2C384 => 1868,
+            Self::XC2C512 => 1980,
+        }
+    }
+
+    /// Number of bits in a row address
+    pub fn address_len(self) -> usize {
+        match self {
+            Self::XC2C32 | Self::XC2C32A => 6,
+            Self::XC2C512 => 8,
+            _ => 7,
+        }
+    }
+
+    /// Row addresses are Gray coded and shifted MSB first
+    fn address(self, row: usize) -> BitVec {
+        let gray = row ^ (row >> 1);
+        (0..self.address_len())
+            .rev()
+            .map(|i| gray & (1 << i) != 0)
+            .collect()
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// What a bit in the physical fuse array holds
+pub enum Xc2cMapCell {
+    /// The fuse with the given index in the JED file
+    Fuse(usize),
+    /// One of the DONE bits, which are programmed last
+    Done,
+    /// One of the security (read protection) bits
+    Security,
+    /// Not used and left unprogrammed
+    Unused,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// Layout of the physical fuse array of a CoolRunner-II device, i.e. which
+/// JED fuse is programmed into each bit of each row
+///
+/// The JED file lists fuses by function block rather than in the order that
+/// they are programmed in, so this is needed to program a device. Xilinx
+/// ships this information as map files (e.g. `xc2c32a.map` in the
+/// `data/xbr` directory of ISE) which can be loaded with
+/// [parse][Self::parse], or a map can be built in without them by
+/// [generate][Self::generate].
+pub struct Xc2cFuseMap {
+    device: Xc2cDevice,
+    // rows * row_len cells, row by row
+    cells: Vec<Xc2cMapCell>,
+}
+
+impl Xc2cFuseMap {
+    /// Create a map from cells listed row by row, with bit 0 of each row
+    /// (i.e. the first bit shifted) first
+    pub fn new(device: Xc2cDevice, cells: Vec<Xc2cMapCell>) -> Result<Self, JTAGError> {
+        if cells.len() != device.rows() * device.row_len() {
+            return Err(JTAGError::InvalidAction(format!(
+                "{} needs a map with {} cells, not {}",
+                device.name(),
+                device.rows() * device.row_len(),
+                cells.len()
+            )));
+        }
+        if let Some(Xc2cMapCell::Fuse(fuse)) = cells
+            .iter()
+            .find(|x| matches!(x, Xc2cMapCell::Fuse(fuse) if *fuse >= device.jed_fuses()))
+        {
+            return Err(JTAGError::InvalidAction(format!(
+                "fuse {fuse} does not exist in {}",
+                device.name()
+            )));
+        }
+        Ok(Self { device, cells })
+    }
+
+    /// Parse a Xilinx map file, where each line lists one column of the
+    /// fuse array as tab separated entries for each row. Entries are either
+    /// JED fuse numbers, names starting with "done" or "sec" for the DONE
+    /// and security bits, or empty.
+    pub fn parse(device: Xc2cDevice, src: &str) -> Result<Self, JTAGError> {
+        let lines = src
+            .lines()
+            .enumerate()
+            .map(|(i, x)| (i + 1, x.trim_end_matches('\r')))
+            .filter(|(_, x)| !x.trim().is_empty())
+            .collect::<Vec<_>>();
+        if lines.len() != device.row_len() {
+            return Err(ParseError::new(
+                "map",
+                format!(
+                    "{} map has {} columns instead of {}",
+                    device.name(),
+                    lines.len(),
+                    device.row_len()
+                ),
+            )
+            .into());
+        }
+
+        let mut cells = vec![Xc2cMapCell::Unused; device.rows() * device.row_len()];
+        for (col, &(line_no, line)) in lines.iter().enumerate() {
+            let err = |msg: String| ParseError::new("map", msg).at_line(line_no);
+            let entries = line.split('\t').collect::<Vec<_>>();
+            if entries.len() > device.rows() {
+                return Err(err(format!(
+                    "{} map column {col} has {} rows instead of {}",
+                    device.name(),
+                    entries.len(),
+                    device.rows()
+                ))
+                .into());
+            }
+            for (row, entry) in entries.iter().enumerate() {
+                let entry = entry.trim().to_ascii_lowercase();
+                cells[row * device.row_len() + col] = if let Ok(fuse) = entry.parse() {
+                    if fuse >= device.jed_fuses() {
+                        return Err(err(format!(
+                            "fuse {fuse} does not exist in {}",
+                            device.name()
+                        ))
+                        .into());
+                    }
+                    Xc2cMapCell::Fuse(fuse)
+                } else if entry.starts_with("done") {
+                    Xc2cMapCell::Done
+                } else if entry.starts_with("sec") {
+                    Xc2cMapCell::Security
+                } else {
+                    Xc2cMapCell::Unused
+                };
+            }
+        }
+        Self::new(device, cells)
+    }
+
+    pub fn device(&self) -> Xc2cDevice {
+        self.device
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Xc2cMapCell {
+        self.cells[row * self.device.row_len() + col]
+    }
+
+    fn rows_with(&self, mut f: impl FnMut(Xc2cMapCell) -> bool) -> Vec<BitVec> {
+        self.cells
+            .chunks(self.device.row_len())
+            .map(|row| row.iter().map(|&x| f(x)).collect())
+            .collect()
+    }
+
+    /// Arrange the fuses of a JED file into rows, leaving the DONE and
+    /// security bits unprogrammed
+    pub fn jed_to_rows(&self, jed: &Jed) -> Result<Vec<BitVec>, JTAGError> {
+        if jed.fuses.len() != self.device.jed_fuses() {
+            return Err(JTAGError::InvalidAction(format!(
+                "JED has {} fuses but {} has {}",
+                jed.fuses.len(),
+                self.device.name(),
+                self.device.jed_fuses()
+            )));
+        }
+        Ok(self.rows_with(|x| match x {
+            Xc2cMapCell::Fuse(fuse) => jed.fuses[fuse],
+            _ => true,
+        }))
+    }
+
+    /// Convert rows read back from a device into a JED file
+    pub fn rows_to_jed(&self, rows: &[BitVec]) -> Jed {
+        let mut fuses = BitVec::repeat(true, self.device.jed_fuses());
+        for (row, bits) in self.cells.chunks(self.device.row_len()).zip(rows) {
+            for (&cell, bit) in row.iter().zip(bits.iter().by_vals()) {
+                if let Xc2cMapCell::Fuse(fuse) = cell {
+                    fuses.set(fuse, bit);
+                }
+            }
+        }
+        let mut jed = Jed::new(fuses);
+        jed.notes.push(format!("DEVICE {}", self.device.name()));
+        jed
+    }
+}
+
+/// Programs CoolRunner-II CPLDs using their in-system configuration (ISC)
+/// instructions
+///
+/// ISC_ENABLE_OTF is used, so the device keeps running its old design until
+/// ISC_DISABLE is sent at the end.
+pub struct CoolRunner2<'a> {
+    map: &'a Xc2cFuseMap,
+}
+
+impl<'a> CoolRunner2<'a> {
+    pub fn new(map: &'a Xc2cFuseMap) -> Self {
+        Self { map }
+    }
+
+    fn device(&self) -> Xc2cDevice {
+        self.map.device
+    }
+
+    /// Check that the device matches the fuse map
+    pub fn check_idcode<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<(), JTAGError> {
+        cpld::check_idcode(adapter, &insn(IDCODE, IR_LEN), self.device())
+    }
+
+    /// Enter ISC mode. This is a buffered action that returns immediately
+    pub fn enable<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) {
+        adapter.shift_ir_out(&insn(ISC_ENABLE_OTF, IR_LEN), false);
+        adapter.queue_action(JTAGAction::DelayNS(ENABLE_DELAY_NS));
+    }
+
+    /// Leave ISC mode, which makes the device start running the programmed
+    /// design. This is a buffered action that returns immediately
+    pub fn disable<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) {
+        adapter.shift_ir_out(&insn(ISC_DISABLE, IR_LEN), false);
+        adapter.queue_action(JTAGAction::DelayNS(DISABLE_DELAY_NS));
+        adapter.shift_ir_out(&insn(BYPASS, IR_LEN), false);
+    }
+
+    /// Check whether the DONE bit is set, i.e. the device has been
+    /// completely programmed
+    pub fn is_done<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<bool, JTAGError> {
+        let status = adapter.shift_ir_inout(&insn(BYPASS, IR_LEN), false)?;
+        Ok(status[2])
+    }
+
+    /// Erase the whole device
+    pub fn erase<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<(), JTAGError> {
+        self.enable(adapter);
+        self.erase_enabled(adapter);
+        self.disable(adapter);
+        adapter.flush()?;
+        Ok(())
+    }
+
+    fn erase_enabled<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) {
+        adapter.shift_ir_out(&insn(ISC_ERASE, IR_LEN), false);
+        adapter.queue_action(JTAGAction::DelayNS(ERASE_DELAY_NS));
+    }
+
+    // Program rows while in ISC mode. Programming can only clear bits, so
+    // bits which are set are left alone.
+    fn program_rows<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T, rows: &[BitVec]) {
+        adapter.shift_ir_out(&insn(ISC_PROGRAM, IR_LEN), false);
+        for (i, row) in rows.iter().enumerate() {
+            if row.all() {
+                continue;
+            }
+            let mut dr = row.clone();
+            dr.extend_from_bitslice(&self.device().address(i));
+            adapter.shift_dr_out(&dr, false);
+            adapter.queue_action(JTAGAction::DelayNS(PROGRAM_DELAY_NS));
+        }
+    }
+
+    // Data shifted in to read a row while in ISC mode, which selects the
+    // row to be shifted out by the next scan
+    fn read_scan(&self, row: usize) -> BitVec {
+        let device = self.device();
+        let mut dr = BitVec::repeat(false, device.row_len());
+        dr.extend_from_bitslice(&device.address(row % device.rows()));
+        dr
+    }
+
+    // Read every row while in ISC mode. Each scan shifts out the row
+    // selected by the previous scan while shifting in the next address.
+    fn read_rows<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+    ) -> Result<Vec<BitVec>, JTAGError> {
+        adapter.shift_ir_out(&insn(ISC_READ, IR_LEN), false);
+        adapter.shift_dr_out(&self.read_scan(0), false);
+        let mut rows = Vec::with_capacity(self.device().rows());
+        for i in 1..=self.device().rows() {
+            adapter.queue_action(JTAGAction::DelayNS(READ_DELAY_NS));
+            let out = adapter.shift_dr_inout(&self.read_scan(i), false)?;
+            rows.push(out[..self.device().row_len()].to_bitvec());
+        }
+        Ok(rows)
+    }
+
+    // Like read_rows, but record the rows as expected data instead of
+    // reading them. Only bits holding fuses are checked.
+    fn expect_rows(&self, recorder: &mut SvfRecorder, rows: &[BitVec]) -> Result<(), JTAGError> {
+        let device = self.device();
+        recorder.shift_ir_out(&insn(ISC_READ, IR_LEN), false);
+        recorder.shift_dr_out(&self.read_scan(0), false);
+        for (i, row) in rows.iter().enumerate() {
+            recorder.queue_action(JTAGAction::DelayNS(READ_DELAY_NS));
+            recorder.shift_dr_out(&self.read_scan(i + 1), false);
+            let mut tdo = row.clone();
+            tdo.resize(device.row_len() + device.address_len(), false);
+            let mut mask = (0..device.row_len())
+                .map(|col| matches!(self.map.cell(i, col), Xc2cMapCell::Fuse(..)))
+                .collect::<BitVec>();
+            mask.resize(tdo.len(), false);
+            recorder.expect_tdo(&tdo, &mask)?;
+        }
+        Ok(())
+    }
+
+    /// Read back the whole device as a JED file. This does not work if the
+    /// device has been read protected.
+    pub fn read<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<Jed, JTAGError> {
+        self.enable(adapter);
+        let rows = self.read_rows(adapter)?;
+        self.disable(adapter);
+        adapter.flush()?;
+        Ok(self.map.rows_to_jed(&rows))
+    }
+
+    /// Check whether every row of the device is erased
+    pub fn blank_check<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<bool, JTAGError> {
+        self.enable(adapter);
+        let rows = self.read_rows(adapter)?;
+        self.disable(adapter);
+        adapter.flush()?;
+        Ok(rows.iter().all(|x| x.all()))
+    }
+
+    // Rows which program the DONE bits, and the security bits if `secure`
+    // is set
+    fn flag_rows(&self, secure: bool) -> Result<Vec<BitVec>, JTAGError> {
+        if secure && !self.map.cells.contains(&Xc2cMapCell::Security) {
+            return Err(JTAGError::InvalidAction(
+                "the fuse map has no security bits".to_string(),
+            ));
+        }
+        Ok(self.map.rows_with(|x| match x {
+            Xc2cMapCell::Done => false,
+            Xc2cMapCell::Security => !secure,
+            _ => true,
+        }))
+    }
+
+    fn verify_rows(&self, expected: &[BitVec], actual: &[BitVec]) -> Result<(), JTAGError> {
+        for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+            for col in 0..self.device().row_len() {
+                if let Xc2cMapCell::Fuse(fuse) = self.map.cell(i, col) {
+                    if expected[col] != actual[col] {
+                        return Err(JTAGError::VerifyFailed(format!(
+                            "fuse {fuse} (row {i} bit {col}) is {}",
+                            actual[col] as u8
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the device contains the fuses from `jed`
+    pub fn verify<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        jed: &Jed,
+    ) -> Result<(), JTAGError> {
+        let expected = self.map.jed_to_rows(jed)?;
+        self.enable(adapter);
+        let actual = self.read_rows(adapter)?;
+        self.disable(adapter);
+        adapter.flush()?;
+        self.verify_rows(&expected, &actual)
+    }
+
+    /// Erase the device, program and verify the fuses from `jed`, and then
+    /// set the DONE bits (and the security bits if `secure` is set, which
+    /// prevents reading back the design). Setting the security bits needs a
+    /// map which locates them, which generated maps do not.
+    ///
+    /// The DONE bits are only set after the fuses have been verified, so an
+    /// interrupted or failed programming attempt leaves the device
+    /// unconfigured instead of running a partial design.
+    pub fn program<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        jed: &Jed,
+        secure: bool,
+    ) -> Result<(), JTAGError> {
+        let rows = self.map.jed_to_rows(jed)?;
+        let flags = self.flag_rows(secure)?;
+        self.check_idcode(adapter)?;
+
+        self.enable(adapter);
+        self.erase_enabled(adapter);
+        self.program_rows(adapter, &rows);
+        let actual = self.read_rows(adapter)?;
+        if let Err(e) = self.verify_rows(&rows, &actual) {
+            self.disable(adapter);
+            adapter.flush()?;
+            return Err(e);
+        }
+
+        self.program_rows(adapter, &flags);
+        self.disable(adapter);
+        adapter.flush()?;
+
+        if !self.is_done(adapter)? {
+            return Err(JTAGError::VerifyFailed(
+                "DONE is not set after programming".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Generate an SVF file which does the same as [program][Self::program]
+    /// without needing a device attached. Data that would be read back is
+    /// checked using the TDO data of the scans instead.
+    pub fn program_svf(&self, jed: &Jed, secure: bool) -> Result<Svf, JTAGError> {
+        let rows = self.map.jed_to_rows(jed)?;
+        let flags = self.flag_rows(secure)?;
+        let mut recorder = SvfRecorder::new(None);
+        recorder.reset_to_tlr();
+        recorder.go_rti();
+
+        cpld::expect_idcode(&mut recorder, &insn(IDCODE, IR_LEN), self.device())?;
+
+        self.enable(&mut recorder);
+        self.erase_enabled(&mut recorder);
+        self.program_rows(&mut recorder, &rows);
+        self.expect_rows(&mut recorder, &rows)?;
+        self.program_rows(&mut recorder, &flags);
+        self.disable(&mut recorder);
+
+        recorder.shift_ir_out(&insn(BYPASS, IR_LEN), false);
+        recorder.expect_tdo(bits![0, 0, 1, 0, 0, 0, 0, 0], bits![0, 0, 1, 0, 0, 0, 0, 0])?;
+        Ok(recorder.into_svf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    const ROWS: usize = 50;
+    const ROW_LEN: usize = 260;
+    const ADDRESS_LEN: usize = 6;
+
+    // Implements enough of the ISC instructions of an XC2C32A to be
+    // programmed, using the map from test_map
+    struct SimXc2c {
+        rows: Vec<BitVec>,
+        address: usize,
+        enabled: bool,
+        done: bool,
+    }
+
+    impl SimXc2c {
+        fn new() -> Self {
+            Self {
+                rows: vec![bitvec![1; ROW_LEN]; ROWS],
+                address: 0,
+                enabled: false,
+                done: false,
+            }
+        }
+    }
+
+    fn decode_address(bits: &BitSlice) -> usize {
+        let gray = bits
+            .iter()
+            .by_vals()
+            .fold(0, |acc, x| acc << 1 | x as usize);
+        (0..ADDRESS_LEN).fold(0, |acc, i| acc ^ (gray >> i))
+    }
+
+    impl drivers::SimulatedDevice for SimXc2c {
+        fn ir_len(&self) -> usize {
+            8
+        }
+        fn reset_ir(&self) -> BitVec {
+            bitvec![1, 0, 0, 0, 0, 0, 0, 0]
+        }
+        fn capture_ir(&self) -> BitVec {
+            let mut ret = bitvec![1, 0, 0, 0, 0, 0, 0, 0];
+            ret.set(2, self.done);
+            ret
+        }
+        fn update_ir(&mut self, ir: &BitSlice) {
+            match ir.load_le::<u8>() {
+                0xe4 => self.enabled = true,
+                0xed if self.enabled => self.rows.iter_mut().for_each(|x| x.fill(true)),
+                0xc0 if self.enabled => {
+                    self.enabled = false;
+                    self.done = !self.rows[ROWS - 1][ROW_LEN - 1];
+                }
+                _ => {}
+            }
+        }
+        fn capture_dr(&mut self, ir: &BitSlice) -> BitVec {
+            match ir.load_le::<u8>() {
+                0x01 => 0x06e1_c093u32
+                    .view_bits::<Lsb0>()
+                    .iter()
+                    .by_vals()
+                    .collect(),
+                0xea | 0xee => {
+                    let mut ret = if ir.load_le::<u8>() == 0xee && self.enabled {
+                        self.rows[self.address].clone()
+                    } else {
+                        bitvec![0; ROW_LEN]
+                    };
+                    ret.resize(ROW_LEN + ADDRESS_LEN, false);
+                    ret
+                }
+                _ => bitvec![0],
+            }
+        }
+        fn update_dr(&mut self, ir: &BitSlice, dr: &BitSlice) {
+            match ir.load_le::<u8>() {
+                0xea if self.enabled => {
+                    let row = &mut self.rows[decode_address(&dr[ROW_LEN..])];
+                    *row &= &dr[..ROW_LEN];
+                }
+                0xee if self.enabled => self.address = decode_address(&dr[ROW_LEN..]),
+                _ => {}
+            }
+        }
+    }
+
+    // The last bit of the array is DONE and the one before it is security
+    fn test_cells() -> Vec<Xc2cMapCell> {
+        (0..ROWS * ROW_LEN)
+            .map(|i| match i {
+                i if i == ROWS * ROW_LEN - 1 => Xc2cMapCell::Done,
+                i if i == ROWS * ROW_LEN - 2 => Xc2cMapCell::Security,
+                i if i < 12278 => Xc2cMapCell::Fuse(12277 - i),
+                _ => Xc2cMapCell::Unused,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_coolrunner2_map() {
+        assert_eq!(
+            Xc2cDevice::from_idcode(IdCode(0x16e1_b093)),
+            Some(Xc2cDevice::XC2C32A)
+        );
+        assert_eq!(
+            Xc2cDevice::from_idcode(IdCode(0x06d4_a093)),
+            Some(Xc2cDevice::XC2C256)
+        );
+        assert_eq!(Xc2cDevice::from_idcode(IdCode(0x0960_2093)), None);
+
+        let cells = test_cells();
+        let mut src = String::new();
+        for col in 0..ROW_LEN {
+            let entries = (0..ROWS)
+                .map(|row| match cells[row * ROW_LEN + col] {
+                    Xc2cMapCell::Fuse(x) => x.to_string(),
+                    Xc2cMapCell::Done => "done0".to_string(),
+                    Xc2cMapCell::Security => "sec_0".to_string(),
+                    Xc2cMapCell::Unused => String::new(),
+                })
+                .collect::<Vec<_>>();
+            src.push_str(&entries.join("\t"));
+            src.push('\n');
+        }
+        let map = Xc2cFuseMap::parse(Xc2cDevice::XC2C32A, &src).unwrap();
+        assert_eq!(map, Xc2cFuseMap::new(Xc2cDevice::XC2C32A, cells).unwrap());
+        assert_eq!(map.cell(0, 1), Xc2cMapCell::Fuse(12276));
+        assert!(Xc2cFuseMap::parse(Xc2cDevice::XC2C64A, &src).is_err());
+    }
+
+    #[test]
+    fn test_coolrunner2_program() {
+        let map = Xc2cFuseMap::new(Xc2cDevice::XC2C32A, test_cells()).unwrap();
+        let cpld = CoolRunner2::new(&map);
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(SimXc2c::new())]);
+
+        let mut jed = Jed::new(bitvec![1; 12278]);
+        for i in (0..12278).step_by(7) {
+            jed.fuses.set(i, false);
+        }
+        assert!(cpld.blank_check(&mut adapter).unwrap());
+        assert!(!cpld.is_done(&mut adapter).unwrap());
+
+        cpld.program(&mut adapter, &jed, false).unwrap();
+        assert!(cpld.is_done(&mut adapter).unwrap());
+        cpld.verify(&mut adapter, &jed).unwrap();
+        assert_eq!(cpld.read(&mut adapter).unwrap().fuses, jed.fuses);
+        assert!(!cpld.blank_check(&mut adapter).unwrap());
+
+        jed.fuses.set(12277, false);
+        assert!(matches!(
+            cpld.verify(&mut adapter, &jed),
+            Err(JTAGError::VerifyFailed(..))
+        ));
+
+        cpld.erase(&mut adapter).unwrap();
+        assert!(cpld.blank_check(&mut adapter).unwrap());
+        assert!(cpld
+            .program(&mut adapter, &Jed::new(bitvec![0; 100]), false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_coolrunner2_program_svf() {
+        assert_eq!(
+            Xc2cDevice::from_name("xc2c32a-6-VQ44"),
+            Some(Xc2cDevice::XC2C32A)
+        );
+        assert_eq!(Xc2cDevice::from_name("XC2C32B"), None);
+
+        let map = Xc2cFuseMap::new(Xc2cDevice::XC2C32A, test_cells()).unwrap();
+        let cpld = CoolRunner2::new(&map);
+        let mut jed = Jed::new(bitvec![1; 12278]);
+        for i in (0..12278).step_by(3) {
+            jed.fuses.set(i, false);
+        }
+        let svf = cpld.program_svf(&jed, false).unwrap();
+        let svf = Svf::parse(&svf.to_string()).unwrap();
+
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(SimXc2c::new())]);
+        svf.play(&mut adapter).unwrap();
+        assert!(cpld.is_done(&mut adapter).unwrap());
+        assert_eq!(cpld.read(&mut adapter).unwrap().fuses, jed.fuses);
+    }
+
+    #[test]
+    fn test_coolrunner2_generated_map() {
+        // (fuse, row, col) of fuses spread across each density, as located
+        // by xc2bit
+        let known = [
+            (Xc2cDevice::XC2C32A, 0, 0, 136),
+            (Xc2cDevice::XC2C32A, 5000, 24, 34),
+            (Xc2cDevice::XC2C32A, 12000, 19, 253),
+            (Xc2cDevice::XC2C32A, 12277, 25, 134),
+            (Xc2cDevice::XC2C64A, 0, 0, 151),
+            (Xc2cDevice::XC2C64A, 5000, 28, 118),
+            (Xc2cDevice::XC2C64A, 12000, 20, 210),
+            (Xc2cDevice::XC2C64A, 25811, 23, 142),
+            (Xc2cDevice::XC2C128, 0, 0, 214),
+            (Xc2cDevice::XC2C128, 5000, 20, 97),
+            (Xc2cDevice::XC2C128, 12000, 26, 280),
+            (Xc2cDevice::XC2C128, 55340, 67, 10),
+            (Xc2cDevice::XC2C256, 0, 0, 217),
+            (Xc2cDevice::XC2C256, 5000, 28, 88),
+            (Xc2cDevice::XC2C256, 12000, 40, 272),
+            (Xc2cDevice::XC2C256, 123248, 23, 177),
+            (Xc2cDevice::XC2C384, 0, 0, 306),
+            (Xc2cDevice::XC2C384, 5000, 20, 129),
+            (Xc2cDevice::XC2C384, 12000, 39, 412),
+            (Xc2cDevice::XC2C384, 209356, 17, 3),
+            (Xc2cDevice::XC2C512, 0, 0, 334),
+            (Xc2cDevice::XC2C512, 5000, 20, 103),
+            (Xc2cDevice::XC2C512, 12000, 31, 303),
+            (Xc2cDevice::XC2C512, 296402, 147, 1),
+        ];
+        for (device, fuse, row, col) in known {
+            let map = Xc2cFuseMap::generate(device);
+            assert_eq!(
+                map.cell(row, col),
+                Xc2cMapCell::Fuse(fuse),
+                "{}",
+                device.name()
+            );
+        }
+
+        for &device in Xc2cDevice::ALL {
+            let map = Xc2cFuseMap::generate(device);
+            let mut seen = bitvec![0; device.jed_fuses()];
+            let mut done = 0;
+            for row in 0..device.rows() {
+                for col in 0..device.row_len() {
+                    match map.cell(row, col) {
+                        Xc2cMapCell::Fuse(x) => {
+                            assert!(!seen[x], "{} fuse {x}", device.name());
+                            seen.set(x, true);
+                        }
+                        Xc2cMapCell::Done => done += 1,
+                        _ => {}
+                    }
+                }
+            }
+            assert!(seen.all(), "{}", device.name());
+            // xc2bit only locates one of the DONE bits (done1)
+            assert_eq!(done, 1, "{}", device.name());
+        }
+
+        // (row, col) of done1 as set by XC2BitstreamBits::to_crbit in xc2bit
+        let known_done = [
+            (Xc2cDevice::XC2C32A, 48, 9),
+            (Xc2cDevice::XC2C64A, 96, 8),
+            (Xc2cDevice::XC2C128, 80, 9),
+            (Xc2cDevice::XC2C256, 96, 9),
+            (Xc2cDevice::XC2C384, 120, 9),
+            (Xc2cDevice::XC2C512, 160, 9),
+        ];
+        for (device, row, col) in known_done {
+            let map = Xc2cFuseMap::generate(device);
+            assert_eq!(map.cell(row, col), Xc2cMapCell::Done, "{}", device.name());
+        }
+
+        let map = Xc2cFuseMap::generate(Xc2cDevice::XC2C32A);
+        let jed = Jed::new(bitvec![1; 12278]);
+        assert!(CoolRunner2::new(&map).program_svf(&jed, false).is_ok());
+        assert!(matches!(
+            CoolRunner2::new(&map).program_svf(&jed, true),
+            Err(JTAGError::InvalidAction(..))
+        ));
+    }
+
+    // Compare the generated maps with the map files shipped with ISE (in
+    // data/xbr), which can't be distributed with this crate. Run with
+    // XC2C_MAP_DIR set to a directory containing xc2c32a.map etc.
+    #[test]
+    #[ignore]
+    fn test_coolrunner2_ise_maps() {
+        let dir = std::env::var("XC2C_MAP_DIR").expect("XC2C_MAP_DIR is not set");
+        let mut checked = 0;
+        for &device in Xc2cDevice::ALL {
+            let path = format!("{dir}/{}.map", device.name().to_ascii_lowercase());
+            let Ok(src) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let ise = Xc2cFuseMap::parse(device, &src).unwrap();
+            let generated = Xc2cFuseMap::generate(device);
+            for row in 0..device.rows() {
+                for col in 0..device.row_len() {
+                    let cell = generated.cell(row, col);
+                    if cell != Xc2cMapCell::Unused {
+                        assert_eq!(
+                            ise.cell(row, col),
+                            cell,
+                            "{} row {row} col {col}",
+                            device.name()
+                        );
+                    }
+                }
+            }
+            checked += 1;
+        }
+        assert!(checked > 0, "no map files found in {dir}");
+    }
+}
@@ -0,0 +1,63 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+/// A CPLD family whose members are listed in [KNOWN_PARTS] by name
+pub trait CpldDevice: Copy + Eq + 'static {
+    /// Every density of the family
+    const ALL: &'static [Self];
+
+    fn name(self) -> &'static str;
+
+    /// Identify the device from its IDCODE using [KNOWN_PARTS]
+    fn from_idcode(idcode: IdCode) -> Option<Self> {
+        let name = idcode.part()?.name;
+        Self::ALL.iter().copied().find(|x| x.name() == name)
+    }
+
+    /// Identify the device from a part name, ignoring any speed grade and
+    /// package suffix (e.g. the `N DEVICE` note of a JED file)
+    fn from_name(name: &str) -> Option<Self> {
+        let name = name.split('-').next()?;
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|x| x.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The entry for this device in [KNOWN_PARTS]
+    fn known_part(self) -> &'static KnownPart {
+        KNOWN_PARTS
+            .iter()
+            .find(|x| x.name == self.name())
+            .expect("every CPLD density is in KNOWN_PARTS")
+    }
+}
+
+/// Read the IDCODE selected by `ir` and check that it belongs to `device`
+pub(crate) fn check_idcode<D: CpldDevice, T: JTAGAdapter + ?Sized>(
+    adapter: &mut T,
+    ir: &BitSlice,
+    device: D,
+) -> Result<(), JTAGError> {
+    let idcode = IdCode(adapter.read_reg(ir, 32)?.load_le());
+    if D::from_idcode(idcode) != Some(device) {
+        return Err(JTAGError::InvalidAction(format!(
+            "expected {} but found {idcode}",
+            device.name()
+        )));
+    }
+    Ok(())
+}
+
+/// Record reading the IDCODE selected by `ir`, expecting that of `device`
+pub(crate) fn expect_idcode<D: CpldDevice>(
+    recorder: &mut SvfRecorder,
+    ir: &BitSlice,
+    device: D,
+) -> Result<(), JTAGError> {
+    let part = device.known_part();
+    let bits = |x: u32| x.view_bits::<Lsb0>().iter().by_vals().collect::<BitVec>();
+    recorder.read_reg(ir, 32)?;
+    recorder.expect_tdo(&bits(part.idcode), &bits(part.mask))
+}
@@ -0,0 +1,307 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+/// Maximum number of devices that chain discovery will look for
+pub const MAX_CHAIN_DEVICES: usize = 64;
+/// Maximum total IR length that chain discovery will handle
+pub const MAX_CHAIN_IR_BITS: usize = 1024;
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// Represents a single device found by [discover_chain]
+pub struct DiscoveredDevice {
+    /// The device's IDCODE, or `None` if it came up with BYPASS selected
+    pub idcode: Option<IdCode>,
+}
+impl DiscoveredDevice {
+    /// IR length of this device if it is listed in [KNOWN_PARTS], for use as
+    /// a hint for [detect_ir_lengths]
+    pub fn ir_len_hint(&self) -> Option<usize> {
+        self.idcode
+            .and_then(|idcode| idcode.part())
+            .map(|part| part.ir_len)
+    }
+}
+
+/// Count the number of devices on the scan chain by placing all of them
+/// into BYPASS and then measuring how long it takes for a 1 to make its way
+/// through the chain.
+///
+/// This resets the TAP to Test-Logic-Reset first.
+pub fn count_chain_devices<T: JTAGAdapter + ?Sized>(adapter: &mut T) -> Result<usize, JTAGError> {
+    adapter.reset_to_tlr();
+    adapter.shift_ir_out(&bitvec![1; MAX_CHAIN_IR_BITS], false);
+
+    let mut flush = bitvec![0; MAX_CHAIN_DEVICES];
+    flush.extend_from_bitslice(bits![1; MAX_CHAIN_DEVICES]);
+    let out = adapter.shift_dr_inout(&flush, false)?;
+
+    match out.first_one() {
+        Some(idx) if idx >= MAX_CHAIN_DEVICES => Ok(idx - MAX_CHAIN_DEVICES),
+        Some(_) => Err(JTAGError::ProtocolViolation(
+            "TDO did not shift out zeros (stuck high?)".to_string(),
+        )),
+        None => Err(JTAGError::ProtocolViolation(format!(
+            "no data came out of the chain (TDO stuck low, or more than {} devices?)",
+            MAX_CHAIN_DEVICES
+        ))),
+    }
+}
+
+/// Enumerate all devices on the scan chain.
+///
+/// After Test-Logic-Reset, each device has either IDCODE (32 bits, LSB=1)
+/// or BYPASS (a single 0 bit) selected, which allows each device's IDCODE
+/// to be read out without knowing anything about the chain.
+///
+/// Devices are returned in the order that their data appears on TDO,
+/// i.e. the first device is the one closest to TDO. This leaves the TAP in
+/// Run-Test/Idle with each device's reset instruction selected.
+pub fn discover_chain<T: JTAGAdapter + ?Sized>(
+    adapter: &mut T,
+) -> Result<Vec<DiscoveredDevice>, JTAGError> {
+    let num_devices = count_chain_devices(adapter)?;
+
+    adapter.reset_to_tlr();
+    let out = adapter.shift_dr_inout(&bitvec![1; num_devices * 32 + 1], false)?;
+
+    let mut ret = Vec::with_capacity(num_devices);
+    let mut pos = 0;
+    for _ in 0..num_devices {
+        if out[pos] {
+            ret.push(DiscoveredDevice {
+                idcode: Some(IdCode(out[pos..pos + 32].load_le::<u32>())),
+            });
+            pos += 32;
+        } else {
+            ret.push(DiscoveredDevice { idcode: None });
+            pos += 1;
+        }
+    }
+
+    // The ones we shifted in should come right after the last device
+    if !out[pos] {
+        return Err(JTAGError::ProtocolViolation(format!(
+            "unexpected data after {} devices",
+            num_devices
+        )));
+    }
+
+    Ok(ret)
+}
+
+/// Determine the IR length of each device on the scan chain.
+///
+/// IEEE 1149.1 requires the two bits closest to TDO to be captured as `01`
+/// in Capture-IR. This captures the IR of the entire chain while also
+/// measuring its total length (by flushing ones through it) and then finds
+/// the ways that the captured data can be split up between devices.
+///
+/// `hints` must contain one entry per device (in the same order as
+/// [discover_chain]). Known IR lengths (e.g. from looking up a device's
+/// IDCODE) can be used to resolve cases where the captured data could be
+/// split up in multiple ways, and [JTAGError::AmbiguousChain] is returned if
+/// this still isn't enough.
+///
+/// This leaves the TAP in Run-Test/Idle with all devices in BYPASS.
+pub fn detect_ir_lengths<T: JTAGAdapter + ?Sized>(
+    adapter: &mut T,
+    hints: &[Option<usize>],
+) -> Result<Vec<usize>, JTAGError> {
+    let num_devices = count_chain_devices(adapter)?;
+    if hints.len() != num_devices {
+        return Err(JTAGError::InvalidAction(format!(
+            "got {} IR length hints for {} devices",
+            hints.len(),
+            num_devices
+        )));
+    }
+
+    // The ones are shifted last so that every device ends up in BYPASS
+    let mut flush = bitvec![0; MAX_CHAIN_IR_BITS];
+    flush.extend_from_bitslice(bits![1; MAX_CHAIN_IR_BITS]);
+    let out = adapter.shift_ir_inout(&flush, false)?;
+
+    let total_len = match out[MAX_CHAIN_IR_BITS..].first_one() {
+        Some(idx) => idx,
+        None => {
+            return Err(JTAGError::ProtocolViolation(format!(
+                "no data came out of the chain (TDO stuck low, or IR longer than {} bits?)",
+                MAX_CHAIN_IR_BITS
+            )))
+        }
+    };
+    if out[total_len..MAX_CHAIN_IR_BITS].any() {
+        return Err(JTAGError::ProtocolViolation(
+            "zeros shifted into the IR did not come out".to_string(),
+        ));
+    }
+
+    let splits = split_ir_capture(&out[..total_len], hints, 2);
+    match splits.len() {
+        0 => Err(JTAGError::ProtocolViolation(format!(
+            "captured IR {:b} does not match {} devices",
+            &out[..total_len],
+            num_devices
+        ))),
+        1 => Ok(splits.into_iter().next().unwrap()),
+        _ => Err(JTAGError::AmbiguousChain(format!(
+            "captured IR {:b} can be split as {:?} or {:?}",
+            &out[..total_len],
+            splits[0],
+            splits[1]
+        ))),
+    }
+}
+
+/// Find up to `limit` ways to split `captured` into IR captures which each
+/// start with 1, 0 and agree with `hints`
+fn split_ir_capture(captured: &BitSlice, hints: &[Option<usize>], limit: usize) -> Vec<Vec<usize>> {
+    fn recurse(
+        captured: &BitSlice,
+        hints: &[Option<usize>],
+        limit: usize,
+        current: &mut Vec<usize>,
+        results: &mut Vec<Vec<usize>>,
+    ) {
+        if results.len() >= limit {
+            return;
+        }
+        let Some((hint, rest_hints)) = hints.split_first() else {
+            if captured.is_empty() {
+                results.push(current.clone());
+            }
+            return;
+        };
+
+        if captured.len() < 2 || !captured[0] || captured[1] {
+            return;
+        }
+
+        // every remaining device needs at least 2 bits
+        let max_len = captured.len().saturating_sub(2 * rest_hints.len());
+        for len in 2..=max_len {
+            if hint.is_some_and(|hint| hint != len) {
+                continue;
+            }
+
+            current.push(len);
+            recurse(&captured[len..], rest_hints, limit, current, results);
+            current.pop();
+        }
+    }
+
+    let mut results = Vec::new();
+    recurse(captured, hints, limit, &mut Vec::new(), &mut results);
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_ir_capture;
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    #[test]
+    fn test_discover_chain() {
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![
+            Box::new(
+                drivers::GenericSimulatedDevice::new(6)
+                    .with_idcode(0x1372_3093, bits![1, 0, 0, 1, 0, 0]),
+            ),
+            Box::new(drivers::GenericSimulatedDevice::new(8)),
+            Box::new(
+                drivers::GenericSimulatedDevice::new(4).with_idcode(0x4ba0_0477, bits![0, 1, 1, 1]),
+            ),
+            Box::new(drivers::GenericSimulatedDevice::new(2)),
+        ]);
+
+        assert_eq!(count_chain_devices(&mut adapter).unwrap(), 4);
+        assert_eq!(
+            discover_chain(&mut adapter).unwrap(),
+            vec![
+                DiscoveredDevice {
+                    idcode: Some(IdCode(0x1372_3093))
+                },
+                DiscoveredDevice { idcode: None },
+                DiscoveredDevice {
+                    idcode: Some(IdCode(0x4ba0_0477))
+                },
+                DiscoveredDevice { idcode: None },
+            ]
+        );
+        assert_eq!(adapter.tap_state(), JTAGState::RunTestIdle);
+    }
+
+    #[test]
+    fn test_detect_ir_lengths() {
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![
+            Box::new(drivers::GenericSimulatedDevice::new(6)),
+            Box::new(drivers::GenericSimulatedDevice::new(8)),
+            Box::new(drivers::GenericSimulatedDevice::new(4)),
+            Box::new(drivers::GenericSimulatedDevice::new(2)),
+        ]);
+
+        assert_eq!(
+            detect_ir_lengths(&mut adapter, &[None; 4]).unwrap(),
+            vec![6, 8, 4, 2]
+        );
+        assert_eq!(adapter.current_ir(1), bits![1; 8]);
+        assert_eq!(adapter.tap_state(), JTAGState::RunTestIdle);
+    }
+
+    #[test]
+    fn test_split_ir_capture() {
+        // unambiguous with the mandatory bits alone
+        assert_eq!(
+            split_ir_capture(bits![1, 0, 0, 1, 0, 1, 0, 0, 0], &[None; 3], 10),
+            vec![vec![3, 2, 4]]
+        );
+
+        // the first device might also have captured 1, 0 in its upper bits
+        let captured = bits![1, 0, 0, 1, 0, 0, 1, 0, 0];
+        assert_eq!(
+            split_ir_capture(captured, &[None; 2], 10),
+            vec![vec![3, 6], vec![6, 3]]
+        );
+        assert_eq!(
+            split_ir_capture(captured, &[Some(6), None], 10),
+            vec![vec![6, 3]]
+        );
+        assert_eq!(split_ir_capture(captured, &[None; 2], 1).len(), 1);
+
+        // doesn't follow 1149.1
+        assert!(split_ir_capture(bits![0, 1, 1, 0], &[None; 2], 10).is_empty());
+    }
+
+    #[test]
+    fn test_detect_ir_lengths_with_hints() {
+        // the first device captures 1, 0 in its upper bits as well
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![
+            Box::new(
+                drivers::GenericSimulatedDevice::new(6)
+                    .with_idcode(0x0362_d093, bits![1, 0, 0, 1, 0, 0])
+                    .with_capture_ir(bits![1, 0, 1, 0, 0, 1]),
+            ),
+            Box::new(
+                drivers::GenericSimulatedDevice::new(4).with_idcode(0x4ba0_0477, bits![0, 1, 1, 1]),
+            ),
+        ]);
+
+        let ret = detect_ir_lengths(&mut adapter, &[None; 2]);
+        assert!(matches!(ret, Err(JTAGError::AmbiguousChain(..))));
+
+        let hints = discover_chain(&mut adapter)
+            .unwrap()
+            .iter()
+            .map(|device| device.ir_len_hint())
+            .collect::<Vec<_>>();
+        assert_eq!(hints, vec![Some(6), Some(4)]);
+        assert_eq!(detect_ir_lengths(&mut adapter, &hints).unwrap(), vec![6, 4]);
+        assert_eq!(
+            detect_ir_lengths(&mut adapter, &[Some(6), None]).unwrap(),
+            vec![6, 4]
+        );
+    }
+}
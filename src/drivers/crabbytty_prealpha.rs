@@ -22,30 +22,27 @@ impl AsMut<BitbangJTAGAdapterState> for CrabbyTTYPreAlphaJTAG {
     }
 }
 impl CrabbyTTYPreAlphaJTAG {
-    pub fn new() -> Self {
-        println!("new");
+    pub fn new() -> Result<Self, JTAGError> {
+        let device =
+            rusb::open_device_with_vid_pid(0xf055, 0x0000).ok_or(JTAGError::DeviceNotFound)?;
+        device.write_control(0x40, 1, 0, 0, &[], std::time::Duration::from_secs(1))?;
 
-        let device = rusb::open_device_with_vid_pid(0xf055, 0x0000).unwrap();
-        device
-            .write_control(0x40, 1, 0, 0, &[], std::time::Duration::from_secs(1))
-            .unwrap();
-
-        Self {
+        Ok(Self {
             jtag_state: JTAGAdapterState::new(),
             chunkshift_state: ChunkShifterJTAGAdapterState::new(),
             bitbang_state: BitbangJTAGAdapterState::new(),
             usb: device,
-        }
+        })
     }
 }
 
 impl BitbangJTAGAdapter for CrabbyTTYPreAlphaJTAG {
-    fn set_clk_speed(&mut self, clk_hz: u64) -> u64 {
+    fn set_clk_speed(&mut self, clk_hz: u64) -> Result<u64, JTAGError> {
         println!("ignoring clock speed {clk_hz} hz");
-        clk_hz
+        Ok(clk_hz)
     }
 
-    fn shift_one_bit(&mut self, tms: bool, tdi: bool) -> bool {
+    fn shift_one_bit(&mut self, tms: bool, tdi: bool) -> Result<bool, JTAGError> {
         let mut reqbyte = 0u8;
 
         if tdi {
@@ -63,11 +60,13 @@ impl BitbangJTAGAdapter for CrabbyTTYPreAlphaJTAG {
             0,
             resbyte,
             std::time::Duration::from_secs(1),
-        );
-        assert_eq!(usbret.unwrap(), 1);
-
-        println!("tms {tms} tdi {tdi} --> {resbyte:?}");
+        )?;
+        if usbret != 1 {
+            return Err(JTAGError::ProtocolViolation(format!(
+                "expected 1 byte from device, got {usbret}"
+            )));
+        }
 
-        resbyte[0] & 1 != 0
+        Ok(resbyte[0] & 1 != 0)
     }
 }
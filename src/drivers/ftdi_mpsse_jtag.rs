@@ -19,10 +19,8 @@ impl AsMut<ChunkShifterJTAGAdapterState> for FTDIJTAG {
     }
 }
 impl FTDIJTAG {
-    pub fn new() -> Self {
-        println!("new");
-
-        let mut device = ftdi::find_by_vid_pid(0x0403, 0x8028).open().unwrap();
+    pub fn new() -> Result<Self, JTAGError> {
+        let mut device = ftdi::find_by_vid_pid(0x0403, 0x8028).open()?;
 
         let mpsse = ftdi_mpsse::MpsseSettings {
             reset: true,
@@ -34,7 +32,7 @@ impl FTDIJTAG {
             clock_frequency: Some(1_000_000),
         };
 
-        device.init(&mpsse).unwrap();
+        device.init(&mpsse)?;
 
         mpsse! {
             const (INIT_DATA, INIT_LEN) = {
@@ -43,29 +41,42 @@ impl FTDIJTAG {
         }
 
         assert_eq!(INIT_LEN, 0);
-        device.send(&INIT_DATA).unwrap();
+        device.send(&INIT_DATA)?;
 
-        Self {
+        Ok(Self {
             jtag_state: JTAGAdapterState::new(),
             chunkshift_state: ChunkShifterJTAGAdapterState::new(),
             ftdi: device,
-        }
+        })
+    }
+}
+
+/// Split off the last bit of a chunk, which is shifted together with TMS
+/// when exiting the shift state
+fn data_portion(tdi_chunk: &BitSlice, tms_exit: bool) -> Result<&BitSlice, JTAGError> {
+    if !tms_exit {
+        return Ok(tdi_chunk);
+    }
+    if tdi_chunk.len() <= 1 {
+        return Err(JTAGError::InvalidAction(format!(
+            "cannot shift {} bit(s) with tms_exit",
+            tdi_chunk.len()
+        )));
     }
+    Ok(&tdi_chunk[..(tdi_chunk.len() - 1)])
 }
 
 impl ChunkShifterJTAGAdapter for FTDIJTAG {
-    fn delay_ns(&mut self, ns: u64) -> u64 {
+    fn delay_ns(&mut self, ns: u64) -> Result<u64, JTAGError> {
         std::thread::sleep(std::time::Duration::from_nanos(ns));
-        ns
+        Ok(ns)
     }
-    fn set_clk_speed(&mut self, clk_hz: u64) -> u64 {
+    fn set_clk_speed(&mut self, clk_hz: u64) -> Result<u64, JTAGError> {
         println!("ignoring clock speed {clk_hz} hz");
-        clk_hz
+        Ok(clk_hz)
     }
 
-    fn shift_tms_chunk(&mut self, tms_chunk: &BitSlice) {
-        println!("shift tms {tms_chunk:b}");
-
+    fn shift_tms_chunk(&mut self, tms_chunk: &BitSlice) -> Result<(), JTAGError> {
         let mut bytes = Vec::new();
 
         for subchunk in tms_chunk.chunks(7) {
@@ -76,20 +87,12 @@ impl ChunkShifterJTAGAdapter for FTDIJTAG {
             bytes.push(thisbyte);
         }
 
-        println!("the resulting buffer is {bytes:x?}");
-
-        self.ftdi.send(&bytes).unwrap();
+        self.ftdi.send(&bytes)?;
+        Ok(())
     }
-    fn shift_tdi_chunk(&mut self, tdi_chunk: &BitSlice, tms_exit: bool) {
-        println!("shift tdi {tdi_chunk:b} tms? {tms_exit}");
-
+    fn shift_tdi_chunk(&mut self, tdi_chunk: &BitSlice, tms_exit: bool) -> Result<(), JTAGError> {
         // this is the main portion that will be sent as bytes
-        let tdi_chunk_ = if tms_exit {
-            assert!(tdi_chunk.len() > 1); // XXX error reporting?
-            &tdi_chunk[..(tdi_chunk.len() - 1)]
-        } else {
-            tdi_chunk
-        };
+        let tdi_chunk_ = data_portion(tdi_chunk, tms_exit)?;
 
         let mut mpsse_bytes = Vec::new();
 
@@ -97,15 +100,17 @@ impl ChunkShifterJTAGAdapter for FTDIJTAG {
             // bytes portion first
             let chunk_bytes = subchunk.len() / 8; // deliberate truncate
 
-            mpsse_bytes.push(ClockDataOut::LsbNeg as u8); // tdi out on -ve
-            mpsse_bytes.push((chunk_bytes - 1) as u8);
-            mpsse_bytes.push(((chunk_bytes - 1) >> 8) as u8);
+            if chunk_bytes > 0 {
+                mpsse_bytes.push(ClockDataOut::LsbNeg as u8); // tdi out on -ve
+                mpsse_bytes.push((chunk_bytes - 1) as u8);
+                mpsse_bytes.push(((chunk_bytes - 1) >> 8) as u8);
 
-            let cur_mpsse_len = mpsse_bytes.len();
-            mpsse_bytes.resize(cur_mpsse_len + chunk_bytes, 0u8);
-            mpsse_bytes[cur_mpsse_len..cur_mpsse_len + chunk_bytes]
-                .view_bits_mut::<Lsb0>()
-                .clone_from_bitslice(&subchunk[..chunk_bytes * 8]);
+                let cur_mpsse_len = mpsse_bytes.len();
+                mpsse_bytes.resize(cur_mpsse_len + chunk_bytes, 0u8);
+                mpsse_bytes[cur_mpsse_len..cur_mpsse_len + chunk_bytes]
+                    .view_bits_mut::<Lsb0>()
+                    .clone_from_bitslice(&subchunk[..chunk_bytes * 8]);
+            }
 
             // bits portion
             let chunk_bits = subchunk.len() % 8;
@@ -130,18 +135,16 @@ impl ChunkShifterJTAGAdapter for FTDIJTAG {
             }
         }
 
-        println!("the resulting buffer is {mpsse_bytes:x?}");
-        self.ftdi.send(&mpsse_bytes).unwrap();
+        self.ftdi.send(&mpsse_bytes)?;
+        Ok(())
     }
-    fn shift_tditdo_chunk(&mut self, tdi_chunk: &BitSlice, tms_exit: bool) -> BitVec {
-        println!("shift tditdo {tdi_chunk:b} tms? {tms_exit}");
-
+    fn shift_tditdo_chunk(
+        &mut self,
+        tdi_chunk: &BitSlice,
+        tms_exit: bool,
+    ) -> Result<BitVec, JTAGError> {
         // this is the main portion that will be sent as bytes
-        let tdi_chunk_ = if tms_exit {
-            &tdi_chunk[..(tdi_chunk.len() - 1)]
-        } else {
-            tdi_chunk
-        };
+        let tdi_chunk_ = data_portion(tdi_chunk, tms_exit)?;
 
         let mut mpsse_bytes = Vec::new();
         let mut rxbytes_bufsz = 0;
@@ -196,12 +199,10 @@ impl ChunkShifterJTAGAdapter for FTDIJTAG {
 
         mpsse_bytes.push(MpsseCmd::SendImmediate as u8);
 
-        println!("the resulting buffer is {mpsse_bytes:x?} rx {rxbytes_bufsz}");
-        self.ftdi.send(&mpsse_bytes).unwrap();
+        self.ftdi.send(&mpsse_bytes)?;
 
         let mut rxbytebuf = vec![0; rxbytes_bufsz];
-        self.ftdi.recv(&mut rxbytebuf).unwrap();
-        println!("got back {rxbytebuf:x?}");
+        self.ftdi.recv(&mut rxbytebuf)?;
 
         let mut ret: BitVec = BitVec::with_capacity(tdi_chunk.len());
         ret.extend_from_bitslice(rxbytebuf[..rxbytes_bytes].view_bits::<Lsb0>());
@@ -214,6 +215,6 @@ impl ChunkShifterJTAGAdapter for FTDIJTAG {
             ret.push((rxbytebuf[rxbytebuf.len() - 1] & 0x80) != 0);
         }
 
-        ret
+        Ok(ret)
     }
 }
@@ -5,3 +5,8 @@ pub use crabbytty_prealpha::CrabbyTTYPreAlphaJTAG;
 
 mod ftdi_mpsse_jtag;
 pub use ftdi_mpsse_jtag::FTDIJTAG;
+
+mod simulated;
+pub use simulated::{
+    GenericSimulatedDevice, SimulatedDevice, SimulatedJTAGAdapter, XilinxSimulatedDevice,
+};
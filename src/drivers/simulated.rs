@@ -0,0 +1,381 @@
+use crate::*;
+
+use bitvec::prelude::*;
+use std::collections::HashMap;
+
+/// Trait for devices that can be placed on the scan chain of a
+/// [SimulatedJTAGAdapter]
+///
+/// All registers are stored with bit 0 being the first bit shifted out on TDO.
+pub trait SimulatedDevice {
+    /// Length of the instruction register
+    fn ir_len(&self) -> usize;
+    /// Instruction selected when the TAP enters Test-Logic-Reset
+    fn reset_ir(&self) -> BitVec;
+    /// Value loaded into the IR shift register in Capture-IR. IEEE 1149.1
+    /// requires the two least significant bits to be `01`.
+    fn capture_ir(&self) -> BitVec {
+        let mut ret = BitVec::repeat(false, self.ir_len());
+        ret.set(0, true);
+        ret
+    }
+    /// Value loaded into the DR shift register in Capture-DR. The length of
+    /// the returned data (which must be at least 1 bit) determines the length
+    /// of the selected data register.
+    fn capture_dr(&mut self, ir: &BitSlice) -> BitVec;
+    /// Called in Update-IR with the newly selected instruction
+    fn update_ir(&mut self, _ir: &BitSlice) {}
+    /// Called in Shift-DR with each bit shifted into the data register, for
+    /// registers which are streamed rather than used in Update-DR
+    fn shift_dr(&mut self, _ir: &BitSlice, _tdi: bool) {}
+    /// Called in Shift-DR after [shift_dr][Self::shift_dr] to override the
+    /// next bit shifted out, for streamed registers whose output depends on
+    /// what has been shifted in so far
+    fn shift_dr_out(&mut self, _ir: &BitSlice) -> Option<bool> {
+        None
+    }
+    /// Called in Update-DR with the data that has been shifted in
+    fn update_dr(&mut self, ir: &BitSlice, dr: &BitSlice);
+    /// Called when the TAP enters Test-Logic-Reset
+    fn reset(&mut self) {}
+}
+
+/// A [SimulatedDevice] with a BYPASS register, an optional IDCODE register,
+/// and any number of additional read/write data registers
+#[derive(Clone, Debug)]
+pub struct GenericSimulatedDevice {
+    ir_len: usize,
+    capture_ir: BitVec,
+    idcode: Option<(u32, BitVec)>,
+    registers: HashMap<BitVec, BitVec>,
+}
+impl GenericSimulatedDevice {
+    /// Create a device which only implements BYPASS
+    pub fn new(ir_len: usize) -> Self {
+        assert!(ir_len >= 2);
+        let mut capture_ir = BitVec::repeat(false, ir_len);
+        capture_ir.set(0, true);
+        Self {
+            ir_len,
+            capture_ir,
+            idcode: None,
+            registers: HashMap::new(),
+        }
+    }
+    /// Set the value captured in Capture-IR (which must start with 1, 0)
+    pub fn with_capture_ir(mut self, capture_ir: &BitSlice) -> Self {
+        assert_eq!(capture_ir.len(), self.ir_len);
+        self.capture_ir = capture_ir.to_owned();
+        self
+    }
+    /// Give the device an IDCODE register, selected by `ir` and after
+    /// Test-Logic-Reset
+    pub fn with_idcode(mut self, idcode: u32, ir: &BitSlice) -> Self {
+        assert_eq!(ir.len(), self.ir_len);
+        self.idcode = Some((idcode, ir.to_owned()));
+        self
+    }
+    /// Add a read/write data register selected by `ir` with the given
+    /// initial value
+    pub fn with_register(mut self, ir: &BitSlice, init: &BitSlice) -> Self {
+        assert_eq!(ir.len(), self.ir_len);
+        self.registers.insert(ir.to_owned(), init.to_owned());
+        self
+    }
+    /// Get the current value of the data register selected by `ir`
+    pub fn register(&self, ir: &BitSlice) -> Option<&BitSlice> {
+        self.registers.get(ir).map(|x| x.as_bitslice())
+    }
+}
+
+impl SimulatedDevice for GenericSimulatedDevice {
+    fn ir_len(&self) -> usize {
+        self.ir_len
+    }
+    fn capture_ir(&self) -> BitVec {
+        self.capture_ir.clone()
+    }
+    fn reset_ir(&self) -> BitVec {
+        if let Some((_, ir)) = &self.idcode {
+            ir.clone()
+        } else {
+            BitVec::repeat(true, self.ir_len)
+        }
+    }
+    fn capture_dr(&mut self, ir: &BitSlice) -> BitVec {
+        if let Some((idcode, idcode_ir)) = &self.idcode {
+            if idcode_ir == ir {
+                return idcode.view_bits::<Lsb0>().iter().by_vals().collect();
+            }
+        }
+        if let Some(reg) = self.registers.get(ir) {
+            return reg.clone();
+        }
+        // BYPASS
+        bitvec![0]
+    }
+    fn update_dr(&mut self, ir: &BitSlice, dr: &BitSlice) {
+        if let Some(reg) = self.registers.get_mut(ir) {
+            reg.clone_from_bitslice(dr);
+        }
+    }
+}
+
+/// Trait for simulated Xilinx FPGAs (7-series and UltraScale), which have a
+/// 6-bit instruction register that selects IDCODE (0x09) after
+/// Test-Logic-Reset
+///
+/// Only the data register behaviour needs to be provided. The methods are
+/// the same as those of [SimulatedDevice].
+pub trait XilinxSimulatedDevice {
+    /// Value loaded into the IR shift register in Capture-IR. Devices which
+    /// report their configuration status in the upper bits override this.
+    fn capture_ir(&self) -> BitVec {
+        bitvec![1, 0, 0, 0, 0, 0]
+    }
+    /// See [SimulatedDevice::capture_dr]
+    fn capture_dr(&mut self, ir: &BitSlice) -> BitVec;
+    /// See [SimulatedDevice::update_ir]
+    fn update_ir(&mut self, _ir: &BitSlice) {}
+    /// See [SimulatedDevice::shift_dr]
+    fn shift_dr(&mut self, _ir: &BitSlice, _tdi: bool) {}
+    /// See [SimulatedDevice::shift_dr_out]
+    fn shift_dr_out(&mut self, _ir: &BitSlice) -> Option<bool> {
+        None
+    }
+    /// See [SimulatedDevice::update_dr]
+    fn update_dr(&mut self, ir: &BitSlice, dr: &BitSlice);
+    /// See [SimulatedDevice::reset]
+    fn reset(&mut self) {}
+}
+
+impl<T: XilinxSimulatedDevice> SimulatedDevice for T {
+    fn ir_len(&self) -> usize {
+        6
+    }
+    fn reset_ir(&self) -> BitVec {
+        bitvec![1, 0, 0, 1, 0, 0]
+    }
+    fn capture_ir(&self) -> BitVec {
+        XilinxSimulatedDevice::capture_ir(self)
+    }
+    fn capture_dr(&mut self, ir: &BitSlice) -> BitVec {
+        XilinxSimulatedDevice::capture_dr(self, ir)
+    }
+    fn update_ir(&mut self, ir: &BitSlice) {
+        XilinxSimulatedDevice::update_ir(self, ir)
+    }
+    fn shift_dr(&mut self, ir: &BitSlice, tdi: bool) {
+        XilinxSimulatedDevice::shift_dr(self, ir, tdi)
+    }
+    fn shift_dr_out(&mut self, ir: &BitSlice) -> Option<bool> {
+        XilinxSimulatedDevice::shift_dr_out(self, ir)
+    }
+    fn update_dr(&mut self, ir: &BitSlice, dr: &BitSlice) {
+        XilinxSimulatedDevice::update_dr(self, ir, dr)
+    }
+    fn reset(&mut self) {
+        XilinxSimulatedDevice::reset(self)
+    }
+}
+
+struct SimulatedTAP {
+    device: Box<dyn SimulatedDevice>,
+    ir: BitVec,
+    ir_shift: BitVec,
+    dr_shift: BitVec,
+}
+
+/// The simulated scan chain, clocked one bit at a time
+struct SimulatedChain {
+    bitbang_state: BitbangJTAGAdapterState,
+    tap_state: JTAGState,
+    taps: Vec<SimulatedTAP>,
+    tck_cycles: u64,
+}
+impl AsMut<BitbangJTAGAdapterState> for SimulatedChain {
+    fn as_mut(&mut self) -> &mut BitbangJTAGAdapterState {
+        &mut self.bitbang_state
+    }
+}
+
+/// JTAG adapter that drives a pure-software model of a scan chain rather
+/// than real hardware
+///
+/// Devices are listed in the order that their data appears on TDO, i.e.
+/// the first device is the one closest to TDO.
+pub struct SimulatedJTAGAdapter {
+    jtag_state: JTAGAdapterState,
+    chunkshift_state: ChunkShifterJTAGAdapterState,
+    chain: SimulatedChain,
+    delayed_ns: u64,
+}
+impl AsMut<JTAGAdapterState> for SimulatedJTAGAdapter {
+    fn as_mut(&mut self) -> &mut JTAGAdapterState {
+        &mut self.jtag_state
+    }
+}
+impl AsMut<ChunkShifterJTAGAdapterState> for SimulatedJTAGAdapter {
+    fn as_mut(&mut self) -> &mut ChunkShifterJTAGAdapterState {
+        &mut self.chunkshift_state
+    }
+}
+impl SimulatedJTAGAdapter {
+    pub fn new(devices: Vec<Box<dyn SimulatedDevice>>) -> Self {
+        let mut chain = SimulatedChain {
+            bitbang_state: BitbangJTAGAdapterState::new(),
+            tap_state: JTAGState::TestLogicReset,
+            taps: devices
+                .into_iter()
+                .map(|device| SimulatedTAP {
+                    device,
+                    ir: BitVec::new(),
+                    ir_shift: BitVec::new(),
+                    dr_shift: BitVec::new(),
+                })
+                .collect(),
+            tck_cycles: 0,
+        };
+        chain.reset_taps();
+        Self {
+            jtag_state: JTAGAdapterState::new(),
+            chunkshift_state: ChunkShifterJTAGAdapterState::new(),
+            chain,
+            delayed_ns: 0,
+        }
+    }
+
+    /// Current state of the simulated TAP controllers
+    pub fn tap_state(&self) -> JTAGState {
+        self.chain.tap_state
+    }
+    /// Number of TCK cycles that have been clocked so far
+    pub fn tck_cycles(&self) -> u64 {
+        self.chain.tck_cycles
+    }
+    /// Total time that has been waited for with delays, which are not
+    /// actually slept for
+    pub fn delayed_ns(&self) -> u64 {
+        self.delayed_ns
+    }
+    /// Currently active instruction of the given device
+    pub fn current_ir(&self, device: usize) -> &BitSlice {
+        &self.chain.taps[device].ir
+    }
+}
+
+/// Delays are only counted, everything else is clocked through the chain
+impl ChunkShifterJTAGAdapter for SimulatedJTAGAdapter {
+    fn delay_ns(&mut self, ns: u64) -> Result<u64, JTAGError> {
+        self.delayed_ns += ns;
+        Ok(ns)
+    }
+    fn set_clk_speed(&mut self, clk_hz: u64) -> Result<u64, JTAGError> {
+        BitbangJTAGAdapter::set_clk_speed(&mut self.chain, clk_hz)
+    }
+
+    fn shift_tms_chunk(&mut self, tms_chunk: &BitSlice) -> Result<(), JTAGError> {
+        self.chain.shift_tms_chunk(tms_chunk)
+    }
+    fn shift_tdi_chunk(&mut self, tdi_chunk: &BitSlice, tms_exit: bool) -> Result<(), JTAGError> {
+        self.chain.shift_tdi_chunk(tdi_chunk, tms_exit)
+    }
+    fn shift_tditdo_chunk(
+        &mut self,
+        tdi_chunk: &BitSlice,
+        tms_exit: bool,
+    ) -> Result<BitVec, JTAGError> {
+        self.chain.shift_tditdo_chunk(tdi_chunk, tms_exit)
+    }
+}
+
+impl SimulatedChain {
+    fn reset_taps(&mut self) {
+        for tap in &mut self.taps {
+            tap.device.reset();
+            tap.ir = tap.device.reset_ir();
+        }
+    }
+
+    // Shift one bit through every TAP's selected shift register, going from
+    // TDI towards TDO
+    fn shift_chain(&mut self, tdi: bool, ir: bool) {
+        let mut carry = tdi;
+        for tap in self.taps.iter_mut().rev() {
+            let reg = if ir {
+                &mut tap.ir_shift
+            } else {
+                tap.device.shift_dr(&tap.ir, carry);
+                &mut tap.dr_shift
+            };
+            reg.push(carry);
+            carry = reg.remove(0);
+            if !ir {
+                if let Some(out) = tap.device.shift_dr_out(&tap.ir) {
+                    reg.set(0, out);
+                }
+            }
+        }
+    }
+}
+
+impl BitbangJTAGAdapter for SimulatedChain {
+    fn set_clk_speed(&mut self, clk_hz: u64) -> Result<u64, JTAGError> {
+        Ok(clk_hz)
+    }
+
+    fn shift_one_bit(&mut self, tms: bool, tdi: bool) -> Result<bool, JTAGError> {
+        self.tck_cycles += 1;
+
+        // rising edge
+        match self.tap_state {
+            JTAGState::CaptureIR => {
+                for tap in &mut self.taps {
+                    tap.ir_shift = tap.device.capture_ir();
+                }
+            }
+            JTAGState::CaptureDR => {
+                for tap in &mut self.taps {
+                    tap.dr_shift = tap.device.capture_dr(&tap.ir);
+                    assert!(
+                        !tap.dr_shift.is_empty(),
+                        "capture_dr returned an empty data register for IR {}",
+                        tap.ir
+                    );
+                }
+            }
+            JTAGState::ShiftIR => self.shift_chain(tdi, true),
+            JTAGState::ShiftDR => self.shift_chain(tdi, false),
+            _ => {}
+        }
+        self.tap_state = self.tap_state.transition(tms);
+
+        // falling edge
+        match self.tap_state {
+            JTAGState::TestLogicReset => self.reset_taps(),
+            JTAGState::UpdateIR => {
+                for tap in &mut self.taps {
+                    tap.ir = tap.ir_shift.clone();
+                    tap.device.update_ir(&tap.ir);
+                }
+            }
+            JTAGState::UpdateDR => {
+                for tap in &mut self.taps {
+                    tap.device.update_dr(&tap.ir, &tap.dr_shift);
+                }
+            }
+            _ => {}
+        }
+
+        // TDO is only driven in Shift-IR/DR, otherwise assume a pull-up
+        let tdo_reg = match self.tap_state {
+            JTAGState::ShiftIR => self.taps.first().map(|tap| &tap.ir_shift),
+            JTAGState::ShiftDR => self.taps.first().map(|tap| &tap.dr_shift),
+            _ => return Ok(true),
+        };
+        Ok(match tdo_reg {
+            Some(reg) => reg[0],
+            None => tdi,
+        })
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Where in an input file a [ParseError] was found
+pub enum ParseLocation {
+    /// The error is not about any particular part of the file
+    Unknown,
+    /// Line number, starting from 1, for text formats
+    Line(usize),
+    /// Byte offset for binary formats
+    Offset(usize),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// An input file (e.g. BSDL) could not be parsed
+pub struct ParseError {
+    /// Name of the file format, e.g. "SVF"
+    pub format: &'static str,
+    /// Where in the file the error was found
+    pub location: ParseLocation,
+    /// Description of the error
+    pub msg: String,
+}
+
+impl ParseError {
+    /// Create an error in a file of the given format, without a location
+    pub fn new(format: &'static str, msg: impl Into<String>) -> Self {
+        Self {
+            format,
+            location: ParseLocation::Unknown,
+            msg: msg.into(),
+        }
+    }
+
+    /// Set the line (starting from 1) that the error was found on
+    pub fn at_line(mut self, line: usize) -> Self {
+        self.location = ParseLocation::Line(line);
+        self
+    }
+
+    /// Set the byte offset that the error was found at
+    pub fn at_offset(mut self, offset: usize) -> Self {
+        self.location = ParseLocation::Offset(offset);
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            ParseLocation::Unknown => write!(f, "{}: {}", self.format, self.msg),
+            ParseLocation::Line(line) => write!(f, "{} line {line}: {}", self.format, self.msg),
+            ParseLocation::Offset(offset) => {
+                write!(f, "{} offset {offset:#x}: {}", self.format, self.msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug)]
+#[non_exhaustive]
+/// Represents all possible errors that can occur while performing JTAG actions
+pub enum JTAGError {
+    /// The underlying transport (e.g. USB) reported an error
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+    /// The adapter did not respond in time
+    Timeout,
+    /// The requested adapter could not be found
+    DeviceNotFound,
+    /// The adapter (or the TAP behind it) responded with data that does not
+    /// match what was requested
+    ProtocolViolation(String),
+    /// The requested action cannot be performed by this adapter
+    InvalidAction(String),
+    /// The scan chain could not be identified unambiguously
+    AmbiguousChain(String),
+    /// An input file (e.g. BSDL) could not be parsed
+    Parse(ParseError),
+    /// Data read back from the TAP did not match the expected data
+    VerifyFailed(String),
+}
+
+impl fmt::Display for JTAGError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JTAGError::Transport(e) => write!(f, "transport error: {e}"),
+            JTAGError::Timeout => write!(f, "timed out"),
+            JTAGError::DeviceNotFound => write!(f, "device not found"),
+            JTAGError::ProtocolViolation(msg) => write!(f, "protocol violation: {msg}"),
+            JTAGError::InvalidAction(msg) => write!(f, "invalid action: {msg}"),
+            JTAGError::AmbiguousChain(msg) => write!(f, "ambiguous scan chain: {msg}"),
+            JTAGError::Parse(e) => write!(f, "parse error: {e}"),
+            JTAGError::VerifyFailed(msg) => write!(f, "verification failed: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for JTAGError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JTAGError::Transport(e) => Some(e.as_ref()),
+            JTAGError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseError> for JTAGError {
+    fn from(e: ParseError) -> Self {
+        JTAGError::Parse(e)
+    }
+}
+
+impl From<std::io::Error> for JTAGError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::TimedOut => JTAGError::Timeout,
+            std::io::ErrorKind::NotFound => JTAGError::DeviceNotFound,
+            _ => JTAGError::Transport(Box::new(e)),
+        }
+    }
+}
+
+impl From<rusb::Error> for JTAGError {
+    fn from(e: rusb::Error) -> Self {
+        match e {
+            rusb::Error::Timeout => JTAGError::Timeout,
+            rusb::Error::NoDevice | rusb::Error::NotFound => JTAGError::DeviceNotFound,
+            _ => JTAGError::Transport(Box::new(e)),
+        }
+    }
+}
+
+impl From<ftdi::Error> for JTAGError {
+    fn from(e: ftdi::Error) -> Self {
+        match e {
+            ftdi::Error::DeviceNotFound => JTAGError::DeviceNotFound,
+            _ => JTAGError::Transport(Box::new(e)),
+        }
+    }
+}
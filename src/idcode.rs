@@ -0,0 +1,383 @@
+use std::fmt;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// A 32-bit IEEE 1149.1 IDCODE
+pub struct IdCode(pub u32);
+
+impl IdCode {
+    /// Version (bits 31-28)
+    pub const fn version(&self) -> u8 {
+        (self.0 >> 28) as u8
+    }
+    /// Part number (bits 27-12)
+    pub const fn part_number(&self) -> u16 {
+        (self.0 >> 12) as u16
+    }
+    /// JEP106 bank of the manufacturer, expressed as the number of
+    /// continuation codes (i.e. bank number minus one) (bits 11-8)
+    pub const fn manufacturer_bank(&self) -> u8 {
+        ((self.0 >> 8) & 0xf) as u8
+    }
+    /// JEP106 manufacturer ID within its bank, without the parity bit
+    /// (bits 7-1)
+    pub const fn manufacturer_id(&self) -> u8 {
+        ((self.0 >> 1) & 0x7f) as u8
+    }
+    /// Returns `true` if this looks like a real IDCODE, i.e. the LSB is set
+    /// and the manufacturer ID is not the reserved value 0x7f
+    pub const fn is_valid(&self) -> bool {
+        self.0 & 1 != 0 && self.manufacturer_id() != 0x7f
+    }
+
+    /// Name of the manufacturer, if it is known
+    pub fn manufacturer_name(&self) -> Option<&'static str> {
+        let (bank, id) = (self.manufacturer_bank(), self.manufacturer_id());
+        JEP106
+            .iter()
+            .find(|x| x.0 == bank && x.1 == id)
+            .map(|x| x.2)
+    }
+
+    /// Look up this IDCODE in the built-in table of [KNOWN_PARTS]
+    pub fn part(&self) -> Option<&'static KnownPart> {
+        self.part_in(KNOWN_PARTS)
+    }
+    /// Look up this IDCODE in a user-supplied table of parts
+    pub fn part_in<'a>(&self, parts: &'a [KnownPart]) -> Option<&'a KnownPart> {
+        parts
+            .iter()
+            .find(|part| self.0 & part.mask == part.idcode & part.mask)
+    }
+}
+
+impl From<u32> for IdCode {
+    fn from(idcode: u32) -> Self {
+        Self(idcode)
+    }
+}
+impl From<IdCode> for u32 {
+    fn from(idcode: IdCode) -> Self {
+        idcode.0
+    }
+}
+
+impl fmt::Display for IdCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08X}", self.0)?;
+        match (self.part(), self.manufacturer_name()) {
+            (Some(part), Some(mfg)) => write!(f, " ({mfg} {})", part.name),
+            (Some(part), None) => write!(f, " ({})", part.name),
+            (None, Some(mfg)) => write!(
+                f,
+                " ({mfg} part {:04X} version {})",
+                self.part_number(),
+                self.version()
+            ),
+            (None, None) => write!(
+                f,
+                " (unknown manufacturer {}:{:02X} part {:04X} version {})",
+                self.manufacturer_bank(),
+                self.manufacturer_id(),
+                self.part_number(),
+                self.version()
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// Represents an entry in a table of known parts
+pub struct KnownPart {
+    /// Name of the part
+    pub name: &'static str,
+    /// IDCODE of the part
+    pub idcode: u32,
+    /// Bits of [idcode][Self::idcode] which must match
+    pub mask: u32,
+    /// Length of the instruction register
+    pub ir_len: usize,
+}
+
+const fn part(name: &'static str, idcode: u32, mask: u32, ir_len: usize) -> KnownPart {
+    KnownPart {
+        name,
+        idcode,
+        mask,
+        ir_len,
+    }
+}
+
+/// Mask that ignores the version field
+const NOVER: u32 = 0x0fff_ffff;
+
+/// Built-in table of known parts
+pub static KNOWN_PARTS: &[KnownPart] = &[
+    // Xilinx Spartan-6
+    part("XC6SLX9", 0x0400_1093, NOVER, 6),
+    part("XC6SLX16", 0x0400_2093, NOVER, 6),
+    part("XC6SLX25", 0x0400_4093, NOVER, 6),
+    part("XC6SLX45", 0x0400_8093, NOVER, 6),
+    // Xilinx Spartan-7
+    part("XC7S6", 0x0362_2093, NOVER, 6),
+    part("XC7S15", 0x0362_0093, NOVER, 6),
+    part("XC7S25", 0x037c_4093, NOVER, 6),
+    part("XC7S50", 0x0362_f093, NOVER, 6),
+    part("XC7S75", 0x037c_8093, NOVER, 6),
+    part("XC7S100", 0x037c_7093, NOVER, 6),
+    // Xilinx Artix-7
+    part("XC7A12T", 0x037c_3093, NOVER, 6),
+    part("XC7A15T", 0x0362_e093, NOVER, 6),
+    part("XC7A25T", 0x037c_2093, NOVER, 6),
+    part("XC7A35T", 0x0362_d093, NOVER, 6),
+    part("XC7A50T", 0x0362_c093, NOVER, 6),
+    part("XC7A75T", 0x0363_2093, NOVER, 6),
+    part("XC7A100T", 0x0363_1093, NOVER, 6),
+    part("XC7A200T", 0x0363_6093, NOVER, 6),
+    // Xilinx Kintex-7
+    part("XC7K70T", 0x0364_7093, NOVER, 6),
+    part("XC7K160T", 0x0364_c093, NOVER, 6),
+    part("XC7K325T", 0x0365_1093, NOVER, 6),
+    part("XC7K410T", 0x0365_6093, NOVER, 6),
+    // Xilinx Virtex-7 (single die parts only)
+    part("XC7V585T", 0x0367_1093, NOVER, 6),
+    part("XC7VX485T", 0x0368_7093, NOVER, 6),
+    part("XC7VX690T", 0x0369_1093, NOVER, 6),
+    // Xilinx Zynq-7000 (PL TAP)
+    part("XC7Z010", 0x0372_2093, NOVER, 6),
+    part("XC7Z020", 0x0372_7093, NOVER, 6),
+    part("XC7Z030", 0x0372_c093, NOVER, 6),
+    part("XC7Z045", 0x0373_1093, NOVER, 6),
+    // Xilinx CoolRunner-II (bits 14-12 vary with the package)
+    part("XC2C32", 0x06c1_8093, 0x0fff_8fff, 8),
+    part("XC2C32A", 0x06e1_8093, 0x0fff_8fff, 8),
+    part("XC2C64", 0x06c5_8093, 0x0fff_8fff, 8),
+    part("XC2C64A", 0x06e5_8093, 0x0fff_8fff, 8),
+    part("XC2C128", 0x06d8_8093, 0x0fff_8fff, 8),
+    part("XC2C256", 0x06d4_8093, 0x0fff_8fff, 8),
+    part("XC2C384", 0x06d5_8093, 0x0fff_8fff, 8),
+    part("XC2C512", 0x06d7_8093, 0x0fff_8fff, 8),
+    // Xilinx XC9500 (5V)
+    part("XC9536", 0x0950_2093, NOVER, 8),
+    part("XC9572", 0x0950_4093, NOVER, 8),
+    part("XC95108", 0x0950_6093, NOVER, 8),
+    part("XC95144", 0x0950_8093, NOVER, 8),
+    part("XC95216", 0x0951_2093, NOVER, 8),
+    part("XC95288", 0x0951_6093, NOVER, 8),
+    // Xilinx XC9500XL
+    part("XC9536XL", 0x0960_2093, NOVER, 8),
+    part("XC9572XL", 0x0960_4093, NOVER, 8),
+    part("XC95144XL", 0x0960_8093, NOVER, 8),
+    part("XC95288XL", 0x0961_6093, NOVER, 8),
+    // Xilinx XC9500XV
+    part("XC9536XV", 0x0970_2093, NOVER, 8),
+    part("XC9572XV", 0x0970_4093, NOVER, 8),
+    part("XC95144XV", 0x0970_8093, NOVER, 8),
+    part("XC95288XV", 0x0971_6093, NOVER, 8),
+    // Lattice ECP5 (the version field distinguishes the variants)
+    part("LFE5U-12F", 0x2111_1043, 0xffff_ffff, 8),
+    part("LFE5U-25F", 0x4111_1043, 0xffff_ffff, 8),
+    part("LFE5U-45F", 0x4111_2043, 0xffff_ffff, 8),
+    part("LFE5U-85F", 0x4111_3043, 0xffff_ffff, 8),
+    part("LFE5UM-25F", 0x0111_1043, 0xffff_ffff, 8),
+    part("LFE5UM-45F", 0x0111_2043, 0xffff_ffff, 8),
+    part("LFE5UM-85F", 0x0111_3043, 0xffff_ffff, 8),
+    part("LFE5UM5G-25F", 0x8111_1043, 0xffff_ffff, 8),
+    part("LFE5UM5G-45F", 0x8111_2043, 0xffff_ffff, 8),
+    part("LFE5UM5G-85F", 0x8111_3043, 0xffff_ffff, 8),
+    // Intel/Altera MAX II
+    part("EPM240", 0x020a_10dd, NOVER, 10),
+    part("EPM570", 0x020a_20dd, NOVER, 10),
+    part("EPM1270", 0x020a_30dd, NOVER, 10),
+    part("EPM2210", 0x020a_40dd, NOVER, 10),
+    // Intel/Altera Cyclone IV E
+    part("EP4CE6/EP4CE10", 0x020f_10dd, NOVER, 10),
+    part("EP4CE15", 0x020f_20dd, NOVER, 10),
+    part("EP4CE22", 0x020f_30dd, NOVER, 10),
+    part("EP4CE30/EP4CE40", 0x020f_40dd, NOVER, 10),
+    part("EP4CE55", 0x020f_50dd, NOVER, 10),
+    part("EP4CE75", 0x020f_60dd, NOVER, 10),
+    part("EP4CE115", 0x020f_70dd, NOVER, 10),
+    // ARM
+    part("JTAG-DP", 0x0ba0_0477, NOVER, 4),
+    // Microchip (Atmel) AVR
+    part("ATmega128", 0x0970_203f, NOVER, 4),
+    // STMicroelectronics STM32 boundary scan TAPs
+    part("STM32F1 (medium density)", 0x0641_0041, NOVER, 5),
+    part("STM32F4", 0x0641_3041, NOVER, 5),
+];
+
+/// JEP106 manufacturer codes as (bank, ID, name)
+static JEP106: &[(u8, u8, &str)] = &[
+    (0, 0x01, "AMD"),
+    (0, 0x02, "AMI"),
+    (0, 0x03, "Fairchild"),
+    (0, 0x04, "Fujitsu"),
+    (0, 0x05, "GTE"),
+    (0, 0x06, "Harris"),
+    (0, 0x07, "Hitachi"),
+    (0, 0x08, "Inmos"),
+    (0, 0x09, "Intel"),
+    (0, 0x0a, "ITT"),
+    (0, 0x0b, "Intersil"),
+    (0, 0x0c, "Monolithic Memories"),
+    (0, 0x0d, "Mostek"),
+    (0, 0x0e, "Freescale (Motorola)"),
+    (0, 0x0f, "National"),
+    (0, 0x10, "NEC"),
+    (0, 0x11, "RCA"),
+    (0, 0x12, "Raytheon"),
+    (0, 0x13, "Conexant (Rockwell)"),
+    (0, 0x14, "Seeq"),
+    (0, 0x15, "NXP (Philips)"),
+    (0, 0x16, "Synertek"),
+    (0, 0x17, "Texas Instruments"),
+    (0, 0x18, "Toshiba"),
+    (0, 0x19, "Xicor"),
+    (0, 0x1a, "Zilog"),
+    (0, 0x1b, "Eurotechnique"),
+    (0, 0x1c, "Mitsubishi"),
+    (0, 0x1d, "Lucent (AT&T)"),
+    (0, 0x1e, "Exel"),
+    (0, 0x1f, "Atmel"),
+    (0, 0x20, "STMicroelectronics"),
+    (0, 0x21, "Lattice"),
+    (0, 0x22, "NCR"),
+    (0, 0x23, "Wafer Scale Integration"),
+    (0, 0x24, "IBM"),
+    (0, 0x25, "Tristar"),
+    (0, 0x26, "Visic"),
+    (0, 0x27, "International CMOS Technology"),
+    (0, 0x28, "SSSI"),
+    (0, 0x29, "Microchip"),
+    (0, 0x2a, "Ricoh"),
+    (0, 0x2b, "VLSI"),
+    (0, 0x2c, "Micron"),
+    (0, 0x2d, "SK Hynix"),
+    (0, 0x2e, "OKI Semiconductor"),
+    (0, 0x2f, "Actel"),
+    (0, 0x30, "Sharp"),
+    (0, 0x31, "Catalyst"),
+    (0, 0x32, "Panasonic"),
+    (0, 0x33, "IDT"),
+    (0, 0x34, "Cypress"),
+    (0, 0x35, "DEC"),
+    (0, 0x36, "LSI Logic"),
+    (0, 0x37, "Zarlink (Plessey)"),
+    (0, 0x38, "UTMC"),
+    (0, 0x39, "Thinking Machine"),
+    (0, 0x3a, "Thomson CSF"),
+    (0, 0x3b, "Integrated CMOS (Vertex)"),
+    (0, 0x3c, "Honeywell"),
+    (0, 0x3d, "Tektronix"),
+    (0, 0x3e, "Oracle (Sun)"),
+    (0, 0x3f, "Silicon Storage Technology"),
+    (0, 0x40, "ProMOS (Mosel Vitelic)"),
+    (0, 0x41, "Infineon (Siemens)"),
+    (0, 0x42, "Macronix"),
+    (0, 0x43, "Xerox"),
+    (0, 0x44, "Plus Logic"),
+    (0, 0x45, "Western Digital"),
+    (0, 0x46, "Elan Circuit Technology"),
+    (0, 0x47, "European Silicon Structures"),
+    (0, 0x48, "Apple"),
+    (0, 0x49, "Xilinx"),
+    (0, 0x4a, "Compaq"),
+    (0, 0x4b, "Protocol Engines"),
+    (0, 0x4c, "SCI"),
+    (0, 0x4d, "Seiko Instruments"),
+    (0, 0x4e, "Samsung"),
+    (0, 0x4f, "I3 Design System"),
+    (0, 0x50, "Klic"),
+    (0, 0x51, "Crosspoint Solutions"),
+    (0, 0x52, "Alliance Semiconductor"),
+    (0, 0x53, "Tandem"),
+    (0, 0x54, "Hewlett-Packard"),
+    (0, 0x55, "Integrated Silicon Solution"),
+    (0, 0x56, "Brooktree"),
+    (0, 0x57, "New Media"),
+    (0, 0x58, "MHS Electronic"),
+    (0, 0x59, "Performance Semiconductor"),
+    (0, 0x5a, "Winbond"),
+    (0, 0x5b, "Kawasaki Steel"),
+    (0, 0x5c, "Bright Micro"),
+    (0, 0x5d, "TECMAR"),
+    (0, 0x5e, "Exar"),
+    (0, 0x5f, "PCMCIA"),
+    (0, 0x60, "LG Semiconductor"),
+    (0, 0x61, "Northern Telecom"),
+    (0, 0x62, "Sanyo"),
+    (0, 0x63, "Array Microsystems"),
+    (0, 0x64, "Crystal Semiconductor"),
+    (0, 0x65, "Analog Devices"),
+    (0, 0x66, "PMC-Sierra"),
+    (0, 0x67, "Asparix"),
+    (0, 0x68, "Convex Computer"),
+    (0, 0x69, "Quality Semiconductor"),
+    (0, 0x6a, "Nimbus Technology"),
+    (0, 0x6b, "Transwitch"),
+    (0, 0x6c, "Micronas (ITT Intermetall)"),
+    (0, 0x6d, "Cannon"),
+    (0, 0x6e, "Altera"),
+    (0, 0x6f, "NEXCOM"),
+    (0, 0x70, "Qualcomm"),
+    (0, 0x71, "Sony"),
+    (0, 0x72, "Cray Research"),
+    (0, 0x73, "AMS (Austria Micro)"),
+    (0, 0x74, "Vitesse"),
+    (0, 0x75, "Aster Electronics"),
+    (0, 0x76, "Bay Networks (Synoptic)"),
+    (0, 0x77, "Zentrum Mikroelektronik Dresden"),
+    (0, 0x78, "TRW"),
+    (0, 0x79, "Thesys"),
+    (0, 0x7a, "Solbourne Computer"),
+    (0, 0x7b, "Allied-Signal"),
+    (0, 0x7c, "Dialog Semiconductor"),
+    (0, 0x7d, "Media Vision"),
+    (0, 0x7e, "Numonyx"),
+    (4, 0x3b, "ARM"),
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_idcode_fields() {
+        let idcode = IdCode(0x4ba0_0477);
+        assert_eq!(idcode.version(), 4);
+        assert_eq!(idcode.part_number(), 0xba00);
+        assert_eq!(idcode.manufacturer_bank(), 4);
+        assert_eq!(idcode.manufacturer_id(), 0x3b);
+        assert_eq!(idcode.manufacturer_name(), Some("ARM"));
+        assert_eq!(idcode.part().unwrap().ir_len, 4);
+        assert!(idcode.is_valid());
+
+        assert!(!IdCode(0xffff_ffff).is_valid());
+        assert!(!IdCode(0x0000_0000).is_valid());
+    }
+
+    #[test]
+    fn test_idcode_display() {
+        assert_eq!(IdCode(0x1362_d093).to_string(), "1362D093 (Xilinx XC7A35T)");
+        assert_eq!(
+            IdCode(0x1234_5093).to_string(),
+            "12345093 (Xilinx part 2345 version 1)"
+        );
+        assert_eq!(
+            IdCode(0x1234_5f01).to_string(),
+            "12345F01 (unknown manufacturer 15:00 part 2345 version 1)"
+        );
+    }
+
+    #[test]
+    fn test_idcode_user_parts() {
+        let parts = [KnownPart {
+            name: "my part",
+            idcode: 0x1234_5093,
+            mask: 0xffff_ffff,
+            ir_len: 7,
+        }];
+        assert_eq!(IdCode(0x1234_5093).part_in(&parts).unwrap().ir_len, 7);
+        assert!(IdCode(0x1234_5093).part().is_none());
+    }
+}
@@ -0,0 +1,25 @@
+use bitvec::prelude::*;
+
+/// The instruction register value for `opcode` in an IR of `len` bits, with
+/// the LSB (shifted first) in bit 0. Bits beyond the 32 bits of `opcode`
+/// are 0.
+pub(crate) fn insn(opcode: impl Into<u32>, len: usize) -> BitVec {
+    let opcode = opcode.into();
+    (0..len).map(|i| i < 32 && opcode >> i & 1 != 0).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    #[test]
+    fn test_insn() {
+        assert_eq!(insn(0xe8u8, 8), bits![0, 0, 0, 1, 0, 1, 1, 1]);
+        assert_eq!(insn(0x0bu8, 6), bits![1, 1, 0, 1, 0, 0]);
+        assert_eq!(insn(0x3c2u32, 10), bits![0, 1, 0, 0, 0, 0, 1, 1, 1, 1]);
+        let wide = insn(u32::MAX, 40);
+        assert!(wide[..32].all() && wide[32..].not_any());
+    }
+}
@@ -0,0 +1,513 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// Opcodes of the IEEE 1532 instructions used by [Isc]
+pub struct IscInstructions {
+    pub enable: BitVec,
+    pub disable: BitVec,
+    pub erase: BitVec,
+    pub program: BitVec,
+    /// If this is missing, the address is shifted in after the data by
+    /// ISC_PROGRAM and ISC_READ instead
+    pub address_shift: Option<BitVec>,
+    pub read: BitVec,
+    pub noop: BitVec,
+}
+
+impl IscInstructions {
+    /// Look up the instructions by their standard names in a BSDL file
+    pub fn from_bsdl(bsdl: &Bsdl) -> Result<Self, JTAGError> {
+        let opcode = |insn: &str| {
+            bsdl.opcode(insn).map(|x| x.to_bitvec()).ok_or_else(|| {
+                JTAGError::InvalidAction(format!("{} has no {insn} instruction", bsdl.entity))
+            })
+        };
+        Ok(Self {
+            enable: opcode("ISC_ENABLE")?,
+            disable: opcode("ISC_DISABLE")?,
+            erase: opcode("ISC_ERASE")?,
+            program: opcode("ISC_PROGRAM")?,
+            address_shift: opcode("ISC_ADDRESS_SHIFT").ok(),
+            read: opcode("ISC_READ")?,
+            noop: opcode("ISC_NOOP")?,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// How row numbers are turned into addresses
+pub enum IscAddressing {
+    Binary,
+    Gray,
+    /// Only the bit for the row is set
+    OneHot,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// How to tell that an erase or program operation has finished, by shifting
+/// in ISC_NOOP and checking the status captured in the instruction register
+pub struct IscStatus {
+    /// Captured value once the operation has finished successfully
+    pub value: BitVec,
+    /// Bits of [value][Self::value] which are checked
+    pub mask: BitVec,
+    /// Number of times to check the status before giving up
+    pub max_polls: usize,
+    /// Time to wait between checking the status
+    pub poll_ns: u64,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// Describes how a device is configured using IEEE 1532 in-system
+/// configuration (ISC) instructions
+///
+/// The configuration memory is treated as a number of rows which each have
+/// an address and are programmed and read back in one scan.
+pub struct IscDevice {
+    pub instructions: IscInstructions,
+    /// Number of bits in each address
+    pub address_len: usize,
+    pub addressing: IscAddressing,
+    /// Shift addresses MSB first instead of LSB first
+    pub address_msb_first: bool,
+    /// Number of bits in each row
+    pub row_len: usize,
+    /// Data register contents for ISC_ENABLE, if it has one
+    pub enable_data: Option<BitVec>,
+    /// Data register contents for ISC_ERASE, if it has one
+    pub erase_data: Option<BitVec>,
+    pub enable_ns: u64,
+    pub disable_ns: u64,
+    /// Minimum time to wait for an erase (before checking the status)
+    pub erase_ns: u64,
+    /// Minimum time to wait for a row to be programmed (before checking the
+    /// status)
+    pub program_ns: u64,
+    pub read_ns: u64,
+    /// If this is missing, erase and program operations are assumed to have
+    /// finished after their minimum times
+    pub status: Option<IscStatus>,
+}
+
+impl IscDevice {
+    /// Create a description with no extra data registers, no status polling
+    /// and all delays set to zero
+    pub fn new(instructions: IscInstructions, address_len: usize, row_len: usize) -> Self {
+        Self {
+            instructions,
+            address_len,
+            addressing: IscAddressing::Binary,
+            address_msb_first: false,
+            row_len,
+            enable_data: None,
+            erase_data: None,
+            enable_ns: 0,
+            disable_ns: 0,
+            erase_ns: 0,
+            program_ns: 0,
+            read_ns: 0,
+            status: None,
+        }
+    }
+
+    /// The address of the given row, in the order it is shifted in
+    pub fn address(&self, row: usize) -> BitVec {
+        let address = match self.addressing {
+            IscAddressing::Binary => row,
+            IscAddressing::Gray => row ^ (row >> 1),
+            IscAddressing::OneHot => 0,
+        };
+        let mut ret = (0..self.address_len)
+            .map(|i| match self.addressing {
+                IscAddressing::OneHot => i == row,
+                _ => i < usize::BITS as usize && address & (1 << i) != 0,
+            })
+            .collect::<BitVec>();
+        if self.address_msb_first {
+            ret.reverse();
+        }
+        ret
+    }
+}
+
+/// Configures a device using IEEE 1532 in-system configuration instructions
+/// as described by an [IscDevice]
+pub struct Isc<'a> {
+    device: &'a IscDevice,
+}
+
+impl<'a> Isc<'a> {
+    pub fn new(device: &'a IscDevice) -> Self {
+        Self { device }
+    }
+
+    /// Enter ISC mode. This is a buffered action that returns immediately
+    pub fn enable<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) {
+        adapter.shift_ir_out(&self.device.instructions.enable, false);
+        if let Some(data) = &self.device.enable_data {
+            adapter.shift_dr_out(data, false);
+        }
+        adapter.queue_action(JTAGAction::DelayNS(self.device.enable_ns));
+    }
+
+    /// Leave ISC mode, which makes the device start running the new
+    /// configuration. This is a buffered action that returns immediately
+    pub fn disable<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) {
+        adapter.shift_ir_out(&self.device.instructions.disable, false);
+        adapter.queue_action(JTAGAction::DelayNS(self.device.disable_ns));
+        let bypass = BitVec::repeat(true, self.device.instructions.noop.len());
+        adapter.shift_ir_out(&bypass, false);
+    }
+
+    /// Wait for at least `min_ns` and then for the status to show that the
+    /// operation has finished
+    fn wait<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        min_ns: u64,
+        what: &str,
+    ) -> Result<(), JTAGError> {
+        adapter.queue_action(JTAGAction::DelayNS(min_ns));
+        let Some(status) = &self.device.status else {
+            return Ok(());
+        };
+        let ir_len = self.device.instructions.noop.len();
+        if status.value.len() != ir_len || status.mask.len() != ir_len {
+            return Err(JTAGError::InvalidAction(format!(
+                "status value and mask have {} and {} bits instead of {ir_len}",
+                status.value.len(),
+                status.mask.len()
+            )));
+        }
+        for poll in 0..status.max_polls {
+            if poll != 0 {
+                adapter.queue_action(JTAGAction::DelayNS(status.poll_ns));
+            }
+            let captured = adapter.shift_ir_inout(&self.device.instructions.noop, false)?;
+            if (0..captured.len()).all(|i| !status.mask[i] || captured[i] == status.value[i]) {
+                return Ok(());
+            }
+        }
+        Err(JTAGError::VerifyFailed(format!(
+            "{what} did not finish after {} status checks",
+            status.max_polls
+        )))
+    }
+
+    // Select a row for ISC_PROGRAM or ISC_READ, returning the data register
+    // contents needed to finish selecting it
+    fn select<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        insn: &BitSlice,
+        row: usize,
+        data: &BitSlice,
+    ) -> BitVec {
+        let mut dr = data.to_bitvec();
+        match &self.device.instructions.address_shift {
+            Some(address_shift) => {
+                adapter.shift_ir_out(address_shift, false);
+                adapter.shift_dr_out(&self.device.address(row), false);
+                adapter.shift_ir_out(insn, false);
+            }
+            None => {
+                adapter.shift_ir_out(insn, false);
+                dr.extend_from_bitslice(&self.device.address(row));
+            }
+        }
+        dr
+    }
+
+    /// Erase the whole device while in ISC mode
+    pub fn erase<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<(), JTAGError> {
+        adapter.shift_ir_out(&self.device.instructions.erase, false);
+        if let Some(data) = &self.device.erase_data {
+            adapter.shift_dr_out(data, false);
+        }
+        self.wait(adapter, self.device.erase_ns, "erase")
+    }
+
+    /// Program one row while in ISC mode
+    pub fn program_row<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        row: usize,
+        data: &BitSlice,
+    ) -> Result<(), JTAGError> {
+        if data.len() != self.device.row_len {
+            return Err(JTAGError::InvalidAction(format!(
+                "row has {} bits instead of {}",
+                data.len(),
+                self.device.row_len
+            )));
+        }
+        let dr = self.select(adapter, &self.device.instructions.program, row, data);
+        adapter.shift_dr_out(&dr, false);
+        self.wait(adapter, self.device.program_ns, "programming")
+    }
+
+    /// Read one row while in ISC mode
+    pub fn read_row<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        row: usize,
+    ) -> Result<BitVec, JTAGError> {
+        let zeros = BitVec::repeat(false, self.device.row_len);
+        let dr = self.select(adapter, &self.device.instructions.read, row, &zeros);
+        // without ISC_ADDRESS_SHIFT, the address has to be shifted in before
+        // the row can be captured
+        if self.device.instructions.address_shift.is_none() {
+            adapter.shift_dr_out(&dr, false);
+        }
+        adapter.queue_action(JTAGAction::DelayNS(self.device.read_ns));
+        let out = adapter.shift_dr_inout(&dr, false)?;
+        Ok(out[..self.device.row_len].to_bitvec())
+    }
+
+    fn verify_rows<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        rows: &[BitVec],
+    ) -> Result<(), JTAGError> {
+        for (i, row) in rows.iter().enumerate() {
+            if row.len() != self.device.row_len {
+                return Err(JTAGError::InvalidAction(format!(
+                    "row {i} has {} bits instead of {}",
+                    row.len(),
+                    self.device.row_len
+                )));
+            }
+            let actual = self.read_row(adapter, i)?;
+            if let Some(bit) = (0..row.len()).find(|&bit| actual[bit] != row[bit]) {
+                return Err(JTAGError::VerifyFailed(format!(
+                    "row {i} bit {bit} is {}",
+                    actual[bit] as u8
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the first rows of the device contain `rows`
+    pub fn verify<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        rows: &[BitVec],
+    ) -> Result<(), JTAGError> {
+        self.enable(adapter);
+        let ret = self.verify_rows(adapter, rows);
+        self.disable(adapter);
+        adapter.flush()?;
+        ret
+    }
+
+    /// Read the first `count` rows of the device
+    pub fn read<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        count: usize,
+    ) -> Result<Vec<BitVec>, JTAGError> {
+        self.enable(adapter);
+        let ret = (0..count).map(|i| self.read_row(adapter, i)).collect();
+        self.disable(adapter);
+        adapter.flush()?;
+        ret
+    }
+
+    /// Erase the device, then program and verify `rows` starting from the
+    /// first row
+    pub fn program<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        rows: &[BitVec],
+    ) -> Result<(), JTAGError> {
+        self.enable(adapter);
+        let ret = self.erase(adapter).and_then(|_| {
+            for (i, row) in rows.iter().enumerate() {
+                self.program_row(adapter, i, row)?;
+            }
+            self.verify_rows(adapter, rows)
+        });
+        self.disable(adapter);
+        adapter.flush()?;
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    // A 1532 device with a 4-bit IR, 4 rows of 8 bits and one-hot addresses.
+    // Erasing and programming are busy for a few status checks.
+    struct SimIsc {
+        rows: Vec<BitVec>,
+        address: usize,
+        enabled: bool,
+        busy: usize,
+        address_shift: bool,
+    }
+
+    impl drivers::SimulatedDevice for SimIsc {
+        fn ir_len(&self) -> usize {
+            4
+        }
+        fn reset_ir(&self) -> BitVec {
+            bitvec![1, 1, 1, 1]
+        }
+        fn capture_ir(&self) -> BitVec {
+            // bit 2 is set while busy
+            let mut ret = bitvec![1, 0, 0, 0];
+            ret.set(2, self.busy != 0);
+            ret
+        }
+        fn update_ir(&mut self, ir: &BitSlice) {
+            match ir.load_le::<u8>() {
+                0x1 => self.enabled = true,
+                0x2 => self.enabled = false,
+                0x3 if self.enabled => {
+                    self.rows.iter_mut().for_each(|x| x.fill(true));
+                    self.busy = 3;
+                }
+                0x7 => self.busy = self.busy.saturating_sub(1),
+                _ => {}
+            }
+        }
+        fn capture_dr(&mut self, ir: &BitSlice) -> BitVec {
+            let len = if self.address_shift { 8 } else { 12 };
+            match ir.load_le::<u8>() {
+                0x4 => BitVec::repeat(false, len),
+                0x9 if self.enabled => {
+                    let mut ret = self.rows[self.address].clone();
+                    ret.resize(len, false);
+                    ret
+                }
+                0x5 => bitvec![0; 4],
+                _ => bitvec![0],
+            }
+        }
+        fn update_dr(&mut self, ir: &BitSlice, dr: &BitSlice) {
+            let address = |x: &BitSlice| x.first_one().unwrap();
+            match ir.load_le::<u8>() {
+                0x5 => self.address = address(dr),
+                0x4 if self.enabled => {
+                    if !self.address_shift {
+                        self.address = address(&dr[8..]);
+                    }
+                    let row = &mut self.rows[self.address];
+                    *row &= &dr[..8];
+                    self.busy = 2;
+                }
+                0x9 if !self.address_shift => self.address = address(&dr[8..]),
+                _ => {}
+            }
+        }
+    }
+
+    fn test_device(address_shift: bool) -> IscDevice {
+        let insn = |x: u8| x.view_bits::<Lsb0>()[..4].iter().by_vals().collect();
+        let mut device = IscDevice::new(
+            IscInstructions {
+                enable: insn(1),
+                disable: insn(2),
+                erase: insn(3),
+                program: insn(4),
+                address_shift: address_shift.then(|| insn(5)),
+                read: insn(9),
+                noop: insn(7),
+            },
+            4,
+            8,
+        );
+        device.addressing = IscAddressing::OneHot;
+        device.status = Some(IscStatus {
+            value: bitvec![1, 0, 0, 0],
+            mask: bitvec![1, 1, 1, 1],
+            max_polls: 4,
+            poll_ns: 1000,
+        });
+        device
+    }
+
+    #[test]
+    fn test_isc_program() {
+        let rows = vec![
+            bitvec![1, 0, 1, 0, 1, 0, 1, 0],
+            bitvec![0, 0, 0, 0, 1, 1, 1, 1],
+            bitvec![1, 1, 1, 1, 1, 1, 1, 0],
+        ];
+        for address_shift in [true, false] {
+            let device = test_device(address_shift);
+            let isc = Isc::new(&device);
+            let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(SimIsc {
+                rows: vec![bitvec![0; 8]; 4],
+                address: 0,
+                enabled: false,
+                busy: 0,
+                address_shift,
+            })]);
+
+            isc.program(&mut adapter, &rows).unwrap();
+            isc.verify(&mut adapter, &rows).unwrap();
+            let read = isc.read(&mut adapter, 4).unwrap();
+            assert_eq!(read[..3], rows);
+            assert_eq!(read[3], bits![1; 8]);
+            assert!(matches!(
+                isc.verify(&mut adapter, &[bitvec![0; 8]]),
+                Err(JTAGError::VerifyFailed(..))
+            ));
+            assert!(matches!(
+                isc.verify(&mut adapter, &[bitvec![0; 9]]),
+                Err(JTAGError::InvalidAction(..))
+            ));
+        }
+
+        // never finishes erasing
+        let mut device = test_device(true);
+        device.status.as_mut().unwrap().value = bitvec![0; 4];
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(SimIsc {
+            rows: vec![bitvec![0; 8]; 4],
+            address: 0,
+            enabled: false,
+            busy: 0,
+            address_shift: true,
+        })]);
+        assert!(Isc::new(&device).program(&mut adapter, &rows).is_err());
+
+        // status mask does not match the IR
+        device.status.as_mut().unwrap().mask = bitvec![1; 8];
+        assert!(matches!(
+            Isc::new(&device).program(&mut adapter, &rows),
+            Err(JTAGError::InvalidAction(..))
+        ));
+    }
+
+    #[test]
+    fn test_isc_description() {
+        let src = crate::bsdl::tests::TEST_BSDL.replace(
+            r#""IDCODE  (0110),""#,
+            r#""IDCODE  (0110)," & "ISC_ENABLE (0001)," & "ISC_DISABLE (0010)," &
+            "ISC_ERASE (0011)," & "ISC_PROGRAM (0100)," & "ISC_READ (1001)," &
+            "ISC_NOOP (0111),""#,
+        );
+        let bsdl = Bsdl::parse(&src).unwrap();
+        let instructions = IscInstructions::from_bsdl(&bsdl).unwrap();
+        assert_eq!(instructions, test_device(false).instructions);
+        assert!(
+            IscInstructions::from_bsdl(&Bsdl::parse(crate::bsdl::tests::TEST_BSDL).unwrap())
+                .is_err()
+        );
+
+        let mut device = IscDevice::new(instructions, 4, 8);
+        assert_eq!(device.address(6), bits![0, 1, 1, 0]);
+        device.addressing = IscAddressing::Gray;
+        device.address_msb_first = true;
+        assert_eq!(device.address(6), bits![0, 1, 0, 1]);
+        device.addressing = IscAddressing::OneHot;
+        assert_eq!(device.address(2), bits![0, 1, 0, 0]);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+const STX: u8 = 0x02;
+const ETX: u8 = 0x03;
+/// Largest QF accepted, far more than any device has
+const MAX_FUSES: usize = 1 << 24;
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash, Default)]
+/// A JEDEC (JESD3) fuse map file
+///
+/// Only the fuse map related fields (QF, QP, F, L, C and N) are used. Other
+/// fields (e.g. test vectors) are ignored when parsing and are not written.
+pub struct Jed {
+    /// Design specification, i.e. the text between STX and the first field
+    pub header: String,
+    /// Fuse 0 is bit 0
+    pub fuses: BitVec,
+    /// Number of pins (QP)
+    pub pins: Option<usize>,
+    /// Text of each note (N) field
+    pub notes: Vec<String>,
+}
+
+fn parse_err(msg: impl Into<String>) -> JTAGError {
+    ParseError::new("JED", msg).into()
+}
+
+fn parse_number(field: &str, s: &str) -> Result<usize, JTAGError> {
+    s.trim()
+        .parse()
+        .map_err(|_| parse_err(format!("invalid number in {field}")))
+}
+
+fn parse_checksum(field: &str, s: &str) -> Result<u16, JTAGError> {
+    let s = s.trim();
+    if s.len() != 4 {
+        return Err(parse_err(format!("invalid checksum in {field}")));
+    }
+    u16::from_str_radix(s, 16).map_err(|_| parse_err(format!("invalid checksum in {field}")))
+}
+
+impl Jed {
+    pub fn new(fuses: BitVec) -> Self {
+        Self {
+            fuses,
+            ..Default::default()
+        }
+    }
+
+    /// Parse a JED file, checking the fuse checksum (C) and the transmission
+    /// checksum if they are present. A transmission checksum of 0000 is
+    /// accepted without checking as allowed by JESD3.
+    pub fn parse(data: &[u8]) -> Result<Self, JTAGError> {
+        // Be lenient about a missing STX, which some tools leave out
+        let start = data.iter().position(|&x| x == STX);
+        let end = data
+            .iter()
+            .position(|&x| x == ETX)
+            .ok_or_else(|| parse_err("missing ETX"))?;
+        if start.is_some_and(|start| start > end) {
+            return Err(parse_err("ETX before STX"));
+        }
+
+        if let Some(start) = start {
+            let checksum = data
+                .get(end + 1..end + 5)
+                .ok_or_else(|| parse_err("missing transmission checksum"))?;
+            let checksum = parse_checksum(
+                "transmission checksum",
+                std::str::from_utf8(checksum).unwrap_or(""),
+            )?;
+            let actual = data[start..=end]
+                .iter()
+                .fold(0u16, |acc, &x| acc.wrapping_add(x as u16));
+            if checksum != 0 && checksum != actual {
+                return Err(parse_err(format!(
+                    "transmission checksum is {checksum:04X} but should be {actual:04X}"
+                )));
+            }
+        }
+
+        let text = &data[start.map_or(0, |x| x + 1)..end];
+        let text = std::str::from_utf8(text).map_err(|_| parse_err("invalid UTF-8"))?;
+        let mut fields = text.split('*');
+        let mut ret = Self {
+            header: fields.next().unwrap().trim().to_string(),
+            ..Default::default()
+        };
+
+        let mut fuse_count = None;
+        let mut default_fuse = None;
+        let mut fuse_checksum = None;
+        let mut list = Vec::new();
+        for field in fields {
+            let field = field.trim_start();
+            let Some(id) = field.chars().next() else {
+                continue;
+            };
+            let value = &field[id.len_utf8()..];
+            match id {
+                'Q' => match value.chars().next() {
+                    Some('F') => fuse_count = Some(parse_number("QF", &value[1..])?),
+                    Some('P') => ret.pins = Some(parse_number("QP", &value[1..])?),
+                    _ => {}
+                },
+                'F' => {
+                    default_fuse = Some(match value.trim() {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(parse_err("invalid default fuse state")),
+                    })
+                }
+                'L' => {
+                    let value = value.trim_start();
+                    let split = value
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(value.len());
+                    let address = parse_number("L", &value[..split])?;
+                    let mut bits = BitVec::new();
+                    for c in value[split..].chars().filter(|c| !c.is_whitespace()) {
+                        match c {
+                            '0' => bits.push(false),
+                            '1' => bits.push(true),
+                            _ => {
+                                return Err(parse_err(format!("invalid fuse {c:?} in L{address}")))
+                            }
+                        }
+                    }
+                    list.push((address, bits));
+                }
+                'C' => fuse_checksum = Some(parse_checksum("C", value)?),
+                'N' => ret.notes.push(value.trim().to_string()),
+                _ => {}
+            }
+        }
+
+        let fuse_count = match fuse_count {
+            Some(x) => x,
+            None if list.is_empty() => 0,
+            None => return Err(parse_err("missing QF")),
+        };
+        if fuse_count > MAX_FUSES {
+            return Err(parse_err(format!("QF{fuse_count} is too large")));
+        }
+        ret.fuses = BitVec::repeat(default_fuse.unwrap_or(false), fuse_count);
+        for (address, bits) in list {
+            if address
+                .checked_add(bits.len())
+                .is_none_or(|end| end > fuse_count)
+            {
+                return Err(parse_err(format!(
+                    "L{address} is past the end of the {fuse_count} fuses"
+                )));
+            }
+            ret.fuses[address..address + bits.len()].copy_from_bitslice(&bits);
+        }
+
+        if let Some(checksum) = fuse_checksum {
+            let actual = ret.fuse_checksum();
+            if checksum != actual {
+                return Err(parse_err(format!(
+                    "fuse checksum is {checksum:04X} but should be {actual:04X}"
+                )));
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Sum of the fuses taken as bytes (with fuse 0 being the LSB of the
+    /// first byte), as stored in the C field
+    pub fn fuse_checksum(&self) -> u16 {
+        self.fuses
+            .chunks(8)
+            .fold(0u16, |acc, x| acc.wrapping_add(x.load_le::<u8>() as u16))
+    }
+
+    /// The device name given by a Xilinx style `N DEVICE <name>` note
+    pub fn device(&self) -> Option<&str> {
+        self.notes
+            .iter()
+            .find_map(|x| x.strip_prefix("DEVICE"))
+            .map(|x| x.trim())
+    }
+
+    /// Write a JED file including both checksums, with `fuses_per_line`
+    /// fuses in each L field
+    pub fn to_bytes(&self, fuses_per_line: usize) -> Vec<u8> {
+        let mut text = String::new();
+        text.push(STX as char);
+        text.push_str(&self.header);
+        text.push_str("*\n");
+        if let Some(pins) = self.pins {
+            text.push_str(&format!("QP{pins}*\n"));
+        }
+        text.push_str(&format!("QF{}*\n", self.fuses.len()));
+        text.push_str("F0*\n");
+        for note in &self.notes {
+            text.push_str(&format!("N {note}*\n"));
+        }
+
+        let width = self.fuses.len().to_string().len();
+        for (i, chunk) in self.fuses.chunks(fuses_per_line.max(1)).enumerate() {
+            // all zero lines are covered by F0
+            if chunk.not_any() {
+                continue;
+            }
+            let bits = chunk
+                .iter()
+                .by_vals()
+                .map(|x| if x { '1' } else { '0' })
+                .collect::<String>();
+            text.push_str(&format!("L{:0width$} {bits}*\n", i * fuses_per_line.max(1)));
+        }
+        text.push_str(&format!("C{:04X}*\n", self.fuse_checksum()));
+        text.push(ETX as char);
+
+        let mut ret = text.into_bytes();
+        let checksum = ret.iter().fold(0u16, |acc, &x| acc.wrapping_add(x as u16));
+        ret.extend_from_slice(format!("{checksum:04X}").as_bytes());
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    const TEST_JED: &[u8] = b"\x02test design
+*
+QP8*QF20*
+F0*
+N DEVICE TEST-1*
+L0000 1010 1100*
+L0016
+1111*
+C0044*
+\x030000";
+
+    #[test]
+    fn test_jed_parse() {
+        let jed = Jed::parse(TEST_JED).unwrap();
+        assert_eq!(jed.header, "test design");
+        assert_eq!(jed.pins, Some(8));
+        assert_eq!(jed.device(), Some("TEST-1"));
+        assert_eq!(
+            jed.fuses,
+            bits![1, 0, 1, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1]
+        );
+
+        let mut bad = TEST_JED.to_vec();
+        let i = bad.windows(5).position(|x| x == b"C0044").unwrap();
+        bad[i + 4] = b'5';
+        assert!(Jed::parse(&bad).is_err());
+
+        // past the end of the fuses
+        let mut bad = TEST_JED.to_vec();
+        let i = bad.windows(4).position(|x| x == b"0016").unwrap();
+        bad[i + 3] = b'7';
+        assert!(Jed::parse(&bad).is_err());
+        assert!(Jed::parse(format!("\x02x*QF4*L{}1*\x030000", usize::MAX).as_bytes()).is_err());
+        assert!(Jed::parse(format!("\x02x*QF{}*\x030000", usize::MAX).as_bytes()).is_err());
+
+        // unknown fields starting with a multibyte character are skipped
+        let jed = Jed::parse("\x02x*\u{e9}t\u{e9}*QF4*F1*\x030000".as_bytes()).unwrap();
+        assert_eq!(jed.fuses, bits![1; 4]);
+    }
+
+    #[test]
+    fn test_jed_write() {
+        let mut jed = Jed::new(bitvec![0; 100]);
+        jed.header = "written".to_string();
+        jed.notes.push("DEVICE TEST-2".to_string());
+        jed.fuses.set(3, true);
+        jed.fuses.set(99, true);
+
+        let data = jed.to_bytes(16);
+        assert!(data.starts_with(b"\x02written*\nQF100*\nF0*\nN DEVICE TEST-2*\nL000 0001"));
+        assert_eq!(Jed::parse(&data).unwrap(), jed);
+
+        let mut bad = data.clone();
+        let len = bad.len();
+        bad[len - 1] = if bad[len - 1] == b'0' { b'1' } else { b'0' };
+        assert!(Jed::parse(&bad).is_err());
+    }
+}
@@ -1,3 +1,6 @@
+mod error;
+pub use error::{JTAGError, ParseError, ParseLocation};
+
 mod fsm;
 pub use fsm::JTAGState;
 
@@ -13,6 +16,80 @@ pub use chunkshifter::{ChunkShifterJTAGAdapter, ChunkShifterJTAGAdapterState};
 mod statetracking;
 pub use statetracking::StateTrackingJTAGAdapter;
 
+mod scanchain;
+pub use scanchain::{ScanChain, ScanChainDevice};
+
+mod insn;
+pub(crate) use insn::insn;
+
+mod idcode;
+pub use idcode::{IdCode, KnownPart, KNOWN_PARTS};
+
+mod bsdl;
+pub use bsdl::{
+    BoundaryCell, BoundaryCellFunction, Bsdl, BsdlInstruction, BsdlPort, BsdlPortDirection,
+    IdCodePattern,
+};
+
+mod boundaryscan;
+pub use boundaryscan::BoundaryScan;
+
+mod discovery;
+pub use discovery::{
+    count_chain_devices, detect_ir_lengths, discover_chain, DiscoveredDevice, MAX_CHAIN_DEVICES,
+    MAX_CHAIN_IR_BITS,
+};
+
+mod svf;
+pub use svf::{Svf, SvfCommand, SvfPlayer, SvfRunClock, SvfRunTest, SvfScan, SvfTrstMode};
+
+mod svfrecorder;
+pub use svfrecorder::SvfRecorder;
+
+mod xsvf;
+pub use xsvf::{Xsvf, XsvfCommand, XsvfPlayer};
+
+mod stapl;
+pub use stapl::{Stapl, StaplAction, StaplActionStep, StaplPlayer};
+
+mod jed;
+pub use jed::Jed;
+
+mod cpld;
+pub use cpld::CpldDevice;
+
+mod coolrunner2;
+pub use coolrunner2::{CoolRunner2, Xc2cDevice, Xc2cFuseMap, Xc2cMapCell};
+
+mod xc2cmap;
+
+mod xc9500xl;
+pub use xc9500xl::{check_xc9500_5v, Xc9500Xl, Xc9500XlDevice};
+
+mod isc;
+pub use isc::{Isc, IscAddressing, IscDevice, IscInstructions, IscStatus};
+
+mod series7;
+pub use series7::{Series7, Series7IrStatus};
+
+mod series7regs;
+pub use series7regs::{
+    Series7BootStatus, Series7BootSts, Series7Cor0, Series7Ctl0, Series7Register, Series7Registers,
+    Series7Stat, Series7WbStar,
+};
+
+mod xadc;
+pub use xadc::{Xadc, XadcKind, XadcReadings};
+
+mod bscan;
+pub use bscan::{Bscan, BscanUser};
+
+mod spiflash;
+pub use spiflash::{JtagSpi, SpiEraseType, SpiFlash, SpiFlashInfo};
+
+mod bitfile;
+pub use bitfile::BitFile;
+
 #[cfg(test)]
 mod tests;
 
@@ -81,7 +81,7 @@ pub enum JTAGOutput {
     ActualClkSpeed(u64),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 /// State required to be stored by a [JTAGAdapter]
 pub struct JTAGAdapterState {
     queued_actions: Vec<JTAGAction>,
@@ -99,14 +99,16 @@ impl JTAGAdapterState {
 pub trait JTAGAdapter: AsMut<JTAGAdapterState> {
     /// Execute all of the passed-in JTAG actions immediately without buffering
     /// and return their results
-    fn execute_actions(&mut self, actions: &[JTAGAction]) -> Vec<JTAGOutput>;
+    fn execute_actions(&mut self, actions: &[JTAGAction]) -> Result<Vec<JTAGOutput>, JTAGError>;
 
     /// Execute all of the currently buffered JTAG actions and return their
     /// results
-    fn flush(&mut self) -> Vec<JTAGOutput> {
+    ///
+    /// If an error occurs, the remaining buffered actions are discarded.
+    fn flush(&mut self) -> Result<Vec<JTAGOutput>, JTAGError> {
         let state: &mut JTAGAdapterState = self.as_mut();
-        let mut actions = state.queued_actions.split_off(0);
-        self.execute_actions(&mut actions)
+        let actions = state.queued_actions.split_off(0);
+        self.execute_actions(&actions)
     }
     /// Add the given action to the buffered action queue and return
     /// immediately
@@ -239,7 +241,7 @@ pub trait JTAGAdapter: AsMut<JTAGAdapterState> {
     ///
     /// This is a blocking action that will additionally flush all pending
     /// buffered actions.
-    fn shift_bits_inout(&mut self, bits: &BitSlice, tms_exit: bool) -> BitVec {
+    fn shift_bits_inout(&mut self, bits: &BitSlice, tms_exit: bool) -> Result<BitVec, JTAGError> {
         let state: &mut JTAGAdapterState = self.as_mut();
         state.queued_actions.push(JTAGAction::ShiftBits {
             bits_tdi: bits.to_owned(),
@@ -247,12 +249,13 @@ pub trait JTAGAdapter: AsMut<JTAGAdapterState> {
             tms_exit,
         });
 
-        let mut ret = self.flush();
-        let retlen = ret.len();
-        if let JTAGOutput::CapturedBits(out) = &mut ret[retlen - 1] {
-            out.split_off(0)
+        let mut ret = self.flush()?;
+        if let Some(JTAGOutput::CapturedBits(out)) = ret.last_mut() {
+            Ok(out.split_off(0))
         } else {
-            unreachable!()
+            Err(JTAGError::ProtocolViolation(
+                "adapter did not return captured bits".to_string(),
+            ))
         }
     }
 
@@ -276,7 +279,7 @@ pub trait JTAGAdapter: AsMut<JTAGAdapterState> {
     ///
     /// This is a blocking action that will additionally flush all pending
     /// buffered actions.
-    fn shift_ir_inout(&mut self, ir: &BitSlice, pause: bool) -> BitVec {
+    fn shift_ir_inout(&mut self, ir: &BitSlice, pause: bool) -> Result<BitVec, JTAGError> {
         let state: &mut JTAGAdapterState = self.as_mut();
         state.queued_actions.push(JTAGAction::ShiftIR {
             ir: ir.to_owned(),
@@ -284,12 +287,13 @@ pub trait JTAGAdapter: AsMut<JTAGAdapterState> {
             pause,
         });
 
-        let mut ret = self.flush();
-        let retlen = ret.len();
-        if let JTAGOutput::CapturedBits(out) = &mut ret[retlen - 1] {
-            out.split_off(0)
+        let mut ret = self.flush()?;
+        if let Some(JTAGOutput::CapturedBits(out)) = ret.last_mut() {
+            Ok(out.split_off(0))
         } else {
-            unreachable!()
+            Err(JTAGError::ProtocolViolation(
+                "adapter did not return captured bits".to_string(),
+            ))
         }
     }
     /// Take the TAP from its current state to Shift-DR, then shift in the
@@ -312,7 +316,7 @@ pub trait JTAGAdapter: AsMut<JTAGAdapterState> {
     ///
     /// This is a blocking action that will additionally flush all pending
     /// buffered actions.
-    fn shift_dr_inout(&mut self, dr: &BitSlice, pause: bool) -> BitVec {
+    fn shift_dr_inout(&mut self, dr: &BitSlice, pause: bool) -> Result<BitVec, JTAGError> {
         let state: &mut JTAGAdapterState = self.as_mut();
         state.queued_actions.push(JTAGAction::ShiftDR {
             dr: dr.to_owned(),
@@ -320,12 +324,13 @@ pub trait JTAGAdapter: AsMut<JTAGAdapterState> {
             pause,
         });
 
-        let mut ret = self.flush();
-        let retlen = ret.len();
-        if let JTAGOutput::CapturedBits(out) = &mut ret[retlen - 1] {
-            out.split_off(0)
+        let mut ret = self.flush()?;
+        if let Some(JTAGOutput::CapturedBits(out)) = ret.last_mut() {
+            Ok(out.split_off(0))
         } else {
-            unreachable!()
+            Err(JTAGError::ProtocolViolation(
+                "adapter did not return captured bits".to_string(),
+            ))
         }
     }
 
@@ -344,19 +349,20 @@ pub trait JTAGAdapter: AsMut<JTAGAdapterState> {
     ///
     /// This is a blocking action that will additionally flush all pending
     /// buffered actions.
-    fn read_reg(&mut self, ir: &BitSlice, drlen: usize) -> BitVec {
+    fn read_reg(&mut self, ir: &BitSlice, drlen: usize) -> Result<BitVec, JTAGError> {
         let state: &mut JTAGAdapterState = self.as_mut();
         state.queued_actions.push(JTAGAction::ReadReg {
             ir: ir.to_owned(),
             drlen,
         });
 
-        let mut ret = self.flush();
-        let retlen = ret.len();
-        if let JTAGOutput::CapturedBits(out) = &mut ret[retlen - 1] {
-            out.split_off(0)
+        let mut ret = self.flush()?;
+        if let Some(JTAGOutput::CapturedBits(out)) = ret.last_mut() {
+            Ok(out.split_off(0))
         } else {
-            unreachable!()
+            Err(JTAGError::ProtocolViolation(
+                "adapter did not return captured bits".to_string(),
+            ))
         }
     }
     /// Set the current IR to `ir` if it is not already,
@@ -403,7 +409,10 @@ mod tests {
     }
 
     impl JTAGAdapter for TestNativeJTAGAdapter {
-        fn execute_actions(&mut self, actions: &[JTAGAction]) -> Vec<JTAGOutput> {
+        fn execute_actions(
+            &mut self,
+            actions: &[JTAGAction],
+        ) -> Result<Vec<JTAGOutput>, JTAGError> {
             assert_eq!(actions, self.test_actions);
             let mut ret = Vec::new();
 
@@ -422,7 +431,7 @@ mod tests {
                 });
             }
 
-            ret
+            Ok(ret)
         }
     }
 
@@ -432,7 +441,7 @@ mod tests {
 
         testadapter.test_actions = vec![JTAGAction::ResetToTLR];
         testadapter.reset_to_tlr();
-        testadapter.flush();
+        testadapter.flush().unwrap();
 
         // test buffering
         testadapter.test_actions = vec![];
@@ -455,6 +464,21 @@ mod tests {
                 tms_exit: true,
             },
         ];
-        testadapter.shift_bits_inout(bits![0, 1, 0, 1], true);
+        testadapter
+            .shift_bits_inout(bits![0, 1, 0, 1], true)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_native_adapter_missing_capture() {
+        let mut testadapter = TestNativeJTAGAdapter::new(&[JTAGAction::ShiftIR {
+            ir: bitvec![1, 0],
+            capture: true,
+            pause: false,
+        }]);
+
+        // this test adapter only returns data for ShiftBits
+        let ret = testadapter.shift_ir_inout(bits![1, 0], false);
+        assert!(matches!(ret, Err(JTAGError::ProtocolViolation(..))));
     }
 }
@@ -0,0 +1,243 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+/// A JTAG adapter connected to a scan chain containing multiple devices
+///
+/// Devices are listed in the order that their data appears on TDO, i.e.
+/// the first device is the one closest to TDO.
+pub struct ScanChain<T: JTAGAdapter> {
+    adapter: T,
+    ir_lens: Vec<usize>,
+}
+impl<T: JTAGAdapter> ScanChain<T> {
+    /// Describe the scan chain connected to `adapter` using the IR length
+    /// of each device
+    pub fn new(adapter: T, ir_lens: &[usize]) -> Self {
+        Self {
+            adapter,
+            ir_lens: ir_lens.to_vec(),
+        }
+    }
+
+    /// Number of devices on the chain
+    pub fn len(&self) -> usize {
+        self.ir_lens.len()
+    }
+    /// Returns `true` if there are no devices on the chain
+    pub fn is_empty(&self) -> bool {
+        self.ir_lens.is_empty()
+    }
+    /// IR lengths of all devices on the chain
+    pub fn ir_lens(&self) -> &[usize] {
+        &self.ir_lens
+    }
+
+    /// Access the underlying adapter in order to operate on the entire chain
+    pub fn adapter(&mut self) -> &mut T {
+        &mut self.adapter
+    }
+    /// Return the underlying adapter
+    pub fn into_inner(self) -> T {
+        self.adapter
+    }
+
+    /// Get a [JTAGAdapter] which operates on only the device at `index`.
+    ///
+    /// All other devices are placed into BYPASS whenever an IR is shifted
+    /// via the returned handle. DR operations assume that all other devices
+    /// are already in BYPASS.
+    pub fn device(&mut self, index: usize) -> ScanChainDevice<'_, T> {
+        assert!(index < self.ir_lens.len());
+        ScanChainDevice {
+            jtag_state: JTAGAdapterState::new(),
+            adapter: &mut self.adapter,
+            ir_lens: &self.ir_lens,
+            index,
+        }
+    }
+}
+
+/// Handle to a single device on a [ScanChain]
+///
+/// IR/DR operations performed through this handle are automatically padded
+/// to cover the rest of the chain (i.e. the SVF HIR/TIR/HDR/TDR concept).
+/// Raw [JTAGAction::ShiftBits] and state transitions are passed through
+/// unmodified.
+///
+/// Any actions buffered on the underlying adapter are flushed before actions
+/// from this handle are executed. Buffered actions that have not been flushed
+/// are lost when the handle is dropped.
+pub struct ScanChainDevice<'a, T: JTAGAdapter> {
+    jtag_state: JTAGAdapterState,
+    adapter: &'a mut T,
+    ir_lens: &'a [usize],
+    index: usize,
+}
+impl<'a, T: JTAGAdapter> AsMut<JTAGAdapterState> for ScanChainDevice<'a, T> {
+    fn as_mut(&mut self) -> &mut JTAGAdapterState {
+        &mut self.jtag_state
+    }
+}
+impl<'a, T: JTAGAdapter> ScanChainDevice<'a, T> {
+    /// Index of this device on the chain
+    pub fn index(&self) -> usize {
+        self.index
+    }
+    /// IR length of this device
+    pub fn ir_len(&self) -> usize {
+        self.ir_lens[self.index]
+    }
+
+    fn ir_offset(&self) -> usize {
+        self.ir_lens[..self.index].iter().sum()
+    }
+
+    fn pad_ir(&self, ir: &BitSlice) -> Result<BitVec, JTAGError> {
+        if ir.len() != self.ir_len() {
+            return Err(JTAGError::InvalidAction(format!(
+                "IR for device {} must be {} bits, got {}",
+                self.index,
+                self.ir_len(),
+                ir.len()
+            )));
+        }
+
+        let total_len: usize = self.ir_lens.iter().sum();
+        let offset = self.ir_offset();
+        let mut ret = BitVec::repeat(true, total_len);
+        ret[offset..offset + ir.len()].clone_from_bitslice(ir);
+        Ok(ret)
+    }
+
+    fn pad_dr(&self, dr: &BitSlice) -> BitVec {
+        let mut ret = BitVec::repeat(false, self.ir_lens.len() - 1 + dr.len());
+        ret[self.index..self.index + dr.len()].clone_from_bitslice(dr);
+        ret
+    }
+}
+
+impl<'a, T: JTAGAdapter> JTAGAdapter for ScanChainDevice<'a, T> {
+    fn execute_actions(&mut self, actions: &[JTAGAction]) -> Result<Vec<JTAGOutput>, JTAGError> {
+        let mut padded_actions = Vec::with_capacity(actions.len());
+        // (start, len) of the interesting part of any captured data
+        let mut captured_ranges = Vec::with_capacity(actions.len());
+
+        for action in actions {
+            match action {
+                JTAGAction::ShiftIR { ir, capture, pause } => {
+                    padded_actions.push(JTAGAction::ShiftIR {
+                        ir: self.pad_ir(ir)?,
+                        capture: *capture,
+                        pause: *pause,
+                    });
+                    captured_ranges.push(Some((self.ir_offset(), ir.len())));
+                }
+                JTAGAction::ShiftDR { dr, capture, pause } => {
+                    padded_actions.push(JTAGAction::ShiftDR {
+                        dr: self.pad_dr(dr),
+                        capture: *capture,
+                        pause: *pause,
+                    });
+                    captured_ranges.push(Some((self.index, dr.len())));
+                }
+                JTAGAction::SetIR(ir) => {
+                    padded_actions.push(JTAGAction::SetIR(self.pad_ir(ir)?));
+                    captured_ranges.push(None);
+                }
+                JTAGAction::ReadReg { ir, drlen } => {
+                    padded_actions.push(JTAGAction::ReadReg {
+                        ir: self.pad_ir(ir)?,
+                        drlen: self.ir_lens.len() - 1 + drlen,
+                    });
+                    captured_ranges.push(Some((self.index, *drlen)));
+                }
+                JTAGAction::WriteReg { ir, dr } => {
+                    padded_actions.push(JTAGAction::WriteReg {
+                        ir: self.pad_ir(ir)?,
+                        dr: self.pad_dr(dr),
+                    });
+                    captured_ranges.push(None);
+                }
+                _ => {
+                    padded_actions.push(action.clone());
+                    captured_ranges.push(None);
+                }
+            }
+        }
+
+        self.adapter.flush()?;
+        let mut ret = self.adapter.execute_actions(&padded_actions)?;
+        for (output, range) in ret.iter_mut().zip(captured_ranges) {
+            if let (JTAGOutput::CapturedBits(bits), Some((start, len))) = (&mut *output, range) {
+                *bits = bits[start..start + len].to_bitvec();
+            }
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    fn test_chain() -> ScanChain<drivers::SimulatedJTAGAdapter> {
+        let adapter = drivers::SimulatedJTAGAdapter::new(vec![
+            Box::new(
+                drivers::GenericSimulatedDevice::new(6)
+                    .with_idcode(0x1372_3093, bits![1, 0, 0, 1, 0, 0])
+                    .with_register(bits![0, 1, 0, 0, 0, 0], bits![0; 8]),
+            ),
+            Box::new(
+                drivers::GenericSimulatedDevice::new(4).with_idcode(0x4ba0_0477, bits![0, 1, 1, 1]),
+            ),
+            Box::new(
+                drivers::GenericSimulatedDevice::new(5)
+                    .with_register(bits![0, 0, 1, 0, 0], bits![1, 0, 1]),
+            ),
+        ]);
+        ScanChain::new(adapter, &[6, 4, 5])
+    }
+
+    #[test]
+    fn test_scanchain_ir_padding() {
+        let mut chain = test_chain();
+        chain.adapter().reset_to_tlr();
+        chain.adapter().flush().unwrap();
+
+        let mut dev = chain.device(1);
+        let ir = dev.shift_ir_inout(bits![0, 1, 1, 1], false).unwrap();
+        assert_eq!(ir, bits![1, 0, 0, 0]);
+
+        let adapter = chain.adapter();
+        assert_eq!(adapter.current_ir(0), bits![1; 6]);
+        assert_eq!(adapter.current_ir(1), bits![0, 1, 1, 1]);
+        assert_eq!(adapter.current_ir(2), bits![1; 5]);
+
+        let idcode = chain.device(1).shift_dr_inout(bits![0; 32], false).unwrap();
+        assert_eq!(idcode.load_le::<u32>(), 0x4ba0_0477);
+    }
+
+    #[test]
+    fn test_scanchain_regs() {
+        let mut chain = test_chain();
+        chain.adapter().reset_to_tlr();
+
+        let mut dev = chain.device(0);
+        dev.write_reg(bits![0, 1, 0, 0, 0, 0], bits![1, 1, 0, 1, 0, 0, 1, 0]);
+        let reg = dev.read_reg(bits![0, 1, 0, 0, 0, 0], 8).unwrap();
+        assert_eq!(reg, bits![1, 1, 0, 1, 0, 0, 1, 0]);
+
+        let reg = chain.device(2).read_reg(bits![0, 0, 1, 0, 0], 3).unwrap();
+        assert_eq!(reg, bits![1, 0, 1]);
+    }
+
+    #[test]
+    fn test_scanchain_bad_ir_len() {
+        let mut chain = test_chain();
+        let ret = chain.device(2).shift_ir_inout(bits![0; 4], false);
+        assert!(matches!(ret, Err(JTAGError::InvalidAction(..))));
+    }
+}
@@ -0,0 +1,535 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+const CFG_OUT: u8 = 0x04;
+const CFG_IN: u8 = 0x05;
+const USERCODE: u8 = 0x08;
+const IDCODE: u8 = 0x09;
+const JPROGRAM: u8 = 0x0b;
+const JSTART: u8 = 0x0c;
+const ISC_ENABLE: u8 = 0x10;
+const ISC_NOOP: u8 = 0x14;
+const ISC_DISABLE: u8 = 0x16;
+const XSC_DNA: u8 = 0x17;
+const FUSE_DNA: u8 = 0x32;
+const FUSE_USER: u8 = 0x33;
+const FUSE_CNTL: u8 = 0x34;
+const BYPASS: u8 = 0x3f;
+const IR_LEN: usize = 6;
+
+/// Number of times to check for INIT after JPROGRAM before giving up
+const INIT_POLLS: usize = 100;
+const INIT_POLL_NS: u64 = 1_000_000;
+/// TCK cycles to run the startup sequence for after JSTART
+const STARTUP_CYCLES: usize = 2000;
+
+const SYNC: u32 = 0xaa99_5566;
+const NOOP: u32 = 0x2000_0000;
+const CMD_DESYNC: u32 = 0x0d;
+
+/// Length of the Device DNA of 7-series devices
+const DNA_LEN: usize = 57;
+/// Length of the Device DNA of UltraScale and UltraScale+ devices
+const DNA_LEN_ULTRASCALE: usize = 96;
+
+/// Type 1 packet header reading `count` words from `reg`
+fn type1_read(reg: Series7Register, count: u32) -> u32 {
+    0x2800_0000 | reg.address() << 13 | count
+}
+
+/// Type 1 packet header writing `count` words to `reg`
+fn type1_write(reg: Series7Register, count: u32) -> u32 {
+    0x3000_0000 | reg.address() << 13 | count
+}
+
+fn words_bits(words: &[u32]) -> BitVec {
+    let bytes = words
+        .iter()
+        .flat_map(|x| x.to_be_bytes())
+        .collect::<Vec<_>>();
+    bitstream_bits(&bytes)
+}
+
+fn msb_first(bits: &BitSlice) -> u128 {
+    bits.iter().by_vals().fold(0, |acc, x| acc << 1 | x as u128)
+}
+
+/// Convert bitstream bytes into the bits to shift into CFG_IN. The
+/// configuration logic expects the MSB of each byte first, the opposite of
+/// how bytes are normally shifted.
+pub(crate) fn bitstream_bits(data: &[u8]) -> BitVec {
+    data.iter()
+        .flat_map(|x| x.view_bits::<Msb0>().iter().by_vals())
+        .collect()
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, Default)]
+/// Configuration status captured in the instruction register of a 7-series
+/// device
+pub struct Series7IrStatus {
+    /// The DONE pin is high, i.e. the device is configured
+    pub done: bool,
+    /// Configuration memory has been cleared (INIT_B is high)
+    pub init_complete: bool,
+    pub isc_enabled: bool,
+    pub isc_done: bool,
+}
+
+impl Series7IrStatus {
+    pub fn from_capture(ir: &BitSlice) -> Self {
+        Self {
+            isc_done: ir[2],
+            isc_enabled: ir[3],
+            init_complete: ir[4],
+            done: ir[5],
+        }
+    }
+}
+
+/// Configuration of Xilinx 7-series FPGAs (and the PL of Zynq-7000 devices)
+/// over JTAG
+///
+/// Only single die parts with a 6-bit instruction register are supported.
+/// UltraScale and UltraScale+ parts use the same instructions and packets,
+/// but as they are not in [KNOWN_PARTS] they need to be described with a
+/// custom [KnownPart].
+///
+/// On a Zynq, the ARM DAP shares the chain with the PL, so a
+/// [ScanChainDevice] should be used as the adapter.
+pub struct Series7 {
+    part: &'static KnownPart,
+}
+
+impl Series7 {
+    pub fn new(part: &'static KnownPart) -> Result<Self, JTAGError> {
+        let families = ["XC7", "XCKU", "XCVU", "XCZU"];
+        if !families.iter().any(|x| part.name.starts_with(x)) || part.ir_len != 6 {
+            return Err(JTAGError::InvalidAction(format!(
+                "{} is not a supported 7-series device",
+                part.name
+            )));
+        }
+        Ok(Self { part })
+    }
+
+    /// Identify the device using its IDCODE
+    pub fn detect<T: JTAGAdapter + ?Sized>(adapter: &mut T) -> Result<Self, JTAGError> {
+        let idcode = IdCode(adapter.read_reg(&insn(IDCODE, IR_LEN), 32)?.load_le());
+        match idcode.part() {
+            Some(part) => Self::new(part),
+            None => Err(JTAGError::InvalidAction(format!(
+                "{idcode} is not a known 7-series device"
+            ))),
+        }
+    }
+
+    pub fn part(&self) -> &'static KnownPart {
+        self.part
+    }
+
+    fn ultrascale(&self) -> bool {
+        !self.part.name.starts_with("XC7")
+    }
+
+    fn ultrascale_plus(&self) -> bool {
+        let name = self.part.name;
+        self.ultrascale() && (name.starts_with("XCZU") || name.ends_with('P'))
+    }
+
+    /// Read the configuration status from the instruction register
+    pub fn ir_status<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+    ) -> Result<Series7IrStatus, JTAGError> {
+        let ir = adapter.shift_ir_inout(&insn(BYPASS, IR_LEN), false)?;
+        Ok(Series7IrStatus::from_capture(&ir))
+    }
+
+    /// Clear the configuration with JPROGRAM and wait for INIT
+    pub fn clear<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<(), JTAGError> {
+        adapter.shift_ir_out(&insn(JPROGRAM, IR_LEN), false);
+        for _ in 0..INIT_POLLS {
+            adapter.queue_action(JTAGAction::DelayNS(INIT_POLL_NS));
+            let ir = adapter.shift_ir_inout(&insn(ISC_NOOP, IR_LEN), false)?;
+            if Series7IrStatus::from_capture(&ir).init_complete {
+                return Ok(());
+            }
+        }
+        Err(JTAGError::Timeout)
+    }
+
+    /// Load a bitstream into the configuration memory, i.e. the data of a
+    /// .bin file or the bitstream part of a .bit file, and check that the
+    /// device starts up
+    pub fn program<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        bitstream: &[u8],
+    ) -> Result<(), JTAGError> {
+        adapter.reset_to_tlr();
+        self.clear(adapter)?;
+
+        adapter.shift_ir_out(&insn(CFG_IN, IR_LEN), false);
+        adapter.shift_dr_out(&bitstream_bits(bitstream), false);
+
+        adapter.shift_ir_out(&insn(JSTART, IR_LEN), false);
+        adapter.shift_bits_out(&bitvec![0; STARTUP_CYCLES], false);
+        adapter.reset_to_tlr();
+
+        if !self.ir_status(adapter)?.done {
+            return Err(JTAGError::VerifyFailed(
+                "DONE is not set after configuration".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Read a configuration register using CFG_IN and CFG_OUT. This works
+    /// whether or not the device is configured
+    pub fn read_register<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        reg: Series7Register,
+    ) -> Result<u32, JTAGError> {
+        adapter.reset_to_tlr();
+        adapter.shift_ir_out(&insn(CFG_IN, IR_LEN), false);
+        adapter.shift_dr_out(
+            &words_bits(&[SYNC, NOOP, type1_read(reg, 1), NOOP, NOOP]),
+            false,
+        );
+        adapter.shift_ir_out(&insn(CFG_OUT, IR_LEN), false);
+        let out = adapter.shift_dr_inout(&bitvec![0; 32], false)?;
+
+        adapter.shift_ir_out(&insn(CFG_IN, IR_LEN), false);
+        adapter.shift_dr_out(
+            &words_bits(&[type1_write(Series7Register::Cmd, 1), CMD_DESYNC, NOOP, NOOP]),
+            false,
+        );
+        adapter.reset_to_tlr();
+        adapter.flush()?;
+
+        // read back MSB first
+        Ok(out.iter().by_vals().fold(0, |acc, x| acc << 1 | x as u32))
+    }
+
+    /// Read and decode the registers which show why configuration failed
+    pub fn read_registers<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+    ) -> Result<Series7Registers, JTAGError> {
+        Ok(Series7Registers {
+            stat: self.read_register(adapter, Series7Register::Stat)?.into(),
+            ctl0: self.read_register(adapter, Series7Register::Ctl0)?.into(),
+            bootsts: self
+                .read_register(adapter, Series7Register::BootSts)?
+                .into(),
+            idcode: self.read_register(adapter, Series7Register::IdCode)?.into(),
+            cor0: self.read_register(adapter, Series7Register::Cor0)?.into(),
+            wbstar: self.read_register(adapter, Series7Register::WbStar)?.into(),
+        })
+    }
+
+    /// The XADC (or SYSMON) of this device
+    pub fn xadc(&self) -> Xadc {
+        if self.part.name.starts_with("XC7") {
+            Xadc::new(XadcKind::Xadc)
+        } else if self.ultrascale_plus() {
+            Xadc::new(XadcKind::SysmonE4)
+        } else {
+            Xadc::new(XadcKind::SysmonE1)
+        }
+    }
+
+    /// A user register of this device (e.g. for a [JtagSpi] bridge)
+    pub fn bscan(&self, user: BscanUser) -> Bscan {
+        Bscan::new(user).with_ultrascale(self.ultrascale())
+    }
+
+    /// Read the Device DNA, which uniquely identifies the die
+    ///
+    /// On 7-series devices this is the 57-bit value from XSC_DNA (the same as
+    /// the DNA_PORT primitive), on UltraScale and UltraScale+ devices it is
+    /// the 96-bit value from FUSE_DNA (the same as DNA_PORTE2). In both cases
+    /// the MSB is shifted out first.
+    pub fn read_dna<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<u128, JTAGError> {
+        if self.ultrascale() {
+            return self.read_fuse_dna(adapter);
+        }
+
+        // the DNA can only be read while in ISC mode (ISC_CONFIG is 5 bits)
+        adapter.shift_ir_out(&insn(ISC_ENABLE, IR_LEN), false);
+        adapter.shift_dr_out(&bitvec![0; 5], false);
+        let dna = adapter.read_reg(&insn(XSC_DNA, IR_LEN), DNA_LEN)?;
+        adapter.shift_ir_out(&insn(ISC_DISABLE, IR_LEN), false);
+        adapter.reset_to_tlr();
+        adapter.flush()?;
+        Ok(msb_first(&dna))
+    }
+
+    /// Read the Device DNA directly from the eFUSEs with FUSE_DNA. This is
+    /// 57 bits on 7-series devices and 96 bits on UltraScale and UltraScale+
+    /// devices
+    pub fn read_fuse_dna<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+    ) -> Result<u128, JTAGError> {
+        let len = if self.ultrascale() {
+            DNA_LEN_ULTRASCALE
+        } else {
+            DNA_LEN
+        };
+        Ok(msb_first(&adapter.read_reg(&insn(FUSE_DNA, IR_LEN), len)?))
+    }
+
+    /// Read the 32-bit USERCODE set by the loaded bitstream
+    pub fn read_usercode<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+    ) -> Result<u32, JTAGError> {
+        Ok(adapter.read_reg(&insn(USERCODE, IR_LEN), 32)?.load_le())
+    }
+
+    /// Read the 32-bit user eFUSE register
+    pub fn read_fuse_user<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+    ) -> Result<u32, JTAGError> {
+        Ok(adapter.read_reg(&insn(FUSE_USER, IR_LEN), 32)?.load_le())
+    }
+
+    /// Read the eFUSE control register, which holds the security settings
+    /// (e.g. readback disable and whether the AES key must be used)
+    pub fn read_fuse_cntl<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+    ) -> Result<u32, JTAGError> {
+        Ok(adapter.read_reg(&insn(FUSE_CNTL, IR_LEN), 32)?.load_le())
+    }
+
+    /// Load the bitstream from a .bit file after checking that it was built
+    /// for this device
+    pub fn program_bit_file<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        bit: &BitFile,
+    ) -> Result<(), JTAGError> {
+        let idcode = IdCode(adapter.read_reg(&insn(IDCODE, IR_LEN), 32)?.load_le());
+        bit.check_idcode(idcode)?;
+        self.program(adapter, &bit.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+    use std::collections::HashMap;
+
+    /// A 7-series device (XC7A35T) which keeps a copy of everything shifted
+    /// into CFG_IN and starts up if that contained a sync word. CFG_OUT
+    /// returns the register read by the last type 1 read packet.
+    struct SimSeries7 {
+        cfg_in: BitVec,
+        registers: HashMap<u32, u32>,
+        ir: u8,
+        init_polls: usize,
+        init: bool,
+        done: bool,
+        fuse_dna_len: usize,
+    }
+
+    impl SimSeries7 {
+        fn new() -> Self {
+            Self {
+                cfg_in: BitVec::new(),
+                registers: HashMap::from([(0x07, 0x4010_7cfc), (0x0c, 0x0362_d093)]),
+                ir: 0x09,
+                init_polls: 0,
+                init: true,
+                done: false,
+                fuse_dna_len: 57,
+            }
+        }
+    }
+
+    impl drivers::XilinxSimulatedDevice for SimSeries7 {
+        fn capture_ir(&self) -> BitVec {
+            let mut ret = bitvec![1, 0, 0, 0, 0, 0];
+            ret.set(4, self.init);
+            ret.set(5, self.done);
+            ret
+        }
+        fn update_ir(&mut self, ir: &BitSlice) {
+            self.ir = ir.load_le();
+            match self.ir {
+                0x0b => {
+                    self.cfg_in.clear();
+                    self.init_polls = 3;
+                    self.init = false;
+                    self.done = false;
+                }
+                0x14 if self.init_polls > 0 => {
+                    self.init_polls -= 1;
+                    self.init = self.init_polls == 0;
+                }
+                0x0c => {
+                    let sync = crate::series7::bitstream_bits(&[0xaa, 0x99, 0x55, 0x66]);
+                    self.done = self.cfg_in.windows(32).any(|x| x == sync);
+                }
+                _ => {}
+            }
+        }
+        fn shift_dr(&mut self, _ir: &BitSlice, tdi: bool) {
+            if self.ir == 0x05 && self.init {
+                self.cfg_in.push(tdi);
+            }
+        }
+        fn capture_dr(&mut self, _ir: &BitSlice) -> BitVec {
+            match self.ir {
+                0x09 => 0x0362_d093u32
+                    .view_bits::<Lsb0>()
+                    .iter()
+                    .by_vals()
+                    .collect(),
+                0x05 => bitvec![0; 32],
+                0x08 => 0x1234_5678u32
+                    .view_bits::<Lsb0>()
+                    .iter()
+                    .by_vals()
+                    .collect(),
+                0x17 => (0..57).rev().map(|i| TEST_DNA & (1 << i) != 0).collect(),
+                0x32 => (0..self.fuse_dna_len)
+                    .rev()
+                    .map(|i| TEST_DNA as u128 & (1 << i) != 0)
+                    .collect(),
+                0x33 => bitvec![1; 32],
+                0x04 => {
+                    let words = self
+                        .cfg_in
+                        .chunks_exact(32)
+                        .map(|x| x.iter().by_vals().fold(0, |acc, x| acc << 1 | x as u32))
+                        .collect::<Vec<_>>();
+                    let address = words
+                        .iter()
+                        .rev()
+                        .find(|&&x| x >> 27 == 0b00101)
+                        .map_or(0, |x| x >> 13 & 0x1f);
+                    let value = self.registers.get(&address).copied().unwrap_or(0);
+                    (0..32).rev().map(|i| value & (1 << i) != 0).collect()
+                }
+                _ => bitvec![0],
+            }
+        }
+        fn update_dr(&mut self, _ir: &BitSlice, _dr: &BitSlice) {}
+    }
+
+    const TEST_DNA: u64 = 0x0140_2030_4050_6070;
+
+    const TEST_BITSTREAM: &[u8] = &[
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, // dummy
+        0x00, 0x00, 0x00, 0xbb, 0x11, 0x22, 0x00, 0x44, // bus width detection
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, // dummy
+        0xaa, 0x99, 0x55, 0x66, // sync
+        0x20, 0x00, 0x00, 0x00, // NOOP
+        0x30, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00, 0x05, // CMD = START
+        0x20, 0x00, 0x00, 0x00, // NOOP
+    ];
+
+    #[test]
+    fn test_series7_status() {
+        let status = Series7IrStatus::from_capture(bits![1, 0, 0, 1, 1, 0]);
+        assert!(status.isc_enabled && status.init_complete);
+        assert!(!status.isc_done && !status.done);
+
+        let part = IdCode(0x0362_d093).part().unwrap();
+        assert_eq!(Series7::new(part).unwrap().part().name, "XC7A35T");
+        assert!(Series7::new(IdCode(0x06e1_8093).part().unwrap()).is_err());
+        assert_eq!(
+            crate::series7::bitstream_bits(&[0x80, 0x03]),
+            bits![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1]
+        );
+    }
+
+    #[test]
+    fn test_series7_read_registers() {
+        let adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(SimSeries7::new())]);
+        let mut chain = ScanChain::new(adapter, &[6]);
+        let mut device = chain.device(0);
+        let fpga = Series7::detect(&mut device).unwrap();
+        let regs = fpga.read_registers(&mut device).unwrap();
+        assert_eq!(regs.idcode, IdCode(0x0362_d093));
+        assert!(regs.stat.done && regs.stat.init_b);
+        assert!(!regs.ctl0.decrypt);
+        assert_eq!(
+            fpga.read_register(&mut device, Series7Register::Stat)
+                .unwrap(),
+            0x4010_7cfc
+        );
+    }
+
+    #[test]
+    fn test_series7_dna() {
+        let adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(SimSeries7::new())]);
+        let mut chain = ScanChain::new(adapter, &[6]);
+        let mut device = chain.device(0);
+        let fpga = Series7::detect(&mut device).unwrap();
+        assert_eq!(fpga.read_dna(&mut device).unwrap(), TEST_DNA as u128);
+        assert_eq!(fpga.read_fuse_dna(&mut device).unwrap(), TEST_DNA as u128);
+        assert_eq!(fpga.read_usercode(&mut device).unwrap(), 0x1234_5678);
+        assert_eq!(fpga.read_fuse_user(&mut device).unwrap(), 0xffff_ffff);
+        assert_eq!(fpga.read_fuse_cntl(&mut device).unwrap(), 0);
+        assert_eq!(fpga.xadc().kind(), XadcKind::Xadc);
+
+        // UltraScale devices only have the 96-bit DNA
+        static XCKU040: KnownPart = KnownPart {
+            name: "XCKU040",
+            idcode: 0x0380_6093,
+            mask: 0x0fff_ffff,
+            ir_len: 6,
+        };
+        let mut sim = SimSeries7::new();
+        sim.fuse_dna_len = 96;
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(sim)]);
+        let fpga = Series7::new(&XCKU040).unwrap();
+        assert_eq!(fpga.read_dna(&mut adapter).unwrap(), TEST_DNA as u128);
+        assert_eq!(fpga.xadc().kind(), XadcKind::SysmonE1);
+        assert_eq!(
+            fpga.bscan(BscanUser::User1),
+            Bscan::new(BscanUser::User1).with_ultrascale(true)
+        );
+    }
+
+    #[test]
+    fn test_series7_program() {
+        let adapter = drivers::SimulatedJTAGAdapter::new(vec![
+            Box::new(drivers::GenericSimulatedDevice::new(4)),
+            Box::new(SimSeries7::new()),
+        ]);
+        let mut chain = ScanChain::new(adapter, &[4, 6]);
+        let mut device = chain.device(1);
+        let fpga = Series7::detect(&mut device).unwrap();
+        assert!(!fpga.ir_status(&mut device).unwrap().done);
+        fpga.program(&mut device, TEST_BITSTREAM).unwrap();
+        assert!(fpga.ir_status(&mut device).unwrap().done);
+
+        // no sync word
+        assert!(matches!(
+            fpga.program(&mut device, &TEST_BITSTREAM[..24]),
+            Err(JTAGError::VerifyFailed(..))
+        ));
+
+        let mut bit = BitFile {
+            part: "7a35tcsg324".to_string(),
+            data: TEST_BITSTREAM.to_vec(),
+            ..Default::default()
+        };
+        fpga.program_bit_file(&mut device, &bit).unwrap();
+        bit.part = "7a50tcsg324".to_string();
+        assert!(matches!(
+            fpga.program_bit_file(&mut device, &bit),
+            Err(JTAGError::InvalidAction(..))
+        ));
+    }
+}
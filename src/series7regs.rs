@@ -0,0 +1,316 @@
+use crate::*;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Configuration registers of 7-series and UltraScale devices
+pub enum Series7Register {
+    Crc,
+    Far,
+    Fdri,
+    Fdro,
+    Cmd,
+    Ctl0,
+    Mask,
+    Stat,
+    Lout,
+    Cor0,
+    Mfwr,
+    Cbc,
+    IdCode,
+    Axss,
+    Cor1,
+    WbStar,
+    Timer,
+    BootSts,
+    Ctl1,
+    Bspi,
+}
+
+impl Series7Register {
+    /// Address used in type 1 packets
+    pub fn address(self) -> u32 {
+        match self {
+            Self::Crc => 0x00,
+            Self::Far => 0x01,
+            Self::Fdri => 0x02,
+            Self::Fdro => 0x03,
+            Self::Cmd => 0x04,
+            Self::Ctl0 => 0x05,
+            Self::Mask => 0x06,
+            Self::Stat => 0x07,
+            Self::Lout => 0x08,
+            Self::Cor0 => 0x09,
+            Self::Mfwr => 0x0a,
+            Self::Cbc => 0x0b,
+            Self::IdCode => 0x0c,
+            Self::Axss => 0x0d,
+            Self::Cor1 => 0x0e,
+            Self::WbStar => 0x10,
+            Self::Timer => 0x11,
+            Self::BootSts => 0x16,
+            Self::Ctl1 => 0x18,
+            Self::Bspi => 0x1f,
+        }
+    }
+}
+
+fn bit(value: u32, bit: u32) -> bool {
+    value & (1 << bit) != 0
+}
+
+fn field(value: u32, lsb: u32, len: u32) -> u8 {
+    ((value >> lsb) & ((1 << len) - 1)) as u8
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Decoded STAT register
+pub struct Series7Stat {
+    pub crc_error: bool,
+    /// Decryption is enabled and the bitstream has been checked
+    pub part_secured: bool,
+    /// All MMCMs and PLLs are locked
+    pub mmcm_lock: bool,
+    /// All DCI controllers have matched
+    pub dci_match: bool,
+    /// The startup sequence has finished
+    pub end_of_startup: bool,
+    pub gts_cfg_b: bool,
+    pub gwe: bool,
+    pub ghigh_b: bool,
+    /// Value of the mode pins M\[2:0\]
+    pub mode: u8,
+    /// Configuration memory has been cleared
+    pub init_complete: bool,
+    /// Value of the INIT_B pin
+    pub init_b: bool,
+    /// DONE has been released by the startup sequence
+    pub release_done: bool,
+    /// Value of the DONE pin
+    pub done: bool,
+    /// The IDCODE in the bitstream did not match the device
+    pub id_error: bool,
+    /// Decryption error
+    pub dec_error: bool,
+    /// The XADC has detected an over temperature condition
+    pub xadc_over_temp: bool,
+    pub startup_state: u8,
+    /// Detected bus width (0 for x1, 1 for x8, 2 for x16, 3 for x32)
+    pub bus_width: u8,
+}
+
+impl From<u32> for Series7Stat {
+    fn from(value: u32) -> Self {
+        Self {
+            crc_error: bit(value, 0),
+            part_secured: bit(value, 1),
+            mmcm_lock: bit(value, 2),
+            dci_match: bit(value, 3),
+            end_of_startup: bit(value, 4),
+            gts_cfg_b: bit(value, 5),
+            gwe: bit(value, 6),
+            ghigh_b: bit(value, 7),
+            mode: field(value, 8, 3),
+            init_complete: bit(value, 11),
+            init_b: bit(value, 12),
+            release_done: bit(value, 13),
+            done: bit(value, 14),
+            id_error: bit(value, 15),
+            dec_error: bit(value, 16),
+            xadc_over_temp: bit(value, 17),
+            startup_state: field(value, 18, 3),
+            bus_width: field(value, 25, 2),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Decoded CTL0 register
+pub struct Series7Ctl0 {
+    pub gts_usr_b: bool,
+    /// Configuration pins stay enabled after configuration
+    pub persist: bool,
+    /// Readback and reconfiguration restrictions
+    pub security: u8,
+    /// AES decryption is enabled
+    pub decrypt: bool,
+    pub farsrc: bool,
+    pub glutmask_b: bool,
+    pub overtemp_powerdown: bool,
+    pub icap_select: bool,
+    /// Fallback to the golden bitstream is enabled
+    pub config_fallback: bool,
+    /// The AES key is stored in eFUSEs instead of BBRAM
+    pub efuse_key: bool,
+}
+
+impl From<u32> for Series7Ctl0 {
+    fn from(value: u32) -> Self {
+        Self {
+            gts_usr_b: bit(value, 0),
+            persist: bit(value, 3),
+            security: field(value, 4, 2),
+            decrypt: bit(value, 6),
+            farsrc: bit(value, 7),
+            glutmask_b: bit(value, 8),
+            overtemp_powerdown: bit(value, 9),
+            icap_select: bit(value, 10),
+            config_fallback: bit(value, 12),
+            efuse_key: bit(value, 31),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Status of one configuration attempt in the BOOTSTS register
+pub struct Series7BootStatus {
+    /// The rest of this status is valid
+    pub valid: bool,
+    /// The attempt was a fallback to the golden bitstream
+    pub fallback: bool,
+    /// The attempt was started by IPROG
+    pub iprog: bool,
+    pub watchdog_timeout: bool,
+    pub id_error: bool,
+    pub crc_error: bool,
+    /// A BPI flash read wrapped around
+    pub wrap_error: bool,
+    pub hmac_error: bool,
+}
+
+impl From<u8> for Series7BootStatus {
+    fn from(value: u8) -> Self {
+        let value = value as u32;
+        Self {
+            valid: bit(value, 0),
+            fallback: bit(value, 1),
+            iprog: bit(value, 2),
+            watchdog_timeout: bit(value, 3),
+            id_error: bit(value, 4),
+            crc_error: bit(value, 5),
+            wrap_error: bit(value, 6),
+            hmac_error: bit(value, 7),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Decoded BOOTSTS register
+pub struct Series7BootSts {
+    /// The most recent configuration attempt
+    pub last: Series7BootStatus,
+    /// The configuration attempt before that
+    pub previous: Series7BootStatus,
+}
+
+impl From<u32> for Series7BootSts {
+    fn from(value: u32) -> Self {
+        Self {
+            last: (value as u8).into(),
+            previous: ((value >> 8) as u8).into(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Decoded COR0 register
+///
+/// The `*_cycle` fields are the startup phase (0 to 7, where 7 means keep
+/// the current state) in which each startup event happens.
+pub struct Series7Cor0 {
+    pub gwe_cycle: u8,
+    pub gts_cycle: u8,
+    pub lock_cycle: u8,
+    pub match_cycle: u8,
+    pub done_cycle: u8,
+    /// Startup clock source (0 for CCLK, 1 for USERCLK, 2 for JTAG)
+    pub startup_clock: u8,
+    /// Configuration clock frequency selection
+    pub oscfsel: u8,
+    /// Readback capture is single shot
+    pub single: bool,
+    /// DONE is actively driven high
+    pub drive_done: bool,
+    pub done_pipe: bool,
+}
+
+impl From<u32> for Series7Cor0 {
+    fn from(value: u32) -> Self {
+        Self {
+            gwe_cycle: field(value, 0, 3),
+            gts_cycle: field(value, 3, 3),
+            lock_cycle: field(value, 6, 3),
+            match_cycle: field(value, 9, 3),
+            done_cycle: field(value, 12, 3),
+            startup_clock: field(value, 15, 2),
+            oscfsel: field(value, 17, 6),
+            single: bit(value, 23),
+            drive_done: bit(value, 24),
+            done_pipe: bit(value, 25),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Decoded WBSTAR register, which sets where IPROG loads the next bitstream
+/// from
+pub struct Series7WbStar {
+    pub start_address: u32,
+    /// RS\[1:0\] are driven during the reconfiguration
+    pub rs_enabled: bool,
+    /// Value of the RS\[1:0\] pins
+    pub rs: u8,
+}
+
+impl From<u32> for Series7WbStar {
+    fn from(value: u32) -> Self {
+        Self {
+            start_address: value & 0x1fff_ffff,
+            rs_enabled: bit(value, 29),
+            rs: field(value, 30, 2),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// The configuration registers which are useful for working out why a
+/// device did not configure
+pub struct Series7Registers {
+    pub stat: Series7Stat,
+    pub ctl0: Series7Ctl0,
+    pub bootsts: Series7BootSts,
+    pub idcode: IdCode,
+    pub cor0: Series7Cor0,
+    pub wbstar: Series7WbStar,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_series7_register_decode() {
+        let stat = Series7Stat::from(0x4010_7cfc);
+        assert!(stat.done && stat.release_done && stat.init_b && stat.init_complete);
+        assert!(stat.mmcm_lock && stat.dci_match && stat.end_of_startup);
+        assert!(!stat.crc_error && !stat.id_error);
+        assert_eq!(stat.mode, 0b100);
+        assert_eq!(stat.startup_state, 0b100);
+
+        let bootsts = Series7BootSts::from(0x0000_0301);
+        assert!(bootsts.last.valid && !bootsts.last.fallback);
+        assert!(bootsts.previous.valid && bootsts.previous.fallback);
+
+        let cor0 = Series7Cor0::from(0x0200_3fe5);
+        assert_eq!(cor0.gwe_cycle, 5);
+        assert_eq!(cor0.gts_cycle, 4);
+        assert_eq!(cor0.lock_cycle, 7);
+        assert_eq!(cor0.match_cycle, 7);
+        assert_eq!(cor0.done_cycle, 3);
+        assert!(cor0.done_pipe && !cor0.drive_done);
+
+        let wbstar = Series7WbStar::from(0x6000_1000);
+        assert_eq!(wbstar.start_address, 0x1000);
+        assert!(wbstar.rs_enabled);
+        assert_eq!(wbstar.rs, 1);
+        assert_eq!(Series7Register::BootSts.address(), 0x16);
+    }
+}
@@ -0,0 +1,630 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+const READ_JEDEC_ID: u8 = 0x9f;
+const READ_SFDP: u8 = 0x5a;
+const READ_STATUS: u8 = 0x05;
+const WRITE_ENABLE: u8 = 0x06;
+const READ: u8 = 0x03;
+const READ_4B: u8 = 0x13;
+const PAGE_PROGRAM: u8 = 0x02;
+const PAGE_PROGRAM_4B: u8 = 0x12;
+const CHIP_ERASE: u8 = 0xc7;
+
+/// Status register bit set while an erase or program is in progress
+const STATUS_WIP: u8 = 0x01;
+
+/// Number of times to check the status register before giving up, enough
+/// for a chip erase of a large flash
+const READY_POLLS: usize = 500_000;
+const READY_POLL_NS: u64 = 1_000_000;
+
+/// Largest number of bytes to read in one DR scan
+const READ_CHUNK: usize = 4096;
+
+/// Flashes larger than this need 4-byte addresses
+const MAX_3B_SIZE: usize = 1 << 24;
+
+const SFDP_SIGNATURE: &[u8] = b"SFDP";
+
+fn sfdp_err(msg: impl Into<String>) -> JTAGError {
+    ParseError::new("SFDP", msg).into()
+}
+
+fn msb_first_bits(data: &[u8]) -> impl Iterator<Item = bool> + '_ {
+    data.iter()
+        .flat_map(|x| x.view_bits::<Msb0>().iter().by_vals())
+}
+
+/// Equivalent of an erase opcode using 4-byte addresses
+fn erase_opcode_4b(opcode: u8) -> Option<u8> {
+    match opcode {
+        0x20 => Some(0x21),
+        0x52 => Some(0x5c),
+        0xd8 => Some(0xdc),
+        _ => None,
+    }
+}
+
+/// SPI access through a JTAG-SPI bridge design loaded into an FPGA, using
+/// the same protocol as the openocd jtagspi driver (and the proxy
+/// bitstreams built for it)
+///
+/// Each SPI transaction is a single DR scan of the selected user register
+/// containing a start bit, the number of SPI bits minus one (32 bits, MSB
+/// first) and the SPI data (MSB first). Data from the flash is returned
+/// on TDO delayed by one bit.
+pub struct JtagSpi {
+    bscan: Bscan,
+}
+
+impl JtagSpi {
+    /// Use the bridge in the given user register, e.g. from [Series7::bscan]
+    pub fn new(bscan: Bscan) -> Self {
+        Self { bscan }
+    }
+
+    /// Perform one SPI transaction: send `tx` and then read `rx_len` bytes.
+    /// Transactions which only send data are buffered.
+    pub fn transfer<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        tx: &[u8],
+        rx_len: usize,
+    ) -> Result<Vec<u8>, JTAGError> {
+        let spi_bits = (tx.len() + rx_len) * 8;
+        let len = spi_bits
+            .checked_sub(1)
+            .and_then(|x| u32::try_from(x).ok())
+            .ok_or_else(|| {
+                JTAGError::InvalidAction(format!("cannot transfer {spi_bits} SPI bits"))
+            })?;
+
+        let mut dr = bitvec![1];
+        dr.extend(len.view_bits::<Msb0>().iter().by_vals());
+        dr.extend(msb_first_bits(tx));
+        if rx_len == 0 {
+            self.bscan.write(adapter, &dr);
+            return Ok(Vec::new());
+        }
+        dr.extend(BitVec::<usize>::repeat(false, 1 + rx_len * 8));
+        let out = self.bscan.exchange(adapter, &dr)?;
+
+        let rx_start = 1 + 32 + tx.len() * 8 + 1;
+        Ok(out[rx_start..]
+            .chunks_exact(8)
+            .map(|x| x.iter().by_vals().fold(0, |acc, x| acc << 1 | x as u8))
+            .collect())
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// One of the erase operations supported by a flash
+pub struct SpiEraseType {
+    pub size: usize,
+    pub opcode: u8,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// Geometry of a SPI flash, from its SFDP tables
+pub struct SpiFlashInfo {
+    pub jedec_id: [u8; 3],
+    /// Size in bytes
+    pub size: usize,
+    pub page_size: usize,
+    /// Supported erase operations, smallest first
+    pub erase_types: Vec<SpiEraseType>,
+}
+
+impl SpiFlashInfo {
+    /// Parse the SFDP header and basic flash parameter table. `sfdp` must
+    /// start at SFDP address 0 and contain the whole table.
+    pub fn parse_sfdp(jedec_id: [u8; 3], sfdp: &[u8]) -> Result<Self, JTAGError> {
+        if sfdp.len() < 16 || &sfdp[..4] != SFDP_SIGNATURE {
+            return Err(sfdp_err("signature not found"));
+        }
+        // the first parameter header is always the basic flash parameter table
+        let header = &sfdp[8..16];
+        if header[0] != 0x00 || header[7] != 0xff {
+            return Err(sfdp_err("first table is not the basic flash parameters"));
+        }
+        let len = header[3] as usize;
+        let ptp = u32::from_le_bytes([header[4], header[5], header[6], 0]) as usize;
+        if len < 9 || sfdp.len() < ptp + len * 4 {
+            return Err(sfdp_err("basic flash parameter table is truncated"));
+        }
+        let dword =
+            |i: usize| u32::from_le_bytes(sfdp[ptp + (i - 1) * 4..ptp + i * 4].try_into().unwrap());
+
+        let density = dword(2);
+        let size_bits = if density & 0x8000_0000 != 0 {
+            1u64.checked_shl(density & 0x7fff_ffff)
+                .ok_or_else(|| sfdp_err("invalid density"))?
+        } else {
+            density as u64 + 1
+        };
+
+        let mut erase_types = [dword(8), dword(9)]
+            .iter()
+            .flat_map(|x| {
+                let x = x.to_le_bytes();
+                [(x[0], x[1]), (x[2], x[3])]
+            })
+            .filter(|&(exp, _)| exp != 0)
+            .map(|(exp, opcode)| {
+                Ok(SpiEraseType {
+                    size: 1usize
+                        .checked_shl(exp as u32)
+                        .ok_or_else(|| sfdp_err(format!("invalid erase size 2^{exp}")))?,
+                    opcode,
+                })
+            })
+            .collect::<Result<Vec<_>, JTAGError>>()?;
+        if erase_types.is_empty() {
+            return Err(sfdp_err("no erase types"));
+        }
+        erase_types.sort_by_key(|x| x.size);
+
+        // the page size was only added in JESD216A
+        let page_size = if len >= 11 {
+            1 << (dword(11) >> 4 & 0xf)
+        } else {
+            256
+        };
+
+        Ok(Self {
+            jedec_id,
+            size: (size_bits / 8) as usize,
+            page_size,
+            erase_types,
+        })
+    }
+
+    /// Whether 4-byte addresses need to be used
+    pub fn address_4byte(&self) -> bool {
+        self.size > MAX_3B_SIZE
+    }
+}
+
+/// Programming of a SPI flash (e.g. the configuration flash of an FPGA)
+/// through a [JtagSpi] bridge
+///
+/// The flash is identified using its JEDEC ID and SFDP tables. Flashes
+/// larger than 16 MiB are accessed with the 4-byte address opcodes rather
+/// than by switching the flash into 4-byte address mode, so that the FPGA
+/// can still boot from it afterwards.
+pub struct SpiFlash {
+    spi: JtagSpi,
+    info: SpiFlashInfo,
+}
+
+impl SpiFlash {
+    /// Read the JEDEC ID (manufacturer, memory type and capacity)
+    pub fn read_jedec_id<T: JTAGAdapter + ?Sized>(
+        spi: &JtagSpi,
+        adapter: &mut T,
+    ) -> Result<[u8; 3], JTAGError> {
+        let id = spi.transfer(adapter, &[READ_JEDEC_ID], 3)?;
+        Ok([id[0], id[1], id[2]])
+    }
+
+    /// Read `len` bytes of the SFDP tables starting at `addr`
+    pub fn read_sfdp<T: JTAGAdapter + ?Sized>(
+        spi: &JtagSpi,
+        adapter: &mut T,
+        addr: u32,
+        len: usize,
+    ) -> Result<Vec<u8>, JTAGError> {
+        let addr = addr.to_be_bytes();
+        // followed by one dummy byte
+        let cmd = [READ_SFDP, addr[1], addr[2], addr[3], 0];
+        spi.transfer(adapter, &cmd, len)
+    }
+
+    /// Identify the flash behind `spi`
+    pub fn probe<T: JTAGAdapter + ?Sized>(
+        spi: JtagSpi,
+        adapter: &mut T,
+    ) -> Result<Self, JTAGError> {
+        let jedec_id = Self::read_jedec_id(&spi, adapter)?;
+        if jedec_id == [0; 3] || jedec_id == [0xff; 3] {
+            return Err(JTAGError::ProtocolViolation(
+                "no SPI flash found, is the JTAG-SPI bridge loaded?".to_string(),
+            ));
+        }
+
+        let header = Self::read_sfdp(&spi, adapter, 0, 16)?;
+        if &header[..4] != SFDP_SIGNATURE {
+            return Err(sfdp_err(format!(
+                "not supported by flash with JEDEC ID {:02x}{:02x}{:02x}",
+                jedec_id[0], jedec_id[1], jedec_id[2]
+            )));
+        }
+        let len = header[11] as usize;
+        let ptp = u32::from_le_bytes([header[12], header[13], header[14], 0]) as usize;
+        let sfdp = Self::read_sfdp(&spi, adapter, 0, ptp + len * 4)?;
+        let info = SpiFlashInfo::parse_sfdp(jedec_id, &sfdp)?;
+        Ok(Self { spi, info })
+    }
+
+    /// Use a flash with known geometry, e.g. one without SFDP tables
+    pub fn new(spi: JtagSpi, info: SpiFlashInfo) -> Self {
+        Self { spi, info }
+    }
+
+    pub fn info(&self) -> &SpiFlashInfo {
+        &self.info
+    }
+
+    fn address(&self, opcode: u8, addr: usize) -> Vec<u8> {
+        let addr = (addr as u32).to_be_bytes();
+        if self.info.address_4byte() {
+            vec![opcode, addr[0], addr[1], addr[2], addr[3]]
+        } else {
+            vec![opcode, addr[1], addr[2], addr[3]]
+        }
+    }
+
+    fn check_range(&self, addr: usize, len: usize) -> Result<(), JTAGError> {
+        if addr + len > self.info.size {
+            return Err(JTAGError::InvalidAction(format!(
+                "{len} bytes at {addr:#x} do not fit in a {} byte flash",
+                self.info.size
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn read_status<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<u8, JTAGError> {
+        Ok(self.spi.transfer(adapter, &[READ_STATUS], 1)?[0])
+    }
+
+    /// Wait for an erase or program to finish
+    pub fn wait_ready<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<(), JTAGError> {
+        for _ in 0..READY_POLLS {
+            if self.read_status(adapter)? & STATUS_WIP == 0 {
+                return Ok(());
+            }
+            adapter.queue_action(JTAGAction::DelayNS(READY_POLL_NS));
+        }
+        Err(JTAGError::Timeout)
+    }
+
+    fn write_command<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        cmd: &[u8],
+    ) -> Result<(), JTAGError> {
+        self.spi.transfer(adapter, &[WRITE_ENABLE], 0)?;
+        self.spi.transfer(adapter, cmd, 0)?;
+        self.wait_ready(adapter)
+    }
+
+    pub fn read<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        addr: usize,
+        len: usize,
+    ) -> Result<Vec<u8>, JTAGError> {
+        self.check_range(addr, len)?;
+        let opcode = if self.info.address_4byte() {
+            READ_4B
+        } else {
+            READ
+        };
+        let mut ret = Vec::with_capacity(len);
+        while ret.len() < len {
+            let chunk = (len - ret.len()).min(READ_CHUNK);
+            let cmd = self.address(opcode, addr + ret.len());
+            ret.extend(self.spi.transfer(adapter, &cmd, chunk)?);
+        }
+        Ok(ret)
+    }
+
+    pub fn erase_chip<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<(), JTAGError> {
+        self.write_command(adapter, &[CHIP_ERASE])
+    }
+
+    /// Erase every erase block overlapping `len` bytes at `addr`, using the
+    /// largest erase operations possible
+    pub fn erase<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        addr: usize,
+        len: usize,
+    ) -> Result<(), JTAGError> {
+        self.check_range(addr, len)?;
+        let erase_types = self
+            .info
+            .erase_types
+            .iter()
+            .filter_map(|x| {
+                if self.info.address_4byte() {
+                    Some(SpiEraseType {
+                        opcode: erase_opcode_4b(x.opcode)?,
+                        ..*x
+                    })
+                } else {
+                    Some(*x)
+                }
+            })
+            .collect::<Vec<_>>();
+        let smallest = erase_types
+            .first()
+            .ok_or_else(|| JTAGError::InvalidAction("no usable erase opcode".to_string()))?
+            .size;
+
+        let mut pos = addr / smallest * smallest;
+        let end = (addr + len).div_ceil(smallest) * smallest;
+        while pos < end {
+            let erase = erase_types
+                .iter()
+                .rev()
+                .find(|x| pos.is_multiple_of(x.size) && pos + x.size <= end)
+                .unwrap_or(&erase_types[0]);
+            self.write_command(adapter, &self.address(erase.opcode, pos))?;
+            pos += erase.size;
+        }
+        Ok(())
+    }
+
+    /// Program erased flash, splitting `data` at page boundaries
+    pub fn program_pages<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        addr: usize,
+        data: &[u8],
+    ) -> Result<(), JTAGError> {
+        self.check_range(addr, data.len())?;
+        let opcode = if self.info.address_4byte() {
+            PAGE_PROGRAM_4B
+        } else {
+            PAGE_PROGRAM
+        };
+        let mut pos = 0;
+        while pos < data.len() {
+            let page_end = ((addr + pos) / self.info.page_size + 1) * self.info.page_size;
+            let chunk = (page_end - addr - pos).min(data.len() - pos);
+            let mut cmd = self.address(opcode, addr + pos);
+            cmd.extend_from_slice(&data[pos..pos + chunk]);
+            self.write_command(adapter, &cmd)?;
+            pos += chunk;
+        }
+        Ok(())
+    }
+
+    pub fn verify<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        addr: usize,
+        data: &[u8],
+    ) -> Result<(), JTAGError> {
+        let flash = self.read(adapter, addr, data.len())?;
+        match flash.iter().zip(data).position(|(a, b)| a != b) {
+            Some(i) => Err(JTAGError::VerifyFailed(format!(
+                "flash contains {:#04x} instead of {:#04x} at {:#x}",
+                flash[i],
+                data[i],
+                addr + i
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Erase, program and verify. Whole erase blocks are erased, so any
+    /// other data in the first and last block is lost.
+    pub fn program<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        addr: usize,
+        data: &[u8],
+    ) -> Result<(), JTAGError> {
+        self.erase(adapter, addr, data.len())?;
+        self.program_pages(adapter, addr, data)?;
+        self.verify(adapter, addr, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    const FLASH_SIZE: usize = 1 << 20;
+
+    /// SFDP tables of a 1 MiB flash with 4 KiB and 64 KiB erase and 256 byte
+    /// pages
+    fn test_sfdp() -> Vec<u8> {
+        let mut ret = b"SFDP".to_vec();
+        ret.extend_from_slice(&[0x06, 0x01, 0x00, 0xff]);
+        ret.extend_from_slice(&[0x00, 0x06, 0x01, 0x0b, 0x10, 0x00, 0x00, 0xff]);
+        let mut bfpt = [0xffff_ffffu32; 11];
+        bfpt[0] = 0xfff1_20e5;
+        bfpt[1] = (FLASH_SIZE * 8 - 1) as u32;
+        bfpt[7] = 0xd810_200c;
+        bfpt[8] = 0x0000_0000;
+        bfpt[10] = 0xffff_ff81;
+        ret.extend(bfpt.iter().flat_map(|x| x.to_le_bytes()));
+        ret
+    }
+
+    /// A JTAG-SPI bridge in USER1 with a flash behind it. Read data is
+    /// produced while the command is still being shifted in, so it is
+    /// returned with the one bit delay of the real bridge.
+    struct SimJtagSpi {
+        memory: Vec<u8>,
+        sfdp: Vec<u8>,
+        write_enable: bool,
+        received: BitVec,
+        mosi: Vec<u8>,
+        miso: u8,
+        tdo: Option<bool>,
+    }
+
+    impl SimJtagSpi {
+        fn new() -> Self {
+            Self {
+                memory: vec![0xff; FLASH_SIZE],
+                sfdp: test_sfdp(),
+                write_enable: false,
+                received: BitVec::new(),
+                mosi: Vec::new(),
+                miso: 0,
+                tdo: None,
+            }
+        }
+
+        fn addr(&self) -> usize {
+            u32::from_be_bytes([0, self.mosi[1], self.mosi[2], self.mosi[3]]) as usize
+        }
+
+        // byte returned at `index` in the current transaction
+        fn response(&self, index: usize) -> u8 {
+            match self.mosi.first() {
+                Some(0x9f) if index <= 3 => [0xef, 0x40, 0x14][index - 1],
+                Some(0x05) => (self.write_enable as u8) << 1,
+                Some(0x03) if index >= 4 => self.memory[self.addr() + index - 4],
+                Some(0x5a) if index >= 5 => {
+                    *self.sfdp.get(self.addr() + index - 5).unwrap_or(&0xff)
+                }
+                _ => 0,
+            }
+        }
+
+        // end of the transaction, i.e. CS going high
+        fn execute(&mut self) {
+            let erase = match self.mosi[0] {
+                0x06 => {
+                    self.write_enable = true;
+                    return;
+                }
+                0x20 => 0x1000,
+                0xd8 => 0x10000,
+                0xc7 => FLASH_SIZE,
+                0x02 => 0,
+                _ => return,
+            };
+            if !self.write_enable {
+                return;
+            }
+            self.write_enable = false;
+            if self.mosi[0] == 0x02 {
+                let addr = self.addr();
+                for (i, x) in self.mosi[4..].iter().enumerate() {
+                    // wraps around within the page
+                    let i = addr & !0xff | (addr + i) & 0xff;
+                    self.memory[i] &= x;
+                }
+            } else {
+                let addr = if erase == FLASH_SIZE { 0 } else { self.addr() };
+                let addr = addr / erase * erase;
+                self.memory[addr..addr + erase].fill(0xff);
+            }
+        }
+    }
+
+    impl drivers::XilinxSimulatedDevice for SimJtagSpi {
+        fn capture_dr(&mut self, _ir: &BitSlice) -> BitVec {
+            self.received.clear();
+            self.mosi.clear();
+            bitvec![0]
+        }
+        fn shift_dr(&mut self, ir: &BitSlice, tdi: bool) {
+            if ir.load_le::<u8>() != 0x02 {
+                return;
+            }
+            self.received.push(tdi);
+            let n = self.received.len();
+            if n < 34 || !self.received[0] {
+                return;
+            }
+            let spi_len = self.received[1..33]
+                .iter()
+                .by_vals()
+                .fold(0, |acc, x| acc << 1 | x as usize)
+                + 1;
+            let bit = n - 34;
+            if bit >= spi_len {
+                return;
+            }
+
+            if bit.is_multiple_of(8) {
+                self.miso = self.response(bit / 8);
+                self.mosi.push(0);
+            }
+            *self.mosi.last_mut().unwrap() |= (tdi as u8) << (7 - bit % 8);
+            self.tdo = Some(self.miso & (0x80 >> (bit % 8)) != 0);
+            if bit == spi_len - 1 {
+                self.execute();
+            }
+        }
+        fn shift_dr_out(&mut self, _ir: &BitSlice) -> Option<bool> {
+            self.tdo.take()
+        }
+        fn update_dr(&mut self, _ir: &BitSlice, _dr: &BitSlice) {}
+    }
+
+    #[test]
+    fn test_spiflash_sfdp() {
+        let info = SpiFlashInfo::parse_sfdp([0xef, 0x40, 0x14], &test_sfdp()).unwrap();
+        assert_eq!(info.size, FLASH_SIZE);
+        assert_eq!(info.page_size, 256);
+        assert_eq!(
+            info.erase_types,
+            [
+                SpiEraseType {
+                    size: 0x1000,
+                    opcode: 0x20
+                },
+                SpiEraseType {
+                    size: 0x10000,
+                    opcode: 0xd8
+                }
+            ]
+        );
+        assert!(!info.address_4byte());
+        assert!(SpiFlashInfo::parse_sfdp([0; 3], &test_sfdp()[..40]).is_err());
+        assert!(SpiFlashInfo::parse_sfdp([0; 3], &[0xff; 64]).is_err());
+
+        // sizes which do not fit
+        let mut sfdp = test_sfdp();
+        sfdp[20..24].copy_from_slice(&0x8000_0040u32.to_le_bytes());
+        assert!(SpiFlashInfo::parse_sfdp([0; 3], &sfdp).is_err());
+        let mut sfdp = test_sfdp();
+        sfdp[44] = 0x40;
+        assert!(SpiFlashInfo::parse_sfdp([0; 3], &sfdp).is_err());
+    }
+
+    #[test]
+    fn test_spiflash_program() {
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(SimJtagSpi::new())]);
+        let spi = JtagSpi::new(Bscan::new(BscanUser::User1));
+        assert_eq!(
+            SpiFlash::read_jedec_id(&spi, &mut adapter).unwrap(),
+            [0xef, 0x40, 0x14]
+        );
+        assert!(spi.transfer(&mut adapter, &[], 0).is_err());
+        let flash = SpiFlash::probe(spi, &mut adapter).unwrap();
+        assert_eq!(flash.info().size, FLASH_SIZE);
+
+        let data = (0..0x234).map(|x| (x * 7) as u8).collect::<Vec<_>>();
+        flash.program_pages(&mut adapter, 0x20, &[0]).unwrap();
+        flash.program(&mut adapter, 0xff80, &data).unwrap();
+        assert_eq!(flash.read(&mut adapter, 0xff80, 0x234).unwrap(), data);
+        // first 4 KiB block was left alone
+        assert_eq!(flash.read(&mut adapter, 0x20, 1).unwrap(), [0]);
+        assert!(flash.verify(&mut adapter, 0xff7f, &data).is_err());
+
+        flash.erase(&mut adapter, 0x10000, 0x10000).unwrap();
+        assert_eq!(
+            flash.read(&mut adapter, 0xfffe, 4).unwrap(),
+            [0x72, 0x79, 0xff, 0xff]
+        );
+
+        flash.erase_chip(&mut adapter).unwrap();
+        assert_eq!(flash.read(&mut adapter, 0x20, 1).unwrap(), [0xff]);
+        assert!(flash.read(&mut adapter, FLASH_SIZE - 1, 2).is_err());
+    }
+}
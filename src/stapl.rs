@@ -0,0 +1,1882 @@
+use crate::*;
+
+use bitvec::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// A procedure run by a [StaplAction]
+pub struct StaplActionStep {
+    pub procedure: String,
+    /// Optional procedures are skipped unless they are enabled
+    pub optional: bool,
+    /// Recommended procedures are run unless they are disabled
+    pub recommended: bool,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// An ACTION statement, i.e. something that the program can do such as
+/// PROGRAM or VERIFY
+pub struct StaplAction {
+    pub name: String,
+    pub description: Option<String>,
+    pub procedures: Vec<StaplActionStep>,
+}
+
+#[derive(Clone, Debug)]
+/// A parsed STAPL (JESD71) or Jam source file
+///
+/// Only the source form (.jam/.stp) is supported, not the byte-code form
+/// (.jbc). Boolean arrays may be initialized using BIN, HEX or ACA data but
+/// not RLC data, and the VECTOR/VMAP and PUSH/POP statements are rejected.
+pub struct Stapl {
+    /// Key/value pairs from NOTE statements
+    pub notes: Vec<(String, String)>,
+    pub actions: Vec<StaplAction>,
+    statements: Vec<Statement>,
+    labels: HashMap<String, usize>,
+    blocks: Vec<Block>,
+}
+
+fn parse_err(line: usize, msg: impl Into<String>) -> JTAGError {
+    ParseError::new("STAPL", msg).at_line(line).into()
+}
+
+fn run_err(line: usize, msg: impl Into<String>) -> JTAGError {
+    JTAGError::InvalidAction(format!("STAPL line {line}: {}", msg.into()))
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Ident(String),
+    Int(i32),
+    Str(String),
+    Bits(BitVec),
+    Sym(&'static str),
+}
+
+// Longer symbols have to come first
+const SYMBOLS: [&str; 28] = [
+    "==", "!=", "<=", ">=", "<<", ">>", "&&", "||", "..", "<", ">", "+", "-", "*", "/", "%", "&",
+    "|", "^", "~", "!", "=", "(", ")", "[", "]", ",", ":",
+];
+
+const KEYWORDS: [&str; 31] = [
+    "ACTION",
+    "BOOLEAN",
+    "CALL",
+    "CRC",
+    "DATA",
+    "DRSCAN",
+    "DRSTOP",
+    "ENDDATA",
+    "ENDPROC",
+    "EXIT",
+    "EXPORT",
+    "FOR",
+    "FREQUENCY",
+    "GOTO",
+    "IF",
+    "INTEGER",
+    "IRSCAN",
+    "IRSTOP",
+    "LET",
+    "NEXT",
+    "NOTE",
+    "POSTDR",
+    "POSTIR",
+    "PREDR",
+    "PREIR",
+    "PRINT",
+    "PROCEDURE",
+    "STATE",
+    "WAIT",
+    "PUSH",
+    "POP",
+];
+
+/// Parse binary or hex digits where the rightmost digit holds bit 0
+fn parse_bits(line: usize, hex: bool, digits: &str) -> Result<BitVec, JTAGError> {
+    let mut ret = BitVec::new();
+    for c in digits.chars().rev() {
+        let (value, width) = if hex {
+            (c.to_digit(16), 4)
+        } else {
+            (c.to_digit(2), 1)
+        };
+        let value = value.ok_or_else(|| parse_err(line, format!("invalid digit {c:?}")))?;
+        for i in 0..width {
+            ret.push(value & (1 << i) != 0);
+        }
+    }
+    Ok(ret)
+}
+
+fn tokenize(line: usize, s: &str) -> Result<Vec<Token>, JTAGError> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut ret = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' {
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err(parse_err(line, "unterminated string"));
+            }
+            ret.push(Token::Str(chars[start + 1..i].iter().collect()));
+            i += 1;
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            let s = chars[start..i].iter().collect::<String>();
+            let value = s
+                .parse::<u32>()
+                .map_err(|_| parse_err(line, format!("invalid number {s}")))?;
+            ret.push(Token::Int(value as i32));
+        } else if c == '#' || c == '$' {
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            let digits = chars[start + 1..i].iter().collect::<String>();
+            ret.push(Token::Bits(parse_bits(line, c == '$', &digits)?));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            // for CHR$
+            if i < chars.len() && chars[i] == '$' {
+                i += 1;
+            }
+            let s = chars[start..i].iter().collect::<String>();
+            ret.push(Token::Ident(s.to_ascii_uppercase()));
+        } else {
+            let rest = chars[i..chars.len().min(i + 2)].iter().collect::<String>();
+            let sym = SYMBOLS
+                .iter()
+                .find(|sym| rest.starts_with(*sym))
+                .ok_or_else(|| parse_err(line, format!("unexpected character {c:?}")))?;
+            ret.push(Token::Sym(sym));
+            i += sym.len();
+        }
+    }
+    Ok(ret)
+}
+
+// Strip comments and split on `;`, keeping track of the line that each
+// statement starts on
+fn split_statements(src: &str) -> Result<Vec<(usize, String)>, JTAGError> {
+    let mut ret = Vec::new();
+    let mut cur = String::new();
+    let mut start = None;
+
+    for (i, line) in src.lines().enumerate() {
+        let mut in_string = false;
+        for c in line.chars() {
+            if in_string {
+                in_string = c != '"';
+                cur.push(c);
+                continue;
+            }
+            match c {
+                '\'' => break,
+                ';' => {
+                    if let Some(start) = start.take() {
+                        ret.push((start, cur.split_off(0)));
+                    }
+                    cur.clear();
+                }
+                _ => {
+                    if start.is_none() && !c.is_whitespace() {
+                        start = Some(i + 1);
+                    }
+                    in_string = c == '"';
+                    cur.push(c);
+                }
+            }
+        }
+        cur.push(' ');
+    }
+
+    if let Some(start) = start {
+        return Err(parse_err(start, "missing ;"));
+    }
+    Ok(ret)
+}
+
+// Boolean arrays can be initialized with data that doesn't consist of
+// normal tokens, e.g. `BOOLEAN x[8] = HEX A5;`. Returns the statement up to
+// the `=` as well as the format and data if this is the case.
+fn split_raw_init(stmt: &str) -> (&str, Option<(String, String)>) {
+    if let Some((decl, init)) = stmt.split_once('=') {
+        let init = init.trim_start();
+        if let Some((format, data)) = init.split_once(char::is_whitespace) {
+            let format = format.to_ascii_uppercase();
+            if ["BIN", "HEX", "RLC", "ACA"].contains(&format.as_str()) {
+                let data = data.chars().filter(|c| !c.is_whitespace()).collect();
+                return (decl, Some((format, data)));
+            }
+        }
+    }
+    (stmt, None)
+}
+
+fn bits_required(n: usize) -> usize {
+    if n == 0 {
+        1
+    } else {
+        (usize::BITS - n.leading_zeros()) as usize
+    }
+}
+
+/// Decode ACA compressed data, which is a stream of 6-bit characters (packed
+/// LSB first) holding a 32-bit length followed by literal bytes and back
+/// references, in the same way as the reference Jam player.
+fn decode_aca(line: usize, data: &str, stapl: bool) -> Result<BitVec, JTAGError> {
+    let mut packed = BitVec::<u8, Lsb0>::new();
+    for c in data.chars() {
+        let value = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'A'..='Z' => c as u32 - 'A' as u32 + 10,
+            'a'..='z' => c as u32 - 'a' as u32 + 36,
+            '@' => 62,
+            '#' => 63,
+            _ => return Err(run_err(line, format!("invalid ACA character {c:?}"))),
+        };
+        for i in 0..6 {
+            packed.push(value & (1 << i) != 0);
+        }
+    }
+
+    let mut pos = 0;
+    let mut read = |bits: usize| {
+        if pos + bits > packed.len() {
+            return Err(run_err(line, "ACA data is truncated"));
+        }
+        let ret = packed[pos..pos + bits].load_le::<u32>() as usize;
+        pos += bits;
+        Ok(ret)
+    };
+
+    // Jam 1.1 and STAPL differ in the maximum back reference distance
+    let max_offset = if stapl { 8191 } else { 8192 };
+    let len = read(32)?;
+    let mut out = Vec::<u8>::new();
+    while out.len() < len {
+        if read(1)? == 0 {
+            for _ in 0..3 {
+                if out.len() < len {
+                    out.push(read(8)? as u8);
+                }
+            }
+        } else {
+            let offset = read(bits_required(out.len().min(max_offset)))?;
+            let count = read(8)?;
+            if offset == 0 || offset > out.len() {
+                return Err(run_err(line, "invalid ACA back reference"));
+            }
+            for _ in 0..count {
+                if out.len() < len {
+                    out.push(out[out.len() - offset]);
+                }
+            }
+        }
+    }
+    Ok(out.view_bits::<Lsb0>().iter().by_vals().collect())
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Expr {
+    Int(i32),
+    Bits(BitVec),
+    Var(String),
+    Index(String, Box<Expr>),
+    Slice(String, Box<Expr>, Box<Expr>),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Func(String, Box<Expr>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Init {
+    Exprs(Vec<Expr>),
+    Raw(String, String),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum PrintItem {
+    Text(String),
+    Chr(Expr),
+    Expr(Expr),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Stmt {
+    Action,
+    Note,
+    Crc,
+    Procedure(String),
+    EndProc,
+    Data(String),
+    EndData,
+    Boolean {
+        name: String,
+        size: Option<Expr>,
+        init: Option<Init>,
+    },
+    Integer {
+        name: String,
+        size: Option<Expr>,
+        init: Option<Init>,
+    },
+    Let(Expr, Expr),
+    If(Expr, Box<Stmt>),
+    For {
+        var: String,
+        from: Expr,
+        to: Expr,
+        step: Option<Expr>,
+    },
+    Next(String),
+    Goto(String),
+    Call(String),
+    Exit(Expr),
+    Print(Vec<PrintItem>),
+    Export(String, Expr),
+    Scan {
+        ir: bool,
+        len: Expr,
+        data: Expr,
+        capture: Option<Expr>,
+        compare: Option<(Expr, Expr, Expr)>,
+    },
+    Stop {
+        ir: bool,
+        state: JTAGState,
+    },
+    State(Vec<JTAGState>),
+    Wait {
+        wait_state: Option<JTAGState>,
+        cycles: Option<Expr>,
+        usecs: Option<Expr>,
+        end_state: Option<JTAGState>,
+    },
+    Frequency(Option<Expr>),
+    Padding {
+        ir: bool,
+        pre: bool,
+        len: Expr,
+        data: Option<Expr>,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Statement {
+    line: usize,
+    stmt: Stmt,
+}
+
+// A PROCEDURE or DATA block, from its header to its ENDPROC or ENDDATA
+#[derive(Clone, Debug)]
+struct Block {
+    name: String,
+    data: bool,
+    start: usize,
+    end: usize,
+}
+
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | "<=" | ">" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    })
+}
+
+struct Parser {
+    line: usize,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn err(&self, msg: impl Into<String>) -> JTAGError {
+        parse_err(self.line, msg)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, JTAGError> {
+        let ret = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| self.err("unexpected end of statement"))?;
+        self.pos += 1;
+        Ok(ret)
+    }
+
+    fn eat_sym(&mut self, sym: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Sym(x)) if *x == sym) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(x)) if x == keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_sym(&mut self, sym: &str) -> Result<(), JTAGError> {
+        if !self.eat_sym(sym) {
+            return Err(self.err(format!("expected {sym}")));
+        }
+        Ok(())
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), JTAGError> {
+        if !self.eat_keyword(keyword) {
+            return Err(self.err(format!("expected {keyword}")));
+        }
+        Ok(())
+    }
+
+    fn ident(&mut self) -> Result<String, JTAGError> {
+        match self.next()? {
+            Token::Ident(x) => Ok(x),
+            x => Err(self.err(format!("expected a name, got {x:?}"))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JTAGError> {
+        match self.next()? {
+            Token::Str(x) => Ok(x),
+            x => Err(self.err(format!("expected a string, got {x:?}"))),
+        }
+    }
+
+    fn state(&mut self) -> Result<JTAGState, JTAGError> {
+        let name = self.ident()?;
+        svf::try_parse_state(&name).ok_or_else(|| self.err(format!("unknown state {name}")))
+    }
+
+    fn done(&self) -> Result<(), JTAGError> {
+        match self.peek() {
+            Some(x) => Err(self.err(format!("unexpected {x:?}"))),
+            None => Ok(()),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, JTAGError> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, min_prec: u8) -> Result<Expr, JTAGError> {
+        let mut lhs = self.unary()?;
+        while let Some(&Token::Sym(op)) = self.peek() {
+            match precedence(op) {
+                Some(prec) if prec >= min_prec => {
+                    self.pos += 1;
+                    let rhs = self.binary(prec + 1)?;
+                    lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+                }
+                _ => break,
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, JTAGError> {
+        Ok(match self.next()? {
+            Token::Sym(op @ ("-" | "!" | "~")) => Expr::Unary(op, Box::new(self.unary()?)),
+            Token::Sym("(") => {
+                let ret = self.expr()?;
+                self.expect_sym(")")?;
+                ret
+            }
+            Token::Int(x) => Expr::Int(x),
+            Token::Bits(x) => Expr::Bits(x),
+            Token::Ident(name) => {
+                if self.eat_sym("(") {
+                    let arg = self.expr()?;
+                    self.expect_sym(")")?;
+                    Expr::Func(name, Box::new(arg))
+                } else if self.eat_sym("[") {
+                    let index = self.expr()?;
+                    let ret = if self.eat_sym("..") {
+                        Expr::Slice(name, Box::new(index), Box::new(self.expr()?))
+                    } else {
+                        Expr::Index(name, Box::new(index))
+                    };
+                    self.expect_sym("]")?;
+                    ret
+                } else {
+                    Expr::Var(name)
+                }
+            }
+            x => return Err(self.err(format!("unexpected {x:?}"))),
+        })
+    }
+
+    fn lvalue(&mut self) -> Result<Expr, JTAGError> {
+        match self.unary()? {
+            x @ (Expr::Var(..) | Expr::Index(..) | Expr::Slice(..)) => Ok(x),
+            x => Err(self.err(format!("cannot assign to {x:?}"))),
+        }
+    }
+
+    fn declaration(
+        &mut self,
+        raw: Option<(String, String)>,
+    ) -> Result<(String, Option<Expr>, Option<Init>), JTAGError> {
+        let name = self.ident()?;
+        let size = if self.eat_sym("[") {
+            let size = self.expr()?;
+            self.expect_sym("]")?;
+            Some(size)
+        } else {
+            None
+        };
+        let init = if let Some((format, data)) = raw {
+            Some(Init::Raw(format, data))
+        } else if self.eat_sym("=") {
+            let mut exprs = vec![self.expr()?];
+            while self.eat_sym(",") {
+                exprs.push(self.expr()?);
+            }
+            Some(Init::Exprs(exprs))
+        } else {
+            None
+        };
+        Ok((name, size, init))
+    }
+
+    fn scan(&mut self, ir: bool) -> Result<Stmt, JTAGError> {
+        let len = self.expr()?;
+        self.expect_sym(",")?;
+        let data = self.expr()?;
+        let mut capture = None;
+        let mut compare = None;
+        while self.eat_sym(",") {
+            if self.eat_keyword("CAPTURE") {
+                capture = Some(self.lvalue()?);
+            } else {
+                self.expect_keyword("COMPARE")?;
+                let expected = self.expr()?;
+                self.expect_sym(",")?;
+                let mask = self.expr()?;
+                self.expect_sym(",")?;
+                compare = Some((expected, mask, self.lvalue()?));
+            }
+        }
+        Ok(Stmt::Scan {
+            ir,
+            len,
+            data,
+            capture,
+            compare,
+        })
+    }
+
+    fn wait(&mut self) -> Result<Stmt, JTAGError> {
+        let mut wait_state = None;
+        let mut cycles = None;
+        let mut usecs = None;
+        let mut end_state = None;
+        loop {
+            match self.peek() {
+                Some(Token::Ident(x)) if svf::try_parse_state(x).is_some() => {
+                    let state = self.state()?;
+                    if cycles.is_none() && usecs.is_none() && wait_state.is_none() {
+                        wait_state = Some(state);
+                    } else {
+                        end_state = Some(state);
+                    }
+                }
+                _ => {
+                    let value = self.expr()?;
+                    if self.eat_keyword("CYCLES") {
+                        cycles = Some(value);
+                    } else {
+                        self.expect_keyword("USEC")?;
+                        usecs = Some(value);
+                    }
+                }
+            }
+            if !self.eat_sym(",") {
+                break;
+            }
+        }
+        Ok(Stmt::Wait {
+            wait_state,
+            cycles,
+            usecs,
+            end_state,
+        })
+    }
+
+    /// Parse a statement (without a label). ACTION and NOTE statements are
+    /// returned separately since they don't do anything when executed.
+    fn statement(
+        &mut self,
+        raw: Option<(String, String)>,
+        actions: &mut Vec<StaplAction>,
+        notes: &mut Vec<(String, String)>,
+    ) -> Result<Stmt, JTAGError> {
+        let keyword = self.ident()?;
+        // LET is optional
+        if !KEYWORDS.contains(&keyword.as_str())
+            && matches!(self.peek(), Some(Token::Sym("=" | "[")))
+        {
+            self.pos -= 1;
+            let target = self.lvalue()?;
+            self.expect_sym("=")?;
+            return Ok(Stmt::Let(target, self.expr()?));
+        }
+        if raw.is_some() && keyword != "BOOLEAN" {
+            return Err(self.err(format!("{keyword} cannot have array data")));
+        }
+
+        let stmt = match keyword.as_str() {
+            "ACTION" => {
+                let name = self.ident()?;
+                let description = match self.peek() {
+                    Some(Token::Str(..)) => Some(self.string()?),
+                    _ => None,
+                };
+                self.expect_sym("=")?;
+                let mut procedures = Vec::new();
+                loop {
+                    let procedure = self.ident()?;
+                    let optional = self.eat_keyword("OPTIONAL");
+                    let recommended = self.eat_keyword("RECOMMENDED");
+                    procedures.push(StaplActionStep {
+                        procedure,
+                        optional: optional || recommended,
+                        recommended,
+                    });
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                actions.push(StaplAction {
+                    name,
+                    description,
+                    procedures,
+                });
+                Stmt::Action
+            }
+            "NOTE" => {
+                let key = self.string()?;
+                let value = match self.peek() {
+                    Some(Token::Str(..)) => self.string()?,
+                    _ => String::new(),
+                };
+                notes.push((key, value));
+                Stmt::Note
+            }
+            "PROCEDURE" => {
+                let name = self.ident()?;
+                // Everything is global, so the names used don't matter
+                if self.eat_keyword("USES") {
+                    self.ident()?;
+                    while self.eat_sym(",") {
+                        self.ident()?;
+                    }
+                }
+                Stmt::Procedure(name)
+            }
+            "ENDPROC" => Stmt::EndProc,
+            "DATA" => Stmt::Data(self.ident()?),
+            "ENDDATA" => Stmt::EndData,
+            "BOOLEAN" => {
+                let (name, size, init) = self.declaration(raw)?;
+                Stmt::Boolean { name, size, init }
+            }
+            "INTEGER" => {
+                let (name, size, init) = self.declaration(None)?;
+                Stmt::Integer { name, size, init }
+            }
+            "LET" => {
+                let target = self.lvalue()?;
+                self.expect_sym("=")?;
+                Stmt::Let(target, self.expr()?)
+            }
+            "IF" => {
+                let cond = self.expr()?;
+                self.expect_keyword("THEN")?;
+                let stmt = self.statement(None, actions, notes)?;
+                Stmt::If(cond, Box::new(stmt))
+            }
+            "FOR" => {
+                let var = self.ident()?;
+                self.expect_sym("=")?;
+                let from = self.expr()?;
+                self.expect_keyword("TO")?;
+                let to = self.expr()?;
+                let step = if self.eat_keyword("STEP") {
+                    Some(self.expr()?)
+                } else {
+                    None
+                };
+                Stmt::For {
+                    var,
+                    from,
+                    to,
+                    step,
+                }
+            }
+            "NEXT" => Stmt::Next(self.ident()?),
+            "GOTO" => Stmt::Goto(self.ident()?),
+            "CALL" => Stmt::Call(self.ident()?),
+            "EXIT" => Stmt::Exit(self.expr()?),
+            "PRINT" => {
+                let mut items = Vec::new();
+                while self.peek().is_some() {
+                    items.push(match self.peek() {
+                        Some(Token::Str(..)) => PrintItem::Text(self.string()?),
+                        Some(Token::Ident(x)) if x == "CHR$" => {
+                            self.pos += 1;
+                            self.expect_sym("(")?;
+                            let value = self.expr()?;
+                            self.expect_sym(")")?;
+                            PrintItem::Chr(value)
+                        }
+                        _ => PrintItem::Expr(self.expr()?),
+                    });
+                    if !self.eat_sym(",") {
+                        break;
+                    }
+                }
+                Stmt::Print(items)
+            }
+            "EXPORT" => {
+                let key = self.string()?;
+                self.expect_sym(",")?;
+                Stmt::Export(key, self.expr()?)
+            }
+            "IRSCAN" => self.scan(true)?,
+            "DRSCAN" => self.scan(false)?,
+            "IRSTOP" | "DRSTOP" => {
+                let state = self.state()?;
+                if !svf::is_stable_state(state) {
+                    return Err(self.err(format!("{state:?} is not a stable state")));
+                }
+                Stmt::Stop {
+                    ir: keyword == "IRSTOP",
+                    state,
+                }
+            }
+            "STATE" => {
+                let mut states = Vec::new();
+                while self.peek().is_some() {
+                    states.push(self.state()?);
+                    self.eat_sym(",");
+                }
+                Stmt::State(states)
+            }
+            "WAIT" => self.wait()?,
+            "FREQUENCY" => Stmt::Frequency(if self.peek().is_some() {
+                Some(self.expr()?)
+            } else {
+                None
+            }),
+            "PREIR" | "POSTIR" | "PREDR" | "POSTDR" => {
+                let len = self.expr()?;
+                let data = if self.eat_sym(",") {
+                    Some(self.expr()?)
+                } else {
+                    None
+                };
+                Stmt::Padding {
+                    ir: keyword.ends_with("IR"),
+                    pre: keyword.starts_with("PRE"),
+                    len,
+                    data,
+                }
+            }
+            "PUSH" | "POP" | "VECTOR" | "VMAP" => {
+                return Err(self.err(format!("{keyword} is not supported")))
+            }
+            _ => return Err(self.err(format!("unknown statement {keyword}"))),
+        };
+        Ok(stmt)
+    }
+}
+
+impl Stapl {
+    /// Parse the contents of a STAPL or Jam file
+    pub fn parse(src: &str) -> Result<Self, JTAGError> {
+        let mut ret = Self {
+            notes: Vec::new(),
+            actions: Vec::new(),
+            statements: Vec::new(),
+            labels: HashMap::new(),
+            blocks: Vec::new(),
+        };
+        let mut block: Option<Block> = None;
+
+        for (line, text) in split_statements(src)? {
+            let text = text.trim();
+            // The CRC is written in hex without a prefix
+            if text
+                .split_whitespace()
+                .next()
+                .is_some_and(|x| x.eq_ignore_ascii_case("CRC"))
+            {
+                ret.statements.push(Statement {
+                    line,
+                    stmt: Stmt::Crc,
+                });
+                continue;
+            }
+
+            let (text, raw) = split_raw_init(text);
+            let mut parser = Parser {
+                line,
+                tokens: tokenize(line, text)?,
+                pos: 0,
+            };
+            if let (Some(Token::Ident(label)), Some(Token::Sym(":"))) =
+                (parser.tokens.first(), parser.tokens.get(1))
+            {
+                if ret
+                    .labels
+                    .insert(label.clone(), ret.statements.len())
+                    .is_some()
+                {
+                    return Err(parse_err(line, format!("duplicate label {label}")));
+                }
+                parser.pos = 2;
+            }
+            let stmt = parser.statement(raw, &mut ret.actions, &mut ret.notes)?;
+            parser.done()?;
+
+            let index = ret.statements.len();
+            match (&stmt, &block) {
+                (Stmt::Procedure(name) | Stmt::Data(name), None) => {
+                    block = Some(Block {
+                        name: name.clone(),
+                        data: matches!(stmt, Stmt::Data(..)),
+                        start: index,
+                        end: index,
+                    });
+                }
+                (Stmt::EndProc, Some(Block { data: false, .. }))
+                | (Stmt::EndData, Some(Block { data: true, .. })) => {
+                    let mut block = block.take().unwrap();
+                    block.end = index;
+                    ret.blocks.push(block);
+                }
+                (Stmt::Procedure(..) | Stmt::Data(..) | Stmt::EndProc | Stmt::EndData, _) => {
+                    return Err(parse_err(line, "mismatched PROCEDURE or DATA block"));
+                }
+                _ => {}
+            }
+            ret.statements.push(Statement { line, stmt });
+        }
+
+        if let Some(block) = block {
+            return Err(parse_err(
+                ret.statements[block.start].line,
+                format!("{} is not terminated", block.name),
+            ));
+        }
+        for action in &ret.actions {
+            for step in &action.procedures {
+                if ret.procedure(&step.procedure).is_none() {
+                    return Err(ParseError::new(
+                        "STAPL",
+                        format!(
+                            "action {} uses unknown procedure {}",
+                            action.name, step.procedure
+                        ),
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    fn procedure(&self, name: &str) -> Option<&Block> {
+        self.blocks.iter().find(|x| !x.data && x.name == name)
+    }
+
+    /// Get the meaning of a standard exit code
+    pub fn exit_code_description(code: i32) -> Option<&'static str> {
+        Some(match code {
+            0 => "success",
+            1 => "checking chain failure",
+            2 => "reading IDCODE failure",
+            3 => "reading USERCODE failure",
+            4 => "reading UESCODE failure",
+            5 => "entering ISP failure",
+            6 => "unrecognized device",
+            7 => "device revision is not supported",
+            8 => "erase failure",
+            9 => "device is not blank",
+            10 => "device programming failure",
+            11 => "device verify failure",
+            12 => "read failure",
+            13 => "calculating checksum failure",
+            14 => "setting security bit failure",
+            15 => "querying security bit failure",
+            16 => "exiting ISP failure",
+            17 => "performing system test failure",
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Var {
+    Int(i32),
+    Bool(bool),
+    IntArray(Vec<i32>),
+    BoolArray(BitVec),
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Int(i32),
+    Bool(bool),
+    Bits(BitVec),
+}
+
+enum Step {
+    Next,
+    Goto(usize),
+    Exit(i32),
+}
+
+struct Loop {
+    var: String,
+    to: i32,
+    step: i32,
+    body: usize,
+}
+
+/// Runs the actions of a [Stapl] program on a [JTAGAdapter]
+///
+/// IRSCAN and DRSCAN are queued as buffered actions unless their data is
+/// captured or compared, in which case they are executed immediately.
+/// Cycles in WAIT are clocked with TMS held (so that the TAP stays in the
+/// wait state) and microseconds become [JTAGAction::DelayNS].
+pub struct StaplPlayer<'a> {
+    stapl: &'a Stapl,
+    enabled: HashMap<String, bool>,
+    vars: HashMap<String, Var>,
+    output: Vec<String>,
+    exports: Vec<(String, String)>,
+    line: usize,
+    irstop: JTAGState,
+    drstop: JTAGState,
+    preir: BitVec,
+    postir: BitVec,
+    predr: BitVec,
+    postdr: BitVec,
+    tap: svf::PauseTracker,
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Int(x) => x.to_string(),
+        Value::Bool(x) => (*x as u8).to_string(),
+        Value::Bits(x) => svf::format_hex(x),
+    }
+}
+
+impl<'a> StaplPlayer<'a> {
+    pub fn new(stapl: &'a Stapl) -> Self {
+        Self {
+            stapl,
+            enabled: HashMap::new(),
+            vars: HashMap::new(),
+            output: Vec::new(),
+            exports: Vec::new(),
+            line: 0,
+            irstop: JTAGState::RunTestIdle,
+            drstop: JTAGState::RunTestIdle,
+            preir: BitVec::new(),
+            postir: BitVec::new(),
+            predr: BitVec::new(),
+            postdr: BitVec::new(),
+            tap: svf::PauseTracker::new(),
+        }
+    }
+
+    /// Enable an optional procedure or disable a recommended one
+    pub fn with_procedure(mut self, procedure: &str, enabled: bool) -> Self {
+        self.enabled.insert(procedure.to_ascii_uppercase(), enabled);
+        self
+    }
+
+    /// Lines printed using PRINT
+    pub fn output(&self) -> &[String] {
+        &self.output
+    }
+    /// Key/value pairs from EXPORT statements
+    pub fn exports(&self) -> &[(String, String)] {
+        &self.exports
+    }
+
+    /// Run the procedures of `action` and then flush the adapter. If the
+    /// program doesn't have any actions (i.e. it is a Jam 1.1 program), its
+    /// statements are run from the start instead and `action` must be `None`.
+    ///
+    /// Returns the code given to EXIT, or 0 if every procedure completed.
+    /// See [Stapl::exit_code_description] for the meaning of the standard
+    /// codes.
+    pub fn run<T: JTAGAdapter + ?Sized>(
+        &mut self,
+        adapter: &mut T,
+        action: Option<&str>,
+    ) -> Result<i32, JTAGError> {
+        let stapl = self.stapl;
+
+        let code = match action {
+            Some(name) => {
+                let action = stapl
+                    .actions
+                    .iter()
+                    .find(|x| x.name.eq_ignore_ascii_case(name))
+                    .ok_or_else(|| JTAGError::InvalidAction(format!("unknown action {name}")))?;
+
+                // Data blocks only contain declarations
+                let mut code = None;
+                for block in stapl.blocks.iter().filter(|x| x.data) {
+                    code = code.or(self.run_block(adapter, block.start + 1, block.end)?);
+                }
+                for step in &action.procedures {
+                    if code.is_some() {
+                        break;
+                    }
+                    let enabled = self
+                        .enabled
+                        .get(&step.procedure)
+                        .copied()
+                        .unwrap_or(!step.optional || step.recommended);
+                    if enabled {
+                        code = self.call(adapter, &step.procedure)?;
+                    }
+                }
+                code
+            }
+            None => {
+                if !stapl.actions.is_empty() {
+                    return Err(JTAGError::InvalidAction(
+                        "an action has to be selected".to_string(),
+                    ));
+                }
+                self.run_block(adapter, 0, stapl.statements.len())?
+            }
+        };
+
+        adapter.flush()?;
+        Ok(code.unwrap_or(0))
+    }
+
+    fn err(&self, msg: impl Into<String>) -> JTAGError {
+        run_err(self.line, msg)
+    }
+
+    fn call<T: JTAGAdapter + ?Sized>(
+        &mut self,
+        adapter: &mut T,
+        name: &str,
+    ) -> Result<Option<i32>, JTAGError> {
+        let block = self
+            .stapl
+            .procedure(name)
+            .ok_or_else(|| self.err(format!("unknown procedure {name}")))?;
+        self.run_block(adapter, block.start + 1, block.end)
+    }
+
+    // Run the statements in start..end, returning the exit code if EXIT was
+    // reached
+    fn run_block<T: JTAGAdapter + ?Sized>(
+        &mut self,
+        adapter: &mut T,
+        start: usize,
+        end: usize,
+    ) -> Result<Option<i32>, JTAGError> {
+        let stapl = self.stapl;
+        let mut loops = Vec::new();
+        let mut pc = start;
+
+        while pc < end {
+            let statement = &stapl.statements[pc];
+            self.line = statement.line;
+            pc = match self.exec(adapter, &statement.stmt, pc, &mut loops)? {
+                Step::Next => pc + 1,
+                Step::Goto(target) => {
+                    if target < start || target >= end {
+                        return Err(self.err("cannot jump out of the current procedure"));
+                    }
+                    target
+                }
+                Step::Exit(code) => return Ok(Some(code)),
+            };
+        }
+        Ok(None)
+    }
+
+    fn exec<T: JTAGAdapter + ?Sized>(
+        &mut self,
+        adapter: &mut T,
+        stmt: &Stmt,
+        pc: usize,
+        loops: &mut Vec<Loop>,
+    ) -> Result<Step, JTAGError> {
+        let stapl = self.stapl;
+        match stmt {
+            Stmt::Action | Stmt::Note | Stmt::Crc => {}
+            Stmt::Procedure(..) | Stmt::Data(..) => {
+                // only reached when running from the start
+                let block = stapl.blocks.iter().find(|x| x.start == pc).unwrap();
+                return Ok(Step::Goto(block.end + 1));
+            }
+            Stmt::EndProc | Stmt::EndData => unreachable!(),
+            Stmt::Boolean { name, size, init } => {
+                let var = match size {
+                    None => {
+                        let value = match init {
+                            None => false,
+                            Some(Init::Exprs(exprs)) if exprs.len() == 1 => {
+                                let value = self.eval(&exprs[0])?;
+                                self.to_bool(value)?
+                            }
+                            _ => return Err(self.err("invalid initial value")),
+                        };
+                        Var::Bool(value)
+                    }
+                    Some(size) => Var::BoolArray(self.bool_array(size, init.as_ref())?),
+                };
+                self.vars.insert(name.clone(), var);
+            }
+            Stmt::Integer { name, size, init } => {
+                let var = match size {
+                    None => {
+                        let value = match init {
+                            None => 0,
+                            Some(Init::Exprs(exprs)) if exprs.len() == 1 => {
+                                self.eval_int(&exprs[0])?
+                            }
+                            _ => return Err(self.err("invalid initial value")),
+                        };
+                        Var::Int(value)
+                    }
+                    Some(size) => {
+                        let size = self.eval_size(size)?;
+                        let mut values = match init {
+                            None => Vec::new(),
+                            Some(Init::Exprs(exprs)) => exprs
+                                .iter()
+                                .map(|x| self.eval_int(x))
+                                .collect::<Result<Vec<_>, _>>()?,
+                            Some(Init::Raw(..)) => return Err(self.err("invalid initial value")),
+                        };
+                        if values.len() > size {
+                            return Err(self.err("too many initial values"));
+                        }
+                        values.resize(size, 0);
+                        Var::IntArray(values)
+                    }
+                };
+                self.vars.insert(name.clone(), var);
+            }
+            Stmt::Let(target, value) => {
+                let value = self.eval(value)?;
+                self.assign(target, value)?;
+            }
+            Stmt::If(cond, stmt) => {
+                let cond = self.eval(cond)?;
+                if self.to_bool(cond)? {
+                    return self.exec(adapter, stmt, pc, loops);
+                }
+            }
+            Stmt::For {
+                var,
+                from,
+                to,
+                step,
+            } => {
+                let from = self.eval_int(from)?;
+                let to = self.eval_int(to)?;
+                let step = match step {
+                    Some(step) => self.eval_int(step)?,
+                    None => 1,
+                };
+                if step == 0 {
+                    return Err(self.err("FOR step cannot be 0"));
+                }
+                self.assign(&Expr::Var(var.clone()), Value::Int(from))?;
+                if (step > 0 && from > to) || (step < 0 && from < to) {
+                    // skip the loop entirely
+                    let mut depth = 0;
+                    for (i, statement) in stapl.statements.iter().enumerate().skip(pc + 1) {
+                        match &statement.stmt {
+                            Stmt::For { var: x, .. } if x == var => depth += 1,
+                            Stmt::Next(x) if x == var => {
+                                if depth == 0 {
+                                    return Ok(Step::Goto(i + 1));
+                                }
+                                depth -= 1;
+                            }
+                            _ => {}
+                        }
+                    }
+                    return Err(self.err(format!("FOR {var} has no NEXT")));
+                }
+                loops.push(Loop {
+                    var: var.clone(),
+                    to,
+                    step,
+                    body: pc + 1,
+                });
+            }
+            Stmt::Next(var) => {
+                while loops.last().is_some_and(|x| x.var != *var) {
+                    loops.pop();
+                }
+                let Some(l) = loops.last() else {
+                    return Err(self.err(format!("NEXT {var} without FOR")));
+                };
+                let (to, step, body) = (l.to, l.step, l.body);
+                let value = self.eval_int(&Expr::Var(var.clone()))?.wrapping_add(step);
+                self.assign(&Expr::Var(var.clone()), Value::Int(value))?;
+                if (step > 0 && value <= to) || (step < 0 && value >= to) {
+                    return Ok(Step::Goto(body));
+                }
+                loops.pop();
+            }
+            Stmt::Goto(label) => {
+                let target = stapl
+                    .labels
+                    .get(label)
+                    .ok_or_else(|| self.err(format!("unknown label {label}")))?;
+                return Ok(Step::Goto(*target));
+            }
+            Stmt::Call(name) => {
+                let line = self.line;
+                let code = self.call(adapter, name)?;
+                self.line = line;
+                if let Some(code) = code {
+                    return Ok(Step::Exit(code));
+                }
+            }
+            Stmt::Exit(code) => return Ok(Step::Exit(self.eval_int(code)?)),
+            Stmt::Print(items) => {
+                let mut line = String::new();
+                for item in items {
+                    match item {
+                        PrintItem::Text(x) => line.push_str(x),
+                        PrintItem::Chr(x) => {
+                            let x = self.eval_int(x)?;
+                            line.push(
+                                char::from_u32(x as u32)
+                                    .ok_or_else(|| self.err(format!("invalid character {x}")))?,
+                            );
+                        }
+                        PrintItem::Expr(x) => line.push_str(&format_value(&self.eval(x)?)),
+                    }
+                }
+                self.output.push(line);
+            }
+            Stmt::Export(key, value) => {
+                let value = format_value(&self.eval(value)?);
+                self.exports.push((key.clone(), value));
+            }
+            Stmt::Scan {
+                ir,
+                len,
+                data,
+                capture,
+                compare,
+            } => {
+                let len = self.eval_size(len)?;
+                let data = self.eval_bits(data, len)?;
+                let captured =
+                    self.scan(adapter, *ir, &data, capture.is_some() || compare.is_some())?;
+                if let (Some(captured), Some(target)) = (&captured, capture) {
+                    self.store_bits(target, captured)?;
+                }
+                if let (Some(captured), Some((expected, mask, result))) = (&captured, compare) {
+                    let expected = self.eval_bits(expected, len)?;
+                    let mask = self.eval_bits(mask, len)?;
+                    let ok = (0..len).all(|i| !mask[i] || captured[i] == expected[i]);
+                    self.assign(result, Value::Bool(ok))?;
+                }
+            }
+            Stmt::Stop { ir: true, state } => self.irstop = *state,
+            Stmt::Stop { ir: false, state } => self.drstop = *state,
+            Stmt::State(states) => {
+                for &state in states {
+                    self.tap.go(adapter, state);
+                }
+            }
+            Stmt::Wait {
+                wait_state,
+                cycles,
+                usecs,
+                end_state,
+            } => {
+                let wait_state = wait_state.unwrap_or(JTAGState::RunTestIdle);
+                let end_state = end_state.unwrap_or(wait_state);
+                self.tap.go(adapter, wait_state);
+                if let Some(cycles) = cycles {
+                    let cycles = self.eval_size(cycles)?;
+                    if wait_state == JTAGState::TestLogicReset {
+                        for _ in 0..cycles.div_ceil(5) {
+                            adapter.reset_to_tlr();
+                        }
+                    } else if svf::is_stable_state(wait_state) {
+                        adapter.shift_bits_out(&BitVec::repeat(false, cycles), false);
+                    } else {
+                        return Err(self.err(format!("cannot wait in {wait_state:?}")));
+                    }
+                }
+                if let Some(usecs) = usecs {
+                    let usecs = self.eval_size(usecs)?;
+                    adapter.queue_action(JTAGAction::DelayNS(usecs as u64 * 1000));
+                }
+                self.tap.go(adapter, end_state);
+            }
+            Stmt::Frequency(hz) => {
+                if let Some(hz) = hz {
+                    let hz = self.eval_size(hz)?;
+                    if hz > 0 {
+                        adapter.queue_action(JTAGAction::SetClkSpeed(hz as u64));
+                    }
+                }
+            }
+            Stmt::Padding { ir, pre, len, data } => {
+                let len = self.eval_size(len)?;
+                let data = match data {
+                    Some(data) => self.eval_bits(data, len)?,
+                    // devices other than the target are normally in BYPASS
+                    None => BitVec::repeat(*ir, len),
+                };
+                match (ir, pre) {
+                    (true, true) => self.preir = data,
+                    (true, false) => self.postir = data,
+                    (false, true) => self.predr = data,
+                    (false, false) => self.postdr = data,
+                }
+            }
+        }
+        Ok(Step::Next)
+    }
+
+    // Shift data surrounded by the PRE/POST data, returning the captured bits
+    // corresponding to `data` if `capture` is set
+    fn scan<T: JTAGAdapter + ?Sized>(
+        &mut self,
+        adapter: &mut T,
+        ir: bool,
+        data: &BitSlice,
+        capture: bool,
+    ) -> Result<Option<BitVec>, JTAGError> {
+        let (pre, post, stop, pause_state) = if ir {
+            (&self.preir, &self.postir, self.irstop, JTAGState::PauseIR)
+        } else {
+            (&self.predr, &self.postdr, self.drstop, JTAGState::PauseDR)
+        };
+        let mut bits = pre.clone();
+        bits.extend_from_bitslice(data);
+        bits.extend_from_bitslice(post);
+        let offset = pre.len();
+
+        self.tap.resume(adapter, ir);
+        let pause = stop != JTAGState::RunTestIdle;
+        let ret = match (capture, ir) {
+            (true, true) => Some(adapter.shift_ir_inout(&bits, pause)?),
+            (true, false) => Some(adapter.shift_dr_inout(&bits, pause)?),
+            (false, true) => {
+                adapter.shift_ir_out(&bits, pause);
+                None
+            }
+            (false, false) => {
+                adapter.shift_dr_out(&bits, pause);
+                None
+            }
+        };
+        if pause && stop != pause_state {
+            self.tap.go(adapter, stop);
+        }
+        self.tap.set(stop);
+
+        Ok(ret.map(|x| x[offset..offset + data.len()].to_bitvec()))
+    }
+
+    fn bool_array(&self, size: &Expr, init: Option<&Init>) -> Result<BitVec, JTAGError> {
+        let size = self.eval_size(size)?;
+        let mut bits =
+            match init {
+                None => BitVec::new(),
+                Some(Init::Raw(format, data)) => match format.as_str() {
+                    "BIN" => parse_bits(self.line, false, data)
+                        .map_err(|_| self.err("invalid BIN data"))?,
+                    "HEX" => parse_bits(self.line, true, data)
+                        .map_err(|_| self.err("invalid HEX data"))?,
+                    "ACA" => decode_aca(self.line, data, !self.stapl.actions.is_empty())?,
+                    _ => return Err(self.err(format!("{format} data is not supported"))),
+                },
+                Some(Init::Exprs(exprs)) if exprs.len() == 1 => match self.eval(&exprs[0])? {
+                    Value::Bits(bits) => bits,
+                    value => BitVec::repeat(self.to_bool(value)?, 1),
+                },
+                // a list of elements, starting at index 0
+                Some(Init::Exprs(exprs)) => exprs
+                    .iter()
+                    .map(|x| {
+                        let value = self.eval(x)?;
+                        self.to_bool(value)
+                    })
+                    .collect::<Result<BitVec, _>>()?,
+            };
+        if bits[size.min(bits.len())..].any() {
+            return Err(self.err(format!("initial value is longer than {size} bits")));
+        }
+        bits.resize(size, false);
+        Ok(bits)
+    }
+
+    fn var(&self, name: &str) -> Result<&Var, JTAGError> {
+        self.vars
+            .get(name)
+            .ok_or_else(|| self.err(format!("unknown variable {name}")))
+    }
+
+    fn to_int(&self, value: Value) -> Result<i32, JTAGError> {
+        match value {
+            Value::Int(x) => Ok(x),
+            Value::Bool(x) => Ok(x as i32),
+            Value::Bits(..) => Err(self.err("expected an integer, got a Boolean array")),
+        }
+    }
+
+    fn to_bool(&self, value: Value) -> Result<bool, JTAGError> {
+        match value {
+            Value::Int(x) => Ok(x != 0),
+            Value::Bool(x) => Ok(x),
+            Value::Bits(..) => Err(self.err("expected a Boolean, got a Boolean array")),
+        }
+    }
+
+    fn eval_int(&self, expr: &Expr) -> Result<i32, JTAGError> {
+        let value = self.eval(expr)?;
+        self.to_int(value)
+    }
+
+    fn eval_size(&self, expr: &Expr) -> Result<usize, JTAGError> {
+        let value = self.eval_int(expr)?;
+        usize::try_from(value).map_err(|_| self.err(format!("{value} cannot be negative")))
+    }
+
+    // Evaluate to a Boolean array and take its first `len` bits
+    fn eval_bits(&self, expr: &Expr, len: usize) -> Result<BitVec, JTAGError> {
+        match self.eval(expr)? {
+            Value::Bits(bits) if bits.len() >= len => {
+                if bits[len..].any() && matches!(expr, Expr::Bits(..)) {
+                    return Err(self.err(format!("data is longer than {len} bits")));
+                }
+                Ok(bits[..len].to_bitvec())
+            }
+            Value::Bits(bits) => {
+                Err(self.err(format!("data has {} bits instead of {len}", bits.len())))
+            }
+            _ => Err(self.err("expected a Boolean array")),
+        }
+    }
+
+    // Indexes of a slice, where the lower index always ends up as bit 0
+    fn slice_range(&self, len: usize, a: &Expr, b: &Expr) -> Result<(usize, usize), JTAGError> {
+        let a = self.eval_size(a)?;
+        let b = self.eval_size(b)?;
+        let (lo, hi) = (a.min(b), a.max(b));
+        if hi >= len {
+            return Err(self.err(format!("index {hi} is out of range")));
+        }
+        Ok((lo, hi + 1))
+    }
+
+    fn index(&self, len: usize, index: &Expr) -> Result<usize, JTAGError> {
+        let index = self.eval_size(index)?;
+        if index >= len {
+            return Err(self.err(format!("index {index} is out of range")));
+        }
+        Ok(index)
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<Value, JTAGError> {
+        Ok(match expr {
+            Expr::Int(x) => Value::Int(*x),
+            Expr::Bits(x) => Value::Bits(x.clone()),
+            Expr::Var(name) => match self.var(name)? {
+                Var::Int(x) => Value::Int(*x),
+                Var::Bool(x) => Value::Bool(*x),
+                Var::BoolArray(x) => Value::Bits(x.clone()),
+                Var::IntArray(..) => {
+                    return Err(self.err(format!("{name} has to be indexed")));
+                }
+            },
+            Expr::Index(name, index) => match self.var(name)? {
+                Var::IntArray(x) => Value::Int(x[self.index(x.len(), index)?]),
+                Var::BoolArray(x) => Value::Bool(x[self.index(x.len(), index)?]),
+                _ => return Err(self.err(format!("{name} is not an array"))),
+            },
+            Expr::Slice(name, a, b) => match self.var(name)? {
+                Var::BoolArray(x) => {
+                    let (lo, hi) = self.slice_range(x.len(), a, b)?;
+                    Value::Bits(x[lo..hi].to_bitvec())
+                }
+                _ => return Err(self.err(format!("{name} is not a Boolean array"))),
+            },
+            Expr::Unary(op, x) => {
+                let x = self.eval(x)?;
+                match *op {
+                    "-" => Value::Int(self.to_int(x)?.wrapping_neg()),
+                    "~" => Value::Int(!self.to_int(x)?),
+                    _ => Value::Bool(!self.to_bool(x)?),
+                }
+            }
+            Expr::Binary(op, a, b) => {
+                let a = self.eval(a)?;
+                let b = self.eval(b)?;
+                self.binary(op, a, b)?
+            }
+            Expr::Func(name, x) => {
+                let x = self.eval(x)?;
+                match name.as_str() {
+                    "ABS" => Value::Int(self.to_int(x)?.wrapping_abs()),
+                    // Boolean arrays are converted with bit 0 as the LSB
+                    "INT" => match x {
+                        Value::Bits(bits) if bits.len() <= 32 => Value::Int(
+                            bits.iter()
+                                .by_vals()
+                                .rev()
+                                .fold(0, |acc, bit| (acc << 1) | bit as i32),
+                        ),
+                        Value::Bits(..) => {
+                            return Err(self.err("INT only takes up to 32 bits"));
+                        }
+                        x => Value::Int(self.to_int(x)?),
+                    },
+                    "LOG2" => {
+                        let x = self.to_int(x)?;
+                        if x <= 0 {
+                            return Err(self.err(format!("LOG2 of {x}")));
+                        }
+                        Value::Int((x as u32).next_power_of_two().trailing_zeros() as i32)
+                    }
+                    "SQRT" => {
+                        let x = self.to_int(x)?;
+                        if x < 0 {
+                            return Err(self.err(format!("SQRT of {x}")));
+                        }
+                        Value::Int((x as f64).sqrt().floor() as i32)
+                    }
+                    _ => return Err(self.err(format!("unknown function {name}"))),
+                }
+            }
+        })
+    }
+
+    fn binary(&self, op: &str, a: Value, b: Value) -> Result<Value, JTAGError> {
+        if let (Value::Bits(a), Value::Bits(b)) = (&a, &b) {
+            return match op {
+                "==" => Ok(Value::Bool(a == b)),
+                "!=" => Ok(Value::Bool(a != b)),
+                _ => Err(self.err(format!("{op} cannot be used on Boolean arrays"))),
+            };
+        }
+        if let (Value::Bool(a), Value::Bool(b)) = (&a, &b) {
+            match op {
+                "&" => return Ok(Value::Bool(a & b)),
+                "|" => return Ok(Value::Bool(a | b)),
+                "^" => return Ok(Value::Bool(a ^ b)),
+                _ => {}
+            }
+        }
+        if op == "&&" || op == "||" {
+            let a = self.to_bool(a)?;
+            let b = self.to_bool(b)?;
+            return Ok(Value::Bool(if op == "&&" { a && b } else { a || b }));
+        }
+
+        let a = self.to_int(a)?;
+        let b = self.to_int(b)?;
+        Ok(match op {
+            "+" => Value::Int(a.wrapping_add(b)),
+            "-" => Value::Int(a.wrapping_sub(b)),
+            "*" => Value::Int(a.wrapping_mul(b)),
+            "/" | "%" if b == 0 => return Err(self.err("division by zero")),
+            "/" => Value::Int(a.wrapping_div(b)),
+            "%" => Value::Int(a.wrapping_rem(b)),
+            "&" => Value::Int(a & b),
+            "|" => Value::Int(a | b),
+            "^" => Value::Int(a ^ b),
+            "<<" => Value::Int(a.wrapping_shl(b as u32)),
+            ">>" => Value::Int(a.wrapping_shr(b as u32)),
+            "==" => Value::Bool(a == b),
+            "!=" => Value::Bool(a != b),
+            "<" => Value::Bool(a < b),
+            "<=" => Value::Bool(a <= b),
+            ">" => Value::Bool(a > b),
+            _ => Value::Bool(a >= b),
+        })
+    }
+
+    fn assign(&mut self, target: &Expr, value: Value) -> Result<(), JTAGError> {
+        match target {
+            Expr::Var(name) => {
+                let var = match self.var(name)? {
+                    Var::Int(..) => Var::Int(self.to_int(value)?),
+                    Var::Bool(..) => Var::Bool(self.to_bool(value)?),
+                    Var::BoolArray(x) => match value {
+                        Value::Bits(mut bits) if bits.len() <= x.len() => {
+                            bits.resize(x.len(), false);
+                            Var::BoolArray(bits)
+                        }
+                        _ => return Err(self.err(format!("{name} only has {} bits", x.len()))),
+                    },
+                    Var::IntArray(..) => return Err(self.err(format!("{name} has to be indexed"))),
+                };
+                self.vars.insert(name.clone(), var);
+            }
+            Expr::Index(name, index) => {
+                let index = match self.var(name)? {
+                    Var::IntArray(x) => self.index(x.len(), index)?,
+                    Var::BoolArray(x) => self.index(x.len(), index)?,
+                    _ => return Err(self.err(format!("{name} is not an array"))),
+                };
+                let int = self.to_int(value.clone());
+                let bool = self.to_bool(value);
+                match self.vars.get_mut(name).unwrap() {
+                    Var::IntArray(x) => x[index] = int?,
+                    Var::BoolArray(x) => x.set(index, bool?),
+                    _ => unreachable!(),
+                }
+            }
+            Expr::Slice(name, a, b) => match value {
+                Value::Bits(mut bits) => {
+                    // shorter values (e.g. literals) are zero extended
+                    if let Var::BoolArray(x) = self.var(name)? {
+                        let (lo, hi) = self.slice_range(x.len(), a, b)?;
+                        if bits.len() < hi - lo {
+                            bits.resize(hi - lo, false);
+                        }
+                    }
+                    self.store_bits(target, &bits)?
+                }
+                _ => return Err(self.err("expected a Boolean array")),
+            },
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    // Store bits into a Boolean array, starting at bit 0 if the whole array
+    // is given
+    fn store_bits(&mut self, target: &Expr, bits: &BitSlice) -> Result<(), JTAGError> {
+        let (name, range) = match target {
+            Expr::Var(name) => match self.var(name)? {
+                Var::BoolArray(x) if x.len() >= bits.len() => (name, 0..bits.len()),
+                _ => return Err(self.err(format!("{name} cannot hold {} bits", bits.len()))),
+            },
+            Expr::Slice(name, a, b) => match self.var(name)? {
+                Var::BoolArray(x) => {
+                    let (lo, hi) = self.slice_range(x.len(), a, b)?;
+                    if hi - lo != bits.len() {
+                        return Err(self.err(format!(
+                            "{} bits cannot be stored in {} bits",
+                            bits.len(),
+                            hi - lo
+                        )));
+                    }
+                    (name, lo..hi)
+                }
+                _ => return Err(self.err(format!("{name} is not a Boolean array"))),
+            },
+            _ => return Err(self.err("expected a Boolean array")),
+        };
+        let Some(Var::BoolArray(x)) = self.vars.get_mut(name) else {
+            unreachable!()
+        };
+        x[range].copy_from_bitslice(bits);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    const TEST_STAPL: &str = r#"
+NOTE "CREATOR" "hand written";
+ACTION READ_IDCODE "Check the IDCODE" = CHECK_ID, WRITE_REG OPTIONAL;
+DATA PARAMS;
+INTEGER EXPECTED_ID = 19087507; ' 0x01234093
+BOOLEAN REG_DATA[8] = HEX A5;
+BOOLEAN MASK[32] = $0FFFFFFF;
+ENDDATA;
+PROCEDURE CHECK_ID USES PARAMS;
+    BOOLEAN ID[32];
+    BOOLEAN OK;
+    INTEGER I;
+    INTEGER ONES = 0;
+    INTEGER TRIES = 0;
+    IRSTOP IRPAUSE;
+    STATE RESET IDLE;
+    IRSCAN 4, #0110;
+    DRSCAN 32, $00000000, CAPTURE ID[31..0];
+    FOR I = 0 TO 31;
+        IF ID[I] THEN ONES = ONES + 1;
+    NEXT I;
+    PRINT "IDCODE ", INT(ID), " has ", ONES, " ones", CHR$(33);
+AGAIN: LET TRIES = TRIES + 1;
+    DRSCAN 32, $00000000, COMPARE ID, MASK, OK;
+    IF !OK THEN EXIT 6;
+    IF TRIES < 3 THEN GOTO AGAIN;
+    IF INT(ID) != EXPECTED_ID THEN EXIT 6;
+    EXPORT "IDCODE", ID[31..0];
+    WAIT IDLE, 10 CYCLES, 5 USEC, IDLE;
+ENDPROC;
+PROCEDURE WRITE_REG USES PARAMS;
+    IRSTOP IDLE;
+    IRSCAN 4, $1;
+    DRSCAN 8, REG_DATA[7..0];
+ENDPROC;
+CRC 1A2B;
+"#;
+
+    fn test_adapter(idcode: u32) -> drivers::SimulatedJTAGAdapter {
+        drivers::SimulatedJTAGAdapter::new(vec![Box::new(
+            drivers::GenericSimulatedDevice::new(4)
+                .with_idcode(idcode, bits![0, 1, 1, 0])
+                .with_register(bits![1, 0, 0, 0], bits![0; 8]),
+        )])
+    }
+
+    #[test]
+    fn test_stapl_parse() {
+        let stapl = Stapl::parse(TEST_STAPL).unwrap();
+        assert_eq!(
+            stapl.notes,
+            vec![("CREATOR".to_string(), "hand written".to_string())]
+        );
+        assert_eq!(
+            stapl.actions,
+            vec![StaplAction {
+                name: "READ_IDCODE".to_string(),
+                description: Some("Check the IDCODE".to_string()),
+                procedures: vec![
+                    StaplActionStep {
+                        procedure: "CHECK_ID".to_string(),
+                        optional: false,
+                        recommended: false,
+                    },
+                    StaplActionStep {
+                        procedure: "WRITE_REG".to_string(),
+                        optional: true,
+                        recommended: false,
+                    },
+                ],
+            }]
+        );
+
+        assert!(Stapl::parse("PROCEDURE X;").is_err());
+        assert!(Stapl::parse("ACTION X = Y;").is_err());
+        assert!(Stapl::parse("LET A = (1 + 2;").is_err());
+        assert!(Stapl::parse("VECTOR 1, 2;").is_err());
+    }
+
+    #[test]
+    fn test_stapl_run() {
+        let stapl = Stapl::parse(TEST_STAPL).unwrap();
+
+        let mut adapter = test_adapter(0x0123_4093);
+        let mut player = StaplPlayer::new(&stapl);
+        assert_eq!(player.run(&mut adapter, Some("read_idcode")).unwrap(), 0);
+        assert_eq!(player.output(), ["IDCODE 19087507 has 9 ones!"]);
+        assert_eq!(
+            player.exports(),
+            [("IDCODE".to_string(), "01234093".to_string())]
+        );
+        assert_eq!(adapter.tap_state(), JTAGState::RunTestIdle);
+        let reg = adapter.read_reg(bits![1, 0, 0, 0], 8).unwrap();
+        assert_eq!(reg, bits![0; 8]);
+
+        let mut player = StaplPlayer::new(&stapl).with_procedure("write_reg", true);
+        assert_eq!(player.run(&mut adapter, Some("READ_IDCODE")).unwrap(), 0);
+        let reg = adapter.read_reg(bits![1, 0, 0, 0], 8).unwrap();
+        assert_eq!(reg, bits![1, 0, 1, 0, 0, 1, 0, 1]);
+
+        // only the masked out bits differ, so this fails the final check
+        let mut adapter = test_adapter(0x1123_4093);
+        let mut player = StaplPlayer::new(&stapl).with_procedure("WRITE_REG", true);
+        assert_eq!(player.run(&mut adapter, Some("READ_IDCODE")).unwrap(), 6);
+        let reg = adapter.read_reg(bits![1, 0, 0, 0], 8).unwrap();
+        assert_eq!(reg, bits![0; 8]);
+
+        assert!(player.run(&mut adapter, None).is_err());
+        assert!(player.run(&mut adapter, Some("PROGRAM")).is_err());
+    }
+
+    #[test]
+    fn test_stapl_jam() {
+        // Jam 1.1 programs run from the start and can omit LET
+        let stapl = Stapl::parse(
+            "
+            INTEGER A[4] = 1, 2, 3, 4;
+            INTEGER SUM;
+            INTEGER I;
+            BOOLEAN B[56] = ACA 70000eq7uv40;
+            FOR I = 3 TO 0 STEP -1;
+                SUM = SUM * 10 + A[I];
+            NEXT I;
+            FOR I = 1 TO 0;
+                SUM = 0;
+            NEXT I;
+            PRINT SUM, \" \", B[55..48], \" \", LOG2(5) + (1 << 4) % 5;
+            B[7..0] = #1100;
+            PRINT B[15..0];
+            EXIT A[0] - 1;
+            PRINT \"unreachable\";
+            ",
+        )
+        .unwrap();
+        let mut adapter = test_adapter(0x0123_4093);
+        let mut player = StaplPlayer::new(&stapl);
+        assert_eq!(player.run(&mut adapter, None).unwrap(), 0);
+        assert_eq!(player.output(), ["4321 A5 4", "0F0C"]);
+    }
+}
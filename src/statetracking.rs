@@ -13,14 +13,14 @@ pub trait StateTrackingJTAGAdapter {
     /// * [JTAGAction::ResetToTLR]
     /// * [JTAGAction::GoViaStates]
     /// * [JTAGAction::ShiftBits]
-    fn execute_stjtag_action(&mut self, action: &JTAGAction) -> JTAGOutput;
+    fn execute_stjtag_action(&mut self, action: &JTAGAction) -> Result<JTAGOutput, JTAGError>;
 }
 
 /// Automatically turn [ChunkShifterJTAGAdapter] into a [StateTrackingJTAGAdapter]
 impl<T: StateTrackingJTAGAdapter + AsMut<JTAGAdapterState>> JTAGAdapter for T {
-    fn execute_actions(&mut self, actions: &[JTAGAction]) -> Vec<JTAGOutput> {
+    fn execute_actions(&mut self, actions: &[JTAGAction]) -> Result<Vec<JTAGOutput>, JTAGError> {
         actions
-            .into_iter()
+            .iter()
             .map(|action| {
                 match action {
                     JTAGAction::DelayNS(..)
@@ -32,121 +32,121 @@ impl<T: StateTrackingJTAGAdapter + AsMut<JTAGAdapterState>> JTAGAdapter for T {
                     JTAGAction::ShiftIR { ir, capture, pause } => {
                         self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                             JTAGState::ShiftIR,
-                        ]));
+                        ]))?;
 
                         let ret = self.execute_stjtag_action(&JTAGAction::ShiftBits {
                             bits_tdi: ir.clone(),
                             capture: *capture,
                             tms_exit: true,
-                        });
+                        })?;
 
                         if *pause {
                             self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                                 JTAGState::PauseIR,
-                            ]));
+                            ]))?;
                         } else {
                             self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                                 JTAGState::RunTestIdle,
-                            ]));
+                            ]))?;
                         }
 
-                        ret
+                        Ok(ret)
                     }
                     JTAGAction::ShiftDR { dr, capture, pause } => {
                         self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                             JTAGState::ShiftDR,
-                        ]));
+                        ]))?;
 
                         let ret = self.execute_stjtag_action(&JTAGAction::ShiftBits {
                             bits_tdi: dr.clone(),
                             capture: *capture,
                             tms_exit: true,
-                        });
+                        })?;
 
                         if *pause {
                             self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                                 JTAGState::PauseDR,
-                            ]));
+                            ]))?;
                         } else {
                             self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                                 JTAGState::RunTestIdle,
-                            ]));
+                            ]))?;
                         }
 
-                        ret
+                        Ok(ret)
                     }
 
                     // XXX need to track stuff for optimization here
                     JTAGAction::SetIR(ir) => {
                         self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                             JTAGState::ShiftIR,
-                        ]));
+                        ]))?;
 
                         self.execute_stjtag_action(&JTAGAction::ShiftBits {
                             bits_tdi: ir.clone(),
                             capture: false,
                             tms_exit: true,
-                        });
+                        })?;
 
                         self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                             JTAGState::RunTestIdle,
-                        ]));
+                        ]))?;
 
-                        JTAGOutput::NoData
+                        Ok(JTAGOutput::NoData)
                     }
                     JTAGAction::ReadReg { ir, drlen } => {
                         self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                             JTAGState::ShiftIR,
-                        ]));
+                        ]))?;
 
                         self.execute_stjtag_action(&JTAGAction::ShiftBits {
                             bits_tdi: ir.clone(),
                             capture: false,
                             tms_exit: true,
-                        });
+                        })?;
 
                         self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                             JTAGState::ShiftDR,
-                        ]));
+                        ]))?;
 
                         let ret = self.execute_stjtag_action(&JTAGAction::ShiftBits {
                             bits_tdi: BitVec::repeat(false, *drlen),
                             capture: true,
                             tms_exit: true,
-                        });
+                        })?;
 
                         self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                             JTAGState::RunTestIdle,
-                        ]));
+                        ]))?;
 
-                        ret
+                        Ok(ret)
                     }
                     JTAGAction::WriteReg { ir, dr } => {
                         self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                             JTAGState::ShiftIR,
-                        ]));
+                        ]))?;
 
                         self.execute_stjtag_action(&JTAGAction::ShiftBits {
                             bits_tdi: ir.clone(),
                             capture: false,
                             tms_exit: true,
-                        });
+                        })?;
 
                         self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                             JTAGState::ShiftDR,
-                        ]));
+                        ]))?;
 
                         self.execute_stjtag_action(&JTAGAction::ShiftBits {
                             bits_tdi: dr.clone(),
                             capture: false,
                             tms_exit: true,
-                        });
+                        })?;
 
                         self.execute_stjtag_action(&JTAGAction::GoViaStates(vec![
                             JTAGState::RunTestIdle,
-                        ]));
+                        ]))?;
 
-                        JTAGOutput::NoData
+                        Ok(JTAGOutput::NoData)
                     }
                 }
             })
@@ -0,0 +1,990 @@
+use crate::*;
+
+use bitvec::prelude::*;
+use std::fmt;
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash, Default)]
+/// Parameters of an SVF scan statement (HIR, HDR, SIR, SDR, TIR or TDR)
+///
+/// All data has bit 0 being the first bit shifted. Parameters which are
+/// `None` were not specified in the statement.
+pub struct SvfScan {
+    pub len: usize,
+    pub tdi: Option<BitVec>,
+    pub tdo: Option<BitVec>,
+    pub mask: Option<BitVec>,
+    pub smask: Option<BitVec>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Clock that the cycle count of an SVF RUNTEST statement refers to
+pub enum SvfRunClock {
+    Tck,
+    Sck,
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+/// Parameters of an SVF RUNTEST statement
+pub struct SvfRunTest {
+    pub run_state: Option<JTAGState>,
+    pub run_count: Option<(u64, SvfRunClock)>,
+    /// Minimum time to spend in the run state, in seconds
+    pub min_time: Option<f64>,
+    /// Maximum time to spend in the run state, in seconds
+    pub max_time: Option<f64>,
+    pub end_state: Option<JTAGState>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Argument of an SVF TRST statement
+pub enum SvfTrstMode {
+    On,
+    Off,
+    Z,
+    Absent,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+/// A single SVF statement
+pub enum SvfCommand {
+    EndIR(JTAGState),
+    EndDR(JTAGState),
+    /// Clock frequency in Hz, or `None` to run at full speed
+    Frequency(Option<f64>),
+    HIR(SvfScan),
+    HDR(SvfScan),
+    SIR(SvfScan),
+    SDR(SvfScan),
+    TIR(SvfScan),
+    TDR(SvfScan),
+    RunTest(SvfRunTest),
+    /// Go through each of the given states in order
+    State(Vec<JTAGState>),
+    Trst(SvfTrstMode),
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+/// A parsed Serial Vector Format file
+pub struct Svf {
+    pub commands: Vec<SvfCommand>,
+    /// Line that each command starts on, if it was parsed from a file
+    pub lines: Vec<usize>,
+}
+
+fn parse_err(line: usize, msg: impl Into<String>) -> JTAGError {
+    ParseError::new("SVF", msg).at_line(line).into()
+}
+
+fn state_name(state: JTAGState) -> &'static str {
+    match state {
+        JTAGState::TestLogicReset => "RESET",
+        JTAGState::RunTestIdle => "IDLE",
+        JTAGState::SelectDR => "DRSELECT",
+        JTAGState::CaptureDR => "DRCAPTURE",
+        JTAGState::ShiftDR => "DRSHIFT",
+        JTAGState::Exit1DR => "DREXIT1",
+        JTAGState::PauseDR => "DRPAUSE",
+        JTAGState::Exit2DR => "DREXIT2",
+        JTAGState::UpdateDR => "DRUPDATE",
+        JTAGState::SelectIR => "IRSELECT",
+        JTAGState::CaptureIR => "IRCAPTURE",
+        JTAGState::ShiftIR => "IRSHIFT",
+        JTAGState::Exit1IR => "IREXIT1",
+        JTAGState::PauseIR => "IRPAUSE",
+        JTAGState::Exit2IR => "IREXIT2",
+        JTAGState::UpdateIR => "IRUPDATE",
+    }
+}
+
+pub(crate) fn try_parse_state(s: &str) -> Option<JTAGState> {
+    Some(match s {
+        "RESET" => JTAGState::TestLogicReset,
+        "IDLE" => JTAGState::RunTestIdle,
+        "DRSELECT" => JTAGState::SelectDR,
+        "DRCAPTURE" => JTAGState::CaptureDR,
+        "DRSHIFT" => JTAGState::ShiftDR,
+        "DREXIT1" => JTAGState::Exit1DR,
+        "DRPAUSE" => JTAGState::PauseDR,
+        "DREXIT2" => JTAGState::Exit2DR,
+        "DRUPDATE" => JTAGState::UpdateDR,
+        "IRSELECT" => JTAGState::SelectIR,
+        "IRCAPTURE" => JTAGState::CaptureIR,
+        "IRSHIFT" => JTAGState::ShiftIR,
+        "IREXIT1" => JTAGState::Exit1IR,
+        "IRPAUSE" => JTAGState::PauseIR,
+        "IREXIT2" => JTAGState::Exit2IR,
+        "IRUPDATE" => JTAGState::UpdateIR,
+        _ => return None,
+    })
+}
+
+pub(crate) fn is_stable_state(state: JTAGState) -> bool {
+    matches!(
+        state,
+        JTAGState::TestLogicReset
+            | JTAGState::RunTestIdle
+            | JTAGState::PauseDR
+            | JTAGState::PauseIR
+    )
+}
+
+fn parse_stable_state(line: usize, s: Option<&str>) -> Result<JTAGState, JTAGError> {
+    let s = s.ok_or_else(|| parse_err(line, "expected a state"))?;
+    match try_parse_state(s) {
+        Some(state) if is_stable_state(state) => Ok(state),
+        _ => Err(parse_err(line, format!("{s} is not a stable state"))),
+    }
+}
+
+fn parse_number(line: usize, s: Option<&str>) -> Result<f64, JTAGError> {
+    let s = s.ok_or_else(|| parse_err(line, "expected a number"))?;
+    match s.parse::<f64>() {
+        Ok(x) if x.is_finite() && x >= 0.0 => Ok(x),
+        _ => Err(parse_err(line, format!("expected a number, got {s:?}"))),
+    }
+}
+
+/// Parse a parenthesized hex string (written MSB-first) into `len` bits
+fn parse_hex(line: usize, s: Option<&str>, len: usize) -> Result<BitVec, JTAGError> {
+    let s = s.ok_or_else(|| parse_err(line, "expected hex data"))?;
+    let hex = s
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(|| parse_err(line, format!("expected hex data, got {s:?}")))?;
+
+    let mut ret = BitVec::with_capacity(len);
+    for c in hex.chars().rev() {
+        let nibble = c
+            .to_digit(16)
+            .ok_or_else(|| parse_err(line, format!("invalid hex digit {c:?}")))?;
+        for i in 0..4 {
+            let bit = nibble & (1 << i) != 0;
+            if ret.len() < len {
+                ret.push(bit);
+            } else if bit {
+                return Err(parse_err(
+                    line,
+                    format!("hex data is longer than {len} bits"),
+                ));
+            }
+        }
+    }
+    ret.resize(len, false);
+    Ok(ret)
+}
+
+/// Format bits as hex, MSB-first
+pub(crate) fn format_hex(bits: &BitSlice) -> String {
+    (0..bits.len().div_ceil(4))
+        .rev()
+        .map(|i| {
+            let nibble = bits[i * 4..bits.len().min(i * 4 + 4)].load_le::<u32>();
+            char::from_digit(nibble, 16).unwrap().to_ascii_uppercase()
+        })
+        .collect()
+}
+
+// Strip comments and split on `;`, keeping track of the line that each
+// statement starts on
+fn split_statements(src: &str) -> Result<Vec<(usize, String)>, JTAGError> {
+    let mut ret = Vec::new();
+    let mut cur = String::new();
+    let mut start = None;
+
+    for (i, line) in src.lines().enumerate() {
+        let line = line.split('!').next().unwrap();
+        let line = line.split("//").next().unwrap();
+        for c in line.chars() {
+            if c == ';' {
+                if let Some(start) = start.take() {
+                    ret.push((start, cur.split_off(0)));
+                }
+                cur.clear();
+            } else {
+                if start.is_none() && !c.is_whitespace() {
+                    start = Some(i + 1);
+                }
+                cur.push(c);
+            }
+        }
+        cur.push(' ');
+    }
+
+    if let Some(start) = start {
+        return Err(parse_err(start, "missing ;"));
+    }
+    Ok(ret)
+}
+
+// Split on whitespace while keeping parenthesized data (with whitespace
+// removed) together in one token
+fn tokenize(stmt: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    let mut cur = String::new();
+    let mut in_parens = false;
+
+    for c in stmt.chars() {
+        match c {
+            '(' => {
+                if !cur.is_empty() {
+                    ret.push(cur.split_off(0));
+                }
+                in_parens = true;
+                cur.push(c);
+            }
+            ')' => {
+                cur.push(c);
+                ret.push(cur.split_off(0));
+                in_parens = false;
+            }
+            _ if c.is_whitespace() => {
+                if !in_parens && !cur.is_empty() {
+                    ret.push(cur.split_off(0));
+                }
+            }
+            _ => cur.push(c.to_ascii_uppercase()),
+        }
+    }
+    if !cur.is_empty() {
+        ret.push(cur);
+    }
+    ret
+}
+
+fn parse_scan(line: usize, args: &[&str]) -> Result<SvfScan, JTAGError> {
+    let len = args
+        .first()
+        .and_then(|x| x.parse().ok())
+        .ok_or_else(|| parse_err(line, "expected a length"))?;
+    let mut ret = SvfScan {
+        len,
+        ..Default::default()
+    };
+
+    let mut args = args[1..].iter().copied();
+    while let Some(param) = args.next() {
+        let data = Some(parse_hex(line, args.next(), len)?);
+        match param {
+            "TDI" => ret.tdi = data,
+            "TDO" => ret.tdo = data,
+            "MASK" => ret.mask = data,
+            "SMASK" => ret.smask = data,
+            _ => return Err(parse_err(line, format!("unknown scan parameter {param}"))),
+        }
+    }
+    Ok(ret)
+}
+
+fn parse_runtest(line: usize, args: &[&str]) -> Result<SvfRunTest, JTAGError> {
+    let mut ret = SvfRunTest::default();
+    let mut args = args.iter().copied().peekable();
+
+    if args.peek().is_some_and(|x| try_parse_state(x).is_some()) {
+        ret.run_state = Some(parse_stable_state(line, args.next())?);
+    }
+
+    let num = parse_number(line, args.next())?;
+    match args.next() {
+        Some(clk @ ("TCK" | "SCK")) => {
+            if num.fract() != 0.0 {
+                return Err(parse_err(line, format!("{num} is not a cycle count")));
+            }
+            let clk = if clk == "TCK" {
+                SvfRunClock::Tck
+            } else {
+                SvfRunClock::Sck
+            };
+            ret.run_count = Some((num as u64, clk));
+
+            if args.peek().is_some_and(|x| x.parse::<f64>().is_ok()) {
+                ret.min_time = Some(parse_number(line, args.next())?);
+                if args.next() != Some("SEC") {
+                    return Err(parse_err(line, "expected SEC"));
+                }
+            }
+        }
+        Some("SEC") => ret.min_time = Some(num),
+        _ => return Err(parse_err(line, "expected TCK, SCK or SEC")),
+    }
+
+    if args.peek() == Some(&"MAXIMUM") {
+        args.next();
+        ret.max_time = Some(parse_number(line, args.next())?);
+        if args.next() != Some("SEC") {
+            return Err(parse_err(line, "expected SEC"));
+        }
+    }
+    if args.peek() == Some(&"ENDSTATE") {
+        args.next();
+        ret.end_state = Some(parse_stable_state(line, args.next())?);
+    }
+
+    if let Some(x) = args.next() {
+        return Err(parse_err(line, format!("unexpected {x}")));
+    }
+    Ok(ret)
+}
+
+fn parse_command(line: usize, stmt: &str) -> Result<SvfCommand, JTAGError> {
+    let tokens = tokenize(stmt);
+    let args = tokens.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+    let (&cmd, args) = args.split_first().unwrap();
+
+    let expect_args = |n: usize| {
+        if args.len() != n {
+            Err(parse_err(line, format!("{cmd} takes {n} argument(s)")))
+        } else {
+            Ok(())
+        }
+    };
+
+    Ok(match cmd {
+        "ENDIR" => {
+            expect_args(1)?;
+            SvfCommand::EndIR(parse_stable_state(line, args.first().copied())?)
+        }
+        "ENDDR" => {
+            expect_args(1)?;
+            SvfCommand::EndDR(parse_stable_state(line, args.first().copied())?)
+        }
+        "FREQUENCY" => match args {
+            [] => SvfCommand::Frequency(None),
+            [hz, "HZ"] => SvfCommand::Frequency(Some(parse_number(line, Some(hz))?)),
+            _ => return Err(parse_err(line, "expected FREQUENCY [cycles HZ]")),
+        },
+        "HIR" => SvfCommand::HIR(parse_scan(line, args)?),
+        "HDR" => SvfCommand::HDR(parse_scan(line, args)?),
+        "SIR" => SvfCommand::SIR(parse_scan(line, args)?),
+        "SDR" => SvfCommand::SDR(parse_scan(line, args)?),
+        "TIR" => SvfCommand::TIR(parse_scan(line, args)?),
+        "TDR" => SvfCommand::TDR(parse_scan(line, args)?),
+        "RUNTEST" => SvfCommand::RunTest(parse_runtest(line, args)?),
+        "STATE" => {
+            let states = args
+                .iter()
+                .map(|x| {
+                    try_parse_state(x).ok_or_else(|| parse_err(line, format!("unknown state {x}")))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            match states.last() {
+                Some(&state) if is_stable_state(state) => {}
+                _ => return Err(parse_err(line, "STATE must end in a stable state")),
+            }
+            SvfCommand::State(states)
+        }
+        "TRST" => {
+            expect_args(1)?;
+            SvfCommand::Trst(match args[0] {
+                "ON" => SvfTrstMode::On,
+                "OFF" => SvfTrstMode::Off,
+                "Z" => SvfTrstMode::Z,
+                "ABSENT" => SvfTrstMode::Absent,
+                x => return Err(parse_err(line, format!("unknown TRST mode {x}"))),
+            })
+        }
+        "PIO" | "PIOMAP" => return Err(parse_err(line, format!("{cmd} is not supported"))),
+        _ => return Err(parse_err(line, format!("unknown statement {cmd}"))),
+    })
+}
+
+impl Svf {
+    /// Parse the contents of an SVF file
+    pub fn parse(src: &str) -> Result<Self, JTAGError> {
+        let statements = split_statements(src)?;
+        let commands = statements
+            .iter()
+            .map(|(line, stmt)| parse_command(*line, stmt))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            commands,
+            lines: statements.iter().map(|(line, _)| *line).collect(),
+        })
+    }
+
+    /// Play every statement using a new [SvfPlayer]. Verify failures
+    /// include the line of the failing statement if it is known.
+    pub fn play<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<(), JTAGError> {
+        let mut player = SvfPlayer::new();
+        for (i, command) in self.commands.iter().enumerate() {
+            player.play_command(adapter, command).map_err(|e| match e {
+                JTAGError::VerifyFailed(msg) => JTAGError::VerifyFailed(match self.lines.get(i) {
+                    Some(line) => format!("SVF line {line}: {msg}"),
+                    None => format!("statement {}: {msg}", i + 1),
+                }),
+                e => e,
+            })?;
+        }
+        adapter.flush()?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for SvfScan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.len)?;
+        for (name, data) in [
+            ("TDI", &self.tdi),
+            ("TDO", &self.tdo),
+            ("MASK", &self.mask),
+            ("SMASK", &self.smask),
+        ] {
+            if let Some(data) = data {
+                write!(f, " {name} ({})", format_hex(data))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SvfCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SvfCommand::EndIR(state) => write!(f, "ENDIR {};", state_name(*state)),
+            SvfCommand::EndDR(state) => write!(f, "ENDDR {};", state_name(*state)),
+            SvfCommand::Frequency(Some(hz)) => write!(f, "FREQUENCY {hz:E} HZ;"),
+            SvfCommand::Frequency(None) => write!(f, "FREQUENCY;"),
+            SvfCommand::HIR(scan) => write!(f, "HIR {scan};"),
+            SvfCommand::HDR(scan) => write!(f, "HDR {scan};"),
+            SvfCommand::SIR(scan) => write!(f, "SIR {scan};"),
+            SvfCommand::SDR(scan) => write!(f, "SDR {scan};"),
+            SvfCommand::TIR(scan) => write!(f, "TIR {scan};"),
+            SvfCommand::TDR(scan) => write!(f, "TDR {scan};"),
+            SvfCommand::RunTest(runtest) => {
+                write!(f, "RUNTEST")?;
+                if let Some(state) = runtest.run_state {
+                    write!(f, " {}", state_name(state))?;
+                }
+                if let Some((count, clk)) = runtest.run_count {
+                    let clk = match clk {
+                        SvfRunClock::Tck => "TCK",
+                        SvfRunClock::Sck => "SCK",
+                    };
+                    write!(f, " {count} {clk}")?;
+                }
+                if let Some(min_time) = runtest.min_time {
+                    write!(f, " {min_time:E} SEC")?;
+                }
+                if let Some(max_time) = runtest.max_time {
+                    write!(f, " MAXIMUM {max_time:E} SEC")?;
+                }
+                if let Some(state) = runtest.end_state {
+                    write!(f, " ENDSTATE {}", state_name(state))?;
+                }
+                write!(f, ";")
+            }
+            SvfCommand::State(states) => {
+                write!(f, "STATE")?;
+                for state in states {
+                    write!(f, " {}", state_name(*state))?;
+                }
+                write!(f, ";")
+            }
+            SvfCommand::Trst(mode) => {
+                let mode = match mode {
+                    SvfTrstMode::On => "ON",
+                    SvfTrstMode::Off => "OFF",
+                    SvfTrstMode::Z => "Z",
+                    SvfTrstMode::Absent => "ABSENT",
+                };
+                write!(f, "TRST {mode};")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Svf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for command in &self.commands {
+            writeln!(f, "{command}")?;
+        }
+        Ok(())
+    }
+}
+
+// Parameters of a scan statement that persist until the next statement of
+// the same type
+#[derive(Clone, Debug, Default)]
+struct ScanState {
+    tdi: BitVec,
+    tdo: Option<BitVec>,
+    mask: BitVec,
+}
+
+impl ScanState {
+    /// Apply the parameters of `scan`. `default_tdi` is used if the length
+    /// changes without TDI being given (otherwise this is an error).
+    fn update(&mut self, scan: &SvfScan, default_tdi: Option<bool>) -> Result<(), JTAGError> {
+        for data in [&scan.tdi, &scan.tdo, &scan.mask, &scan.smask]
+            .into_iter()
+            .flatten()
+        {
+            if data.len() != scan.len {
+                return Err(JTAGError::InvalidAction(format!(
+                    "scan data has {} bits instead of {}",
+                    data.len(),
+                    scan.len
+                )));
+            }
+        }
+
+        if scan.len != self.tdi.len() {
+            self.mask = BitVec::repeat(true, scan.len);
+            self.tdi = match (&scan.tdi, default_tdi) {
+                (Some(tdi), _) => tdi.clone(),
+                (None, Some(bit)) => BitVec::repeat(bit, scan.len),
+                (None, None) => {
+                    return Err(JTAGError::InvalidAction(format!(
+                        "length changed to {} without TDI being given",
+                        scan.len
+                    )))
+                }
+            };
+        }
+
+        if let Some(tdi) = &scan.tdi {
+            self.tdi = tdi.clone();
+        }
+        if let Some(mask) = &scan.mask {
+            self.mask = mask.clone();
+        }
+        self.tdo = scan.tdo.clone();
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+/// Last known TAP state of a player, only used to tell if a scan was left in
+/// Pause
+pub(crate) struct PauseTracker {
+    state: JTAGState,
+}
+
+impl PauseTracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: JTAGState::TestLogicReset,
+        }
+    }
+
+    /// Record that the TAP has been left in `state`
+    pub(crate) fn set(&mut self, state: JTAGState) {
+        self.state = state;
+    }
+
+    /// Move to `state`, resetting the TAP if that is Test-Logic-Reset
+    pub(crate) fn go<T: JTAGAdapter + ?Sized>(&mut self, adapter: &mut T, state: JTAGState) {
+        if state == JTAGState::TestLogicReset {
+            adapter.reset_to_tlr();
+        } else {
+            adapter.go_to_state(state);
+        }
+        self.state = state;
+    }
+
+    /// Prepare for an IR or DR scan. The shortest path from Pause to Shift
+    /// resumes the previous scan instead of going through Update and
+    /// Capture, so go via Capture when the TAP was left in Pause.
+    pub(crate) fn resume<T: JTAGAdapter + ?Sized>(&mut self, adapter: &mut T, ir: bool) {
+        let (pause_state, capture_state) = if ir {
+            (JTAGState::PauseIR, JTAGState::CaptureIR)
+        } else {
+            (JTAGState::PauseDR, JTAGState::CaptureDR)
+        };
+        if self.state == pause_state {
+            adapter.go_to_state(capture_state);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Executes SVF statements on a [JTAGAdapter]
+///
+/// This keeps track of the state which persists between statements
+/// (header/trailer data, end states, and previously used TDI and MASK
+/// values). Statements are queued as buffered actions, except for scans
+/// with TDO data which are executed immediately so that they can be checked.
+///
+/// TRST ON is emulated using [JTAGAction::ResetToTLR] and the other TRST
+/// modes are ignored. SCK cycles in RUNTEST are approximated using TCK
+/// cycles.
+pub struct SvfPlayer {
+    hir: ScanState,
+    hdr: ScanState,
+    sir: ScanState,
+    sdr: ScanState,
+    tir: ScanState,
+    tdr: ScanState,
+    pub(crate) endir: JTAGState,
+    pub(crate) enddr: JTAGState,
+    pub(crate) run_state: JTAGState,
+    pub(crate) run_end_state: JTAGState,
+    tap: PauseTracker,
+}
+
+impl Default for SvfPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SvfPlayer {
+    pub fn new() -> Self {
+        Self {
+            hir: ScanState::default(),
+            hdr: ScanState::default(),
+            sir: ScanState::default(),
+            sdr: ScanState::default(),
+            tir: ScanState::default(),
+            tdr: ScanState::default(),
+            endir: JTAGState::RunTestIdle,
+            enddr: JTAGState::RunTestIdle,
+            run_state: JTAGState::RunTestIdle,
+            run_end_state: JTAGState::RunTestIdle,
+            tap: PauseTracker::new(),
+        }
+    }
+
+    /// Execute all of `commands` and then flush the adapter.
+    ///
+    /// If TDO data does not match, [JTAGError::VerifyFailed] is returned
+    /// for the first mismatching statement (counting from 1).
+    pub fn play<T: JTAGAdapter + ?Sized>(
+        &mut self,
+        adapter: &mut T,
+        commands: &[SvfCommand],
+    ) -> Result<(), JTAGError> {
+        for (i, command) in commands.iter().enumerate() {
+            self.play_command(adapter, command).map_err(|e| match e {
+                JTAGError::VerifyFailed(msg) => {
+                    JTAGError::VerifyFailed(format!("statement {}: {msg}", i + 1))
+                }
+                e => e,
+            })?;
+        }
+        adapter.flush()?;
+        Ok(())
+    }
+
+    /// Execute a single statement. This may leave buffered actions queued
+    /// on the adapter.
+    pub fn play_command<T: JTAGAdapter + ?Sized>(
+        &mut self,
+        adapter: &mut T,
+        command: &SvfCommand,
+    ) -> Result<(), JTAGError> {
+        self.update(command)?;
+        match command {
+            SvfCommand::Frequency(Some(hz)) => {
+                adapter.queue_action(JTAGAction::SetClkSpeed(*hz as u64))
+            }
+            SvfCommand::SIR(..) => self.scan(adapter, true)?,
+            SvfCommand::SDR(..) => self.scan(adapter, false)?,
+            SvfCommand::RunTest(runtest) => {
+                self.runtest(adapter, runtest);
+                self.tap.set(self.run_end_state);
+            }
+            SvfCommand::State(states) => {
+                if states[..] == [JTAGState::TestLogicReset] {
+                    adapter.reset_to_tlr();
+                } else {
+                    adapter.go_via_states(states);
+                }
+                self.tap.set(*states.last().unwrap());
+            }
+            SvfCommand::Trst(SvfTrstMode::On) => self.tap.go(adapter, JTAGState::TestLogicReset),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Update the persistent state for `command` without executing it
+    pub(crate) fn update(&mut self, command: &SvfCommand) -> Result<(), JTAGError> {
+        match command {
+            SvfCommand::EndIR(state) => self.endir = *state,
+            SvfCommand::EndDR(state) => self.enddr = *state,
+            // Unspecified header/trailer data selects BYPASS
+            SvfCommand::HIR(scan) => self.hir.update(scan, Some(true))?,
+            SvfCommand::HDR(scan) => self.hdr.update(scan, Some(false))?,
+            SvfCommand::TIR(scan) => self.tir.update(scan, Some(true))?,
+            SvfCommand::TDR(scan) => self.tdr.update(scan, Some(false))?,
+            SvfCommand::SIR(scan) => self.sir.update(scan, None)?,
+            SvfCommand::SDR(scan) => self.sdr.update(scan, None)?,
+            SvfCommand::RunTest(runtest) => {
+                if let Some(run_state) = runtest.run_state {
+                    self.run_state = run_state;
+                    self.run_end_state = run_state;
+                }
+                if let Some(end_state) = runtest.end_state {
+                    self.run_end_state = end_state;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Data to shift for the most recent SIR or SDR including the header and
+    /// trailer, along with the expected TDO data and mask if there are any
+    pub(crate) fn scan_data(&self, ir: bool) -> (BitVec, Option<(BitVec, BitVec)>) {
+        let parts = if ir {
+            [&self.hir, &self.sir, &self.tir]
+        } else {
+            [&self.hdr, &self.sdr, &self.tdr]
+        };
+
+        let mut tdi = BitVec::new();
+        for part in parts {
+            tdi.extend_from_bitslice(&part.tdi);
+        }
+        if parts.iter().all(|part| part.tdo.is_none()) {
+            return (tdi, None);
+        }
+
+        let mut expected = BitVec::new();
+        let mut mask = BitVec::new();
+        for part in parts {
+            match &part.tdo {
+                Some(tdo) => {
+                    expected.extend_from_bitslice(tdo);
+                    mask.extend_from_bitslice(&part.mask);
+                }
+                None => {
+                    expected.resize(expected.len() + part.tdi.len(), false);
+                    mask.resize(mask.len() + part.tdi.len(), false);
+                }
+            }
+        }
+        (tdi, Some((expected, mask)))
+    }
+
+    fn scan<T: JTAGAdapter + ?Sized>(
+        &mut self,
+        adapter: &mut T,
+        ir: bool,
+    ) -> Result<(), JTAGError> {
+        let (end_state, pause_state) = if ir {
+            (self.endir, JTAGState::PauseIR)
+        } else {
+            (self.enddr, JTAGState::PauseDR)
+        };
+
+        let (tdi, expected) = self.scan_data(ir);
+        if tdi.is_empty() {
+            return Ok(());
+        }
+
+        self.tap.resume(adapter, ir);
+        self.tap.set(end_state);
+
+        let pause = end_state != JTAGState::RunTestIdle;
+        if let Some((expected, mask)) = expected {
+            let out = if ir {
+                adapter.shift_ir_inout(&tdi, pause)?
+            } else {
+                adapter.shift_dr_inout(&tdi, pause)?
+            };
+
+            if out.len() != tdi.len() {
+                return Err(JTAGError::ProtocolViolation(format!(
+                    "shifted {} bits but got {} back",
+                    tdi.len(),
+                    out.len()
+                )));
+            }
+            if let Some(bit) = (0..out.len()).find(|&i| mask[i] && out[i] != expected[i]) {
+                return Err(JTAGError::VerifyFailed(format!(
+                    "{} bit {bit} is {}: expected ({}) mask ({}), got ({})",
+                    if ir { "SIR" } else { "SDR" },
+                    out[bit] as u8,
+                    format_hex(&expected),
+                    format_hex(&mask),
+                    format_hex(&out)
+                )));
+            }
+        } else if ir {
+            adapter.shift_ir_out(&tdi, pause);
+        } else {
+            adapter.shift_dr_out(&tdi, pause);
+        }
+
+        if end_state != JTAGState::RunTestIdle && end_state != pause_state {
+            adapter.go_to_state(end_state);
+        }
+        Ok(())
+    }
+
+    fn runtest<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T, runtest: &SvfRunTest) {
+        adapter.go_to_state(self.run_state);
+        if let Some((count, _)) = runtest.run_count {
+            let count = count as usize;
+            if self.run_state == JTAGState::TestLogicReset {
+                for _ in 0..count.div_ceil(5) {
+                    adapter.reset_to_tlr();
+                }
+            } else if count > 0 {
+                // TMS=0 stays in every other stable state
+                adapter.shift_bits_out(&bitvec![0; count], false);
+            }
+        }
+        if let Some(min_time) = runtest.min_time {
+            if min_time > 0.0 {
+                adapter.queue_action(JTAGAction::DelayNS((min_time * 1e9).round() as u64));
+            }
+        }
+        adapter.go_to_state(self.run_end_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    const TEST_SVF: &str = "
+        ! Read out the IDCODE
+        TRST OFF;
+        ENDIR IDLE;
+        ENDDR IDLE;
+        STATE RESET;
+        STATE IDLE;
+        FREQUENCY 1.00E+06 HZ;
+        HIR 0;
+        sir 4 tdi (6) tdo (1) mask (3);
+        // IDCODE
+        SDR 32 TDI (00000000)
+            TDO (01234093) MASK (0FFFFFFF);
+        RUNTEST IDLE 100 TCK 1.0E-03 SEC ENDSTATE IDLE;
+    ";
+
+    fn test_adapter() -> drivers::SimulatedJTAGAdapter {
+        drivers::SimulatedJTAGAdapter::new(vec![Box::new(
+            drivers::GenericSimulatedDevice::new(4).with_idcode(0x0123_4093, bits![0, 1, 1, 0]),
+        )])
+    }
+
+    #[test]
+    fn test_svf_parse() {
+        let svf = Svf::parse(TEST_SVF).unwrap();
+        assert_eq!(svf.commands.len(), 10);
+        assert_eq!(svf.commands[0], SvfCommand::Trst(SvfTrstMode::Off));
+        assert_eq!(
+            svf.commands[3],
+            SvfCommand::State(vec![JTAGState::TestLogicReset])
+        );
+        assert_eq!(svf.commands[5], SvfCommand::Frequency(Some(1e6)));
+        assert_eq!(
+            svf.commands[7],
+            SvfCommand::SIR(SvfScan {
+                len: 4,
+                tdi: Some(bitvec![0, 1, 1, 0]),
+                tdo: Some(bitvec![1, 0, 0, 0]),
+                mask: Some(bitvec![1, 1, 0, 0]),
+                smask: None,
+            })
+        );
+        assert_eq!(
+            svf.commands[9],
+            SvfCommand::RunTest(SvfRunTest {
+                run_state: Some(JTAGState::RunTestIdle),
+                run_count: Some((100, SvfRunClock::Tck)),
+                min_time: Some(1e-3),
+                max_time: None,
+                end_state: Some(JTAGState::RunTestIdle),
+            })
+        );
+
+        assert_eq!(svf.lines[..4], [3, 4, 5, 6]);
+        assert_eq!(svf.lines[9], 14);
+
+        // writing it back out gives the same result
+        assert_eq!(Svf::parse(&svf.to_string()).unwrap().commands, svf.commands);
+
+        let err = Svf::parse("\nSIR 4 TDI (16);").unwrap_err();
+        assert!(matches!(
+            err,
+            JTAGError::Parse(ParseError {
+                location: ParseLocation::Line(2),
+                ..
+            })
+        ));
+        assert!(err.to_string().starts_with("parse error: SVF line 2: "));
+        assert!(Svf::parse("ENDIR DRSHIFT;").is_err());
+        assert!(Svf::parse("SIR 4 TDI (6)").is_err());
+    }
+
+    #[test]
+    fn test_svf_play() {
+        let svf = Svf::parse(TEST_SVF).unwrap();
+        let mut adapter = test_adapter();
+        svf.play(&mut adapter).unwrap();
+        assert_eq!(adapter.current_ir(0), bits![0, 1, 1, 0]);
+        assert_eq!(adapter.tap_state(), JTAGState::RunTestIdle);
+
+        let svf = Svf::parse(&TEST_SVF.replace("01234093", "01235093")).unwrap();
+        let ret = svf.play(&mut adapter);
+        assert!(
+            matches!(ret, Err(JTAGError::VerifyFailed(msg)) if msg.starts_with("SVF line 12:"))
+        );
+    }
+
+    #[test]
+    fn test_svf_header_trailer() {
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![
+            Box::new(
+                drivers::GenericSimulatedDevice::new(4).with_idcode(0x0123_4093, bits![0, 1, 1, 0]),
+            ),
+            Box::new(
+                drivers::GenericSimulatedDevice::new(5)
+                    .with_idcode(0x0567_8093, bits![1, 0, 0, 0, 0])
+                    .with_register(bits![0, 1, 0, 0, 0], bits![0; 8]),
+            ),
+            Box::new(drivers::GenericSimulatedDevice::new(3)),
+        ]);
+
+        // talk to the device in the middle
+        let svf = Svf::parse(
+            "
+            HIR 4 TDI (F);
+            HDR 1 TDI (0);
+            TIR 3;
+            TDR 1;
+            ENDDR DRPAUSE;
+            SIR 5 TDI (01) TDO (01);
+            SDR 32 TDI (0) TDO (05678093);
+            SIR 5 TDI (02);
+            SDR 8 TDI (A5);
+            ",
+        )
+        .unwrap();
+        svf.play(&mut adapter).unwrap();
+        assert_eq!(adapter.current_ir(0), bits![1; 4]);
+        assert_eq!(adapter.current_ir(2), bits![1; 3]);
+        assert_eq!(adapter.tap_state(), JTAGState::PauseDR);
+
+        let mut adapter = ScanChain::new(adapter, &[4, 5, 3]);
+        let reg = adapter.device(1).read_reg(bits![0, 1, 0, 0, 0], 8).unwrap();
+        assert_eq!(reg, bits![1, 0, 1, 0, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn test_svf_persistent_data() {
+        let mut adapter = test_adapter();
+        let mut player = SvfPlayer::new();
+        let svf = Svf::parse(
+            "
+            SIR 4 TDI (6) MASK (3);
+            SIR 4 TDO (1);
+            SDR 32 TDI (0) TDO (01234093);
+            SDR 32 TDO (01234093);
+            ",
+        )
+        .unwrap();
+        player.play(&mut adapter, &svf.commands).unwrap();
+
+        // TDI must be given when the length changes
+        let svf = Svf::parse("SDR 16 TDO (4093);").unwrap();
+        let ret = player.play(&mut adapter, &svf.commands);
+        assert!(matches!(ret, Err(JTAGError::InvalidAction(..))));
+    }
+}
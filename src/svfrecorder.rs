@@ -0,0 +1,377 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+/// Adapter which records every action executed on it as SVF
+///
+/// Actions are passed through to an inner adapter if there is one.
+/// Otherwise, captured data is returned as all zeros.
+///
+/// The TAP is assumed to start in Test-Logic-Reset. SVF can only describe
+/// moving between stable states, so [JTAGAction::ShiftBits] as well as
+/// [JTAGAction::GoViaStates] ending in an unstable state cannot be recorded
+/// and return [JTAGError::InvalidAction] without being executed.
+pub struct SvfRecorder<'a> {
+    jtag_state: JTAGAdapterState,
+    inner: Option<&'a mut dyn JTAGAdapter>,
+    svf: Svf,
+    record_tdo: bool,
+    tap: RecordedTap,
+}
+impl<'a> AsMut<JTAGAdapterState> for SvfRecorder<'a> {
+    fn as_mut(&mut self) -> &mut JTAGAdapterState {
+        &mut self.jtag_state
+    }
+}
+
+fn scan(len: usize, tdi: BitVec) -> SvfScan {
+    SvfScan {
+        len,
+        tdi: Some(tdi),
+        ..Default::default()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+/// TAP state and end states as of the last recorded command
+struct RecordedTap {
+    state: JTAGState,
+    endir: JTAGState,
+    enddr: JTAGState,
+}
+
+impl RecordedTap {
+    fn set_endir(&mut self, state: JTAGState, out: &mut Vec<SvfCommand>) {
+        if self.endir != state {
+            self.endir = state;
+            out.push(SvfCommand::EndIR(state));
+        }
+    }
+    fn set_enddr(&mut self, state: JTAGState, out: &mut Vec<SvfCommand>) {
+        if self.enddr != state {
+            self.enddr = state;
+            out.push(SvfCommand::EndDR(state));
+        }
+    }
+
+    /// Translate a single action. If the action captures data, the
+    /// corresponding scan is always the last command.
+    fn translate(&mut self, action: &JTAGAction) -> Result<Vec<SvfCommand>, JTAGError> {
+        let mut out = Vec::new();
+        match action {
+            JTAGAction::DelayNS(ns) => {
+                if !svf::is_stable_state(self.state) {
+                    return Err(JTAGError::InvalidAction(format!(
+                        "cannot record a delay in {:?}",
+                        self.state
+                    )));
+                }
+                out.push(SvfCommand::RunTest(SvfRunTest {
+                    run_state: Some(self.state),
+                    min_time: Some(*ns as f64 / 1e9),
+                    end_state: Some(self.state),
+                    ..Default::default()
+                }));
+            }
+            JTAGAction::SetClkSpeed(hz) => out.push(SvfCommand::Frequency(Some(*hz as f64))),
+            JTAGAction::ResetToTLR => {
+                self.state = JTAGState::TestLogicReset;
+                out.push(SvfCommand::State(vec![JTAGState::TestLogicReset]));
+            }
+            JTAGAction::GoViaStates(states) => {
+                // SVF needs every intermediate state to be listed
+                let mut path = Vec::new();
+                let mut state = self.state;
+                for &target in states {
+                    for tms in state.path_to(target).iter().by_vals() {
+                        state = state.transition(tms);
+                        path.push(state);
+                    }
+                }
+                if !svf::is_stable_state(state) {
+                    return Err(JTAGError::InvalidAction(format!(
+                        "cannot record going to {state:?} in SVF"
+                    )));
+                }
+                self.state = state;
+                if !path.is_empty() {
+                    out.push(SvfCommand::State(path));
+                }
+            }
+            JTAGAction::ShiftBits { .. } => {
+                return Err(JTAGError::InvalidAction(
+                    "cannot record ShiftBits in SVF".to_string(),
+                ))
+            }
+            JTAGAction::ShiftIR { ir, pause, .. } => {
+                let end = if *pause {
+                    JTAGState::PauseIR
+                } else {
+                    JTAGState::RunTestIdle
+                };
+                self.set_endir(end, &mut out);
+                out.push(SvfCommand::SIR(scan(ir.len(), ir.clone())));
+                self.state = end;
+            }
+            JTAGAction::ShiftDR { dr, pause, .. } => {
+                let end = if *pause {
+                    JTAGState::PauseDR
+                } else {
+                    JTAGState::RunTestIdle
+                };
+                self.set_enddr(end, &mut out);
+                out.push(SvfCommand::SDR(scan(dr.len(), dr.clone())));
+                self.state = end;
+            }
+            JTAGAction::SetIR(ir) => {
+                self.set_endir(JTAGState::RunTestIdle, &mut out);
+                out.push(SvfCommand::SIR(scan(ir.len(), ir.clone())));
+                self.state = JTAGState::RunTestIdle;
+            }
+            JTAGAction::ReadReg { ir, drlen } => {
+                self.set_endir(JTAGState::RunTestIdle, &mut out);
+                self.set_enddr(JTAGState::RunTestIdle, &mut out);
+                out.push(SvfCommand::SIR(scan(ir.len(), ir.clone())));
+                out.push(SvfCommand::SDR(scan(*drlen, bitvec![0; *drlen])));
+                self.state = JTAGState::RunTestIdle;
+            }
+            JTAGAction::WriteReg { ir, dr } => {
+                self.set_endir(JTAGState::RunTestIdle, &mut out);
+                self.set_enddr(JTAGState::RunTestIdle, &mut out);
+                out.push(SvfCommand::SIR(scan(ir.len(), ir.clone())));
+                out.push(SvfCommand::SDR(scan(dr.len(), dr.clone())));
+                self.state = JTAGState::RunTestIdle;
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl<'a> SvfRecorder<'a> {
+    pub fn new(inner: Option<&'a mut dyn JTAGAdapter>) -> Self {
+        Self {
+            jtag_state: JTAGAdapterState::new(),
+            inner,
+            svf: Svf::default(),
+            record_tdo: false,
+            tap: RecordedTap {
+                state: JTAGState::TestLogicReset,
+                endir: JTAGState::RunTestIdle,
+                enddr: JTAGState::RunTestIdle,
+            },
+        }
+    }
+
+    /// Also record data captured by the inner adapter as expected TDO data,
+    /// so that playing the SVF verifies that the same data is read back
+    pub fn with_tdo_recording(mut self, record_tdo: bool) -> Self {
+        self.record_tdo = record_tdo;
+        self
+    }
+
+    /// The SVF recorded so far
+    pub fn svf(&self) -> &Svf {
+        &self.svf
+    }
+    pub fn into_svf(self) -> Svf {
+        self.svf
+    }
+
+    /// Flush and then set the expected TDO data of the last recorded scan.
+    /// This is how flows generating an SVF without hardware attached check
+    /// data that would otherwise be read back and compared.
+    pub fn expect_tdo(&mut self, tdo: &BitSlice, mask: &BitSlice) -> Result<(), JTAGError> {
+        self.flush()?;
+        match self.svf.commands.last_mut() {
+            Some(SvfCommand::SIR(scan) | SvfCommand::SDR(scan))
+                if scan.len == tdo.len() && scan.len == mask.len() =>
+            {
+                scan.tdo = Some(tdo.to_bitvec());
+                scan.mask = Some(mask.to_bitvec());
+                Ok(())
+            }
+            _ => Err(JTAGError::InvalidAction(format!(
+                "last command is not a {} bit scan",
+                tdo.len()
+            ))),
+        }
+    }
+}
+
+fn dummy_output(action: &JTAGAction) -> JTAGOutput {
+    match action {
+        JTAGAction::DelayNS(ns) => JTAGOutput::ActualDelay(*ns),
+        JTAGAction::SetClkSpeed(hz) => JTAGOutput::ActualClkSpeed(*hz),
+        JTAGAction::ShiftIR {
+            ir, capture: true, ..
+        } => JTAGOutput::CapturedBits(BitVec::repeat(false, ir.len())),
+        JTAGAction::ShiftDR {
+            dr, capture: true, ..
+        } => JTAGOutput::CapturedBits(BitVec::repeat(false, dr.len())),
+        JTAGAction::ReadReg { drlen, .. } => {
+            JTAGOutput::CapturedBits(BitVec::repeat(false, *drlen))
+        }
+        _ => JTAGOutput::NoData,
+    }
+}
+
+impl<'a> JTAGAdapter for SvfRecorder<'a> {
+    fn execute_actions(&mut self, actions: &[JTAGAction]) -> Result<Vec<JTAGOutput>, JTAGError> {
+        // Translate everything first so that nothing is executed if some
+        // action cannot be recorded, and only keep the new TAP state once
+        // the actions have actually been executed
+        let mut tap = self.tap;
+        let mut translated = Vec::with_capacity(actions.len());
+        for action in actions {
+            translated.push(tap.translate(action)?);
+        }
+
+        let outputs = match &mut self.inner {
+            Some(inner) => inner.execute_actions(actions)?,
+            None => actions.iter().map(dummy_output).collect(),
+        };
+        self.tap = tap;
+
+        for (mut commands, output) in translated.into_iter().zip(&outputs) {
+            if let (true, Some(_), JTAGOutput::CapturedBits(bits)) =
+                (self.record_tdo, &self.inner, output)
+            {
+                if let Some(SvfCommand::SIR(scan) | SvfCommand::SDR(scan)) = commands.last_mut() {
+                    scan.tdo = Some(bits.clone());
+                }
+            }
+            self.svf.commands.extend(commands);
+        }
+
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    #[test]
+    fn test_svfrecorder_standalone() {
+        let mut recorder = SvfRecorder::new(None);
+        recorder.reset_to_tlr();
+        recorder.go_rti();
+        recorder.queue_action(JTAGAction::SetClkSpeed(1_000_000));
+        recorder.shift_ir_out(bits![0, 1, 1, 0], true);
+        recorder.queue_action(JTAGAction::DelayNS(1000));
+        assert_eq!(
+            recorder.read_reg(bits![0, 1, 1, 0], 32).unwrap(),
+            bits![0; 32]
+        );
+        recorder.write_reg(bits![1, 0, 0, 0], bits![1, 0, 1, 0, 0, 1, 0, 1]);
+        recorder.go_pausedr();
+        recorder.flush().unwrap();
+
+        assert_eq!(
+            recorder.svf().to_string(),
+            "STATE RESET;
+STATE IDLE;
+FREQUENCY 1E6 HZ;
+ENDIR IRPAUSE;
+SIR 4 TDI (6);
+RUNTEST IRPAUSE 1E-6 SEC ENDSTATE IRPAUSE;
+ENDIR IDLE;
+SIR 4 TDI (6);
+SDR 32 TDI (00000000);
+SIR 4 TDI (1);
+SDR 8 TDI (A5);
+STATE DRSELECT DRCAPTURE DREXIT1 DRPAUSE;
+"
+        );
+
+        // these can't be expressed in SVF
+        recorder.go_shiftdr();
+        assert!(matches!(
+            recorder.flush(),
+            Err(JTAGError::InvalidAction(..))
+        ));
+        recorder.shift_bits_out(bits![0, 1], false);
+        assert!(matches!(
+            recorder.flush(),
+            Err(JTAGError::InvalidAction(..))
+        ));
+    }
+
+    /// Adapter which fails the first `failures` batches of actions
+    struct FailingAdapter {
+        jtag_state: JTAGAdapterState,
+        failures: usize,
+    }
+    impl AsMut<JTAGAdapterState> for FailingAdapter {
+        fn as_mut(&mut self) -> &mut JTAGAdapterState {
+            &mut self.jtag_state
+        }
+    }
+    impl JTAGAdapter for FailingAdapter {
+        fn execute_actions(
+            &mut self,
+            actions: &[JTAGAction],
+        ) -> Result<Vec<JTAGOutput>, JTAGError> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(JTAGError::Timeout);
+            }
+            Ok(vec![JTAGOutput::NoData; actions.len()])
+        }
+    }
+
+    #[test]
+    fn test_svfrecorder_failed_batch() {
+        let mut adapter = FailingAdapter {
+            jtag_state: JTAGAdapterState::new(),
+            failures: 1,
+        };
+        let mut recorder = SvfRecorder::new(Some(&mut adapter));
+        recorder.shift_ir_out(bits![0, 1, 1, 0], true);
+        assert!(matches!(recorder.flush(), Err(JTAGError::Timeout)));
+
+        // nothing from the failed batch is recorded or remembered
+        recorder.shift_ir_out(bits![0, 1, 1, 0], true);
+        recorder.flush().unwrap();
+        assert_eq!(
+            recorder.svf().to_string(),
+            "ENDIR IRPAUSE;
+SIR 4 TDI (6);
+"
+        );
+    }
+
+    #[test]
+    fn test_svfrecorder_replay() {
+        let device = || {
+            drivers::GenericSimulatedDevice::new(4)
+                .with_idcode(0x0123_4093, bits![0, 1, 1, 0])
+                .with_register(bits![1, 0, 0, 0], bits![0; 8])
+        };
+
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(device())]);
+        let mut recorder = SvfRecorder::new(Some(&mut adapter)).with_tdo_recording(true);
+        recorder.reset_to_tlr();
+        recorder.write_reg(bits![1, 0, 0, 0], bits![1, 0, 1, 0, 0, 1, 0, 1]);
+        let idcode = recorder.read_reg(bits![0, 1, 1, 0], 32).unwrap();
+        assert_eq!(idcode.load_le::<u32>(), 0x0123_4093);
+        let svf = recorder.into_svf();
+        assert_eq!(adapter.current_ir(0), bits![0, 1, 1, 0]);
+
+        // the recorded IDCODE is checked when playing it back
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(device())]);
+        svf.play(&mut adapter).unwrap();
+        let reg = adapter.read_reg(bits![1, 0, 0, 0], 8).unwrap();
+        assert_eq!(reg, bits![1, 0, 1, 0, 0, 1, 0, 1]);
+
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(
+            drivers::GenericSimulatedDevice::new(4).with_idcode(0x0123_5093, bits![0, 1, 1, 0]),
+        )]);
+        assert!(matches!(
+            svf.play(&mut adapter),
+            Err(JTAGError::VerifyFailed(..))
+        ));
+    }
+}
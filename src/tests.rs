@@ -33,11 +33,11 @@ impl TestBitbangJTAGAdapter {
 }
 
 impl BitbangJTAGAdapter for TestBitbangJTAGAdapter {
-    fn set_clk_speed(&mut self, _clk_hz: u64) -> u64 {
+    fn set_clk_speed(&mut self, _clk_hz: u64) -> Result<u64, JTAGError> {
         todo!()
     }
 
-    fn shift_one_bit(&mut self, _tms: bool, _tdi: bool) -> bool {
+    fn shift_one_bit(&mut self, _tms: bool, _tdi: bool) -> Result<bool, JTAGError> {
         todo!()
     }
 }
@@ -66,20 +66,24 @@ impl TestChunkJTAGAdapter {
 }
 
 impl ChunkShifterJTAGAdapter for TestChunkJTAGAdapter {
-    fn delay_ns(&mut self, _ns: u64) -> u64 {
+    fn delay_ns(&mut self, _ns: u64) -> Result<u64, JTAGError> {
         todo!()
     }
-    fn set_clk_speed(&mut self, _clk_hz: u64) -> u64 {
+    fn set_clk_speed(&mut self, _clk_hz: u64) -> Result<u64, JTAGError> {
         todo!()
     }
 
-    fn shift_tms_chunk(&mut self, _tms_chunk: &BitSlice) {
+    fn shift_tms_chunk(&mut self, _tms_chunk: &BitSlice) -> Result<(), JTAGError> {
         todo!()
     }
-    fn shift_tdi_chunk(&mut self, _tdi_chunk: &BitSlice, _tms_exit: bool) {
+    fn shift_tdi_chunk(&mut self, _tdi_chunk: &BitSlice, _tms_exit: bool) -> Result<(), JTAGError> {
         todo!()
     }
-    fn shift_tditdo_chunk(&mut self, _tdi_chunk: &BitSlice, _tms_exit: bool) -> BitVec {
+    fn shift_tditdo_chunk(
+        &mut self,
+        _tdi_chunk: &BitSlice,
+        _tms_exit: bool,
+    ) -> Result<BitVec, JTAGError> {
         todo!()
     }
 }
@@ -101,7 +105,7 @@ impl TestStateJTAGAdapter {
 }
 
 impl StateTrackingJTAGAdapter for TestStateJTAGAdapter {
-    fn execute_stjtag_action(&mut self, _action: &JTAGAction) -> JTAGOutput {
+    fn execute_stjtag_action(&mut self, _action: &JTAGAction) -> Result<JTAGOutput, JTAGError> {
         todo!()
     }
 }
@@ -126,3 +130,81 @@ fn test_state_tracking_adapter() {
 
     testadapter.reset_to_tlr();
 }
+
+fn simulated_idcode_device(idcode: u32) -> Box<dyn drivers::SimulatedDevice> {
+    Box::new(
+        drivers::GenericSimulatedDevice::new(4)
+            .with_idcode(idcode, bits![0, 1, 1, 1])
+            .with_register(bits![1, 0, 0, 0], bits![0; 12]),
+    )
+}
+
+#[test]
+fn test_simulated_idcode() {
+    let mut testadapter =
+        drivers::SimulatedJTAGAdapter::new(vec![simulated_idcode_device(0x1234_5679)]);
+
+    testadapter.reset_to_tlr();
+    let idcode = testadapter.shift_dr_inout(bits![0; 32], false).unwrap();
+    assert_eq!(idcode.load_le::<u32>(), 0x1234_5679);
+    assert_eq!(testadapter.tap_state(), JTAGState::RunTestIdle);
+
+    // the same value must come out when split across several shifts
+    testadapter.go_shiftdr();
+    let idcode_a = testadapter.shift_bits_inout(bits![0; 13], false).unwrap();
+    let idcode_b = testadapter.shift_bits_inout(bits![0; 19], true).unwrap();
+    testadapter.go_rti();
+    testadapter.flush().unwrap();
+    assert_eq!(idcode_a.load_le::<u32>(), 0x1234_5679 & 0x1fff);
+    assert_eq!(idcode_b.load_le::<u32>(), 0x1234_5679 >> 13);
+}
+
+#[test]
+fn test_simulated_ir_capture_and_bypass() {
+    let mut testadapter =
+        drivers::SimulatedJTAGAdapter::new(vec![simulated_idcode_device(0x1234_5679)]);
+
+    testadapter.reset_to_tlr();
+    let ir = testadapter.shift_ir_inout(bits![1; 4], false).unwrap();
+    assert_eq!(ir, bits![1, 0, 0, 0]);
+    assert_eq!(testadapter.current_ir(0), bits![1; 4]);
+
+    let dr = testadapter
+        .shift_dr_inout(bits![1, 0, 1, 1, 0], false)
+        .unwrap();
+    assert_eq!(dr, bits![0, 1, 0, 1, 1]);
+}
+
+#[test]
+fn test_simulated_write_read_reg() {
+    let mut testadapter =
+        drivers::SimulatedJTAGAdapter::new(vec![simulated_idcode_device(0x1234_5679)]);
+
+    testadapter.reset_to_tlr();
+    testadapter.write_reg(bits![1, 0, 0, 0], bits![1, 1, 0, 0, 1, 0, 1, 0, 0, 0, 1, 1]);
+    let reg = testadapter.read_reg(bits![1, 0, 0, 0], 12).unwrap();
+    assert_eq!(reg, bits![1, 1, 0, 0, 1, 0, 1, 0, 0, 0, 1, 1]);
+
+    // going through Test-Logic-Reset reselects IDCODE
+    testadapter.reset_to_tlr();
+    testadapter.flush().unwrap();
+    assert_eq!(testadapter.current_ir(0), bits![0, 1, 1, 1]);
+}
+
+#[test]
+fn test_simulated_chain() {
+    let mut testadapter = drivers::SimulatedJTAGAdapter::new(vec![
+        simulated_idcode_device(0x1111_1111),
+        Box::new(drivers::GenericSimulatedDevice::new(6)),
+        simulated_idcode_device(0x2222_2223),
+    ]);
+
+    testadapter.reset_to_tlr();
+    let dr = testadapter.shift_dr_inout(bits![0; 65], false).unwrap();
+    assert_eq!(dr[..32].load_le::<u32>(), 0x1111_1111);
+    assert!(!dr[32]);
+    assert_eq!(dr[33..].load_le::<u32>(), 0x2222_2223);
+
+    let ir = testadapter.shift_ir_inout(bits![1; 14], false).unwrap();
+    assert_eq!(ir, bits![1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 1, 0, 0, 0]);
+}
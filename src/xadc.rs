@@ -0,0 +1,235 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+const XADC_DRP: u8 = 0x37;
+const IR_LEN: usize = 6;
+
+const CMD_NOP: u32 = 0b00;
+const CMD_READ: u32 = 0b01;
+const CMD_WRITE: u32 = 0b10;
+
+/// DRP addresses of the status registers holding the latest measurements
+const TEMPERATURE: u16 = 0x00;
+const VCCINT: u16 = 0x01;
+const VCCAUX: u16 = 0x02;
+const VCCBRAM: u16 = 0x06;
+
+/// The 32-bit XADC_DRP data register: data in bits 15:0, the DRP address in
+/// bits 25:16 and the command in bits 31:30
+fn drp_bits(cmd: u32, addr: u16, data: u16) -> BitVec {
+    let value = cmd << 30 | ((addr as u32) & 0x3ff) << 16 | data as u32;
+    value.view_bits::<Lsb0>().iter().by_vals().collect()
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// Which analog monitor a device has, which determines how codes are
+/// converted into temperatures
+pub enum XadcKind {
+    /// XADC of 7-series devices
+    Xadc,
+    /// SYSMONE1 of UltraScale devices
+    SysmonE1,
+    /// SYSMONE4 of UltraScale+ devices
+    SysmonE4,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+/// One set of measurements, in degrees Celsius and volts
+pub struct XadcReadings {
+    pub temperature: f64,
+    pub vccint: f64,
+    pub vccaux: f64,
+    pub vccbram: f64,
+}
+
+/// Access to the XADC or SYSMON of a Xilinx FPGA through its DRP
+/// (dynamic reconfiguration port) using the XADC_DRP instruction
+///
+/// This works whether or not the device is configured, as long as the
+/// bitstream (if any) does not instantiate the XADC itself. The adapter is
+/// passed into each method like [Series7].
+pub struct Xadc {
+    kind: XadcKind,
+}
+
+impl Xadc {
+    pub fn new(kind: XadcKind) -> Self {
+        Self { kind }
+    }
+
+    pub fn kind(&self) -> XadcKind {
+        self.kind
+    }
+
+    /// Read a DRP register. DRP commands are pipelined, so the result is
+    /// captured while shifting in a NOP
+    pub fn read_drp<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        addr: u16,
+    ) -> Result<u16, JTAGError> {
+        adapter.shift_ir_out(&insn(XADC_DRP, IR_LEN), false);
+        adapter.shift_dr_out(&drp_bits(CMD_READ, addr, 0), false);
+        let out = adapter.shift_dr_inout(&drp_bits(CMD_NOP, 0, 0), false)?;
+        Ok(out[..16].load_le())
+    }
+
+    /// Write a DRP register, e.g. to change the sequencer configuration
+    pub fn write_drp<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        addr: u16,
+        data: u16,
+    ) -> Result<(), JTAGError> {
+        adapter.shift_ir_out(&insn(XADC_DRP, IR_LEN), false);
+        adapter.shift_dr_out(&drp_bits(CMD_WRITE, addr, data), false);
+        adapter.shift_dr_out(&drp_bits(CMD_NOP, 0, 0), false);
+        adapter.flush()?;
+        Ok(())
+    }
+
+    /// Convert a temperature status register value to degrees Celsius
+    pub fn temperature(&self, raw: u16) -> f64 {
+        let code = (raw >> 4) as f64;
+        match self.kind {
+            XadcKind::Xadc => code * 503.975 / 4096.0 - 273.15,
+            XadcKind::SysmonE1 => code * 502.9098 / 4096.0 - 273.8195,
+            XadcKind::SysmonE4 => code * 509.3140064 / 4096.0 - 280.2308787,
+        }
+    }
+
+    /// Convert a supply status register value (VCCINT, VCCAUX, VCCBRAM) to
+    /// volts
+    pub fn supply_voltage(&self, raw: u16) -> f64 {
+        (raw >> 4) as f64 * 3.0 / 4096.0
+    }
+
+    pub fn read_temperature<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+    ) -> Result<f64, JTAGError> {
+        Ok(self.temperature(self.read_drp(adapter, TEMPERATURE)?))
+    }
+
+    pub fn read_vccint<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<f64, JTAGError> {
+        Ok(self.supply_voltage(self.read_drp(adapter, VCCINT)?))
+    }
+
+    pub fn read_vccaux<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<f64, JTAGError> {
+        Ok(self.supply_voltage(self.read_drp(adapter, VCCAUX)?))
+    }
+
+    pub fn read_vccbram<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<f64, JTAGError> {
+        Ok(self.supply_voltage(self.read_drp(adapter, VCCBRAM)?))
+    }
+
+    pub fn read_all<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+    ) -> Result<XadcReadings, JTAGError> {
+        Ok(XadcReadings {
+            temperature: self.read_temperature(adapter)?,
+            vccint: self.read_vccint(adapter)?,
+            vccaux: self.read_vccaux(adapter)?,
+            vccbram: self.read_vccbram(adapter)?,
+        })
+    }
+
+    /// Read all measurements every `interval_ns` and pass them to `f` until
+    /// it returns false
+    pub fn poll<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        interval_ns: u64,
+        mut f: impl FnMut(XadcReadings) -> bool,
+    ) -> Result<(), JTAGError> {
+        while f(self.read_all(adapter)?) {
+            adapter.queue_action(JTAGAction::DelayNS(interval_ns));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+    use std::collections::HashMap;
+
+    /// An XADC with the DRP registers in a map. Like the real thing, the
+    /// result of a read is returned on the following DR scan.
+    struct SimXadc {
+        regs: HashMap<u16, u16>,
+        result: u16,
+    }
+
+    impl drivers::XilinxSimulatedDevice for SimXadc {
+        fn capture_dr(&mut self, ir: &BitSlice) -> BitVec {
+            if ir.load_le::<u8>() == 0x37 {
+                (self.result as u32)
+                    .view_bits::<Lsb0>()
+                    .iter()
+                    .by_vals()
+                    .collect()
+            } else {
+                bitvec![0]
+            }
+        }
+        fn update_dr(&mut self, ir: &BitSlice, dr: &BitSlice) {
+            if ir.load_le::<u8>() != 0x37 {
+                return;
+            }
+            let value = dr.load_le::<u32>();
+            let addr = (value >> 16 & 0x3ff) as u16;
+            match value >> 30 {
+                0b01 => self.result = self.regs.get(&addr).copied().unwrap_or(0),
+                0b10 => {
+                    self.regs.insert(addr, value as u16);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn sim_xadc() -> drivers::SimulatedJTAGAdapter {
+        let regs = HashMap::from([
+            (0x00, 0x9a30),
+            (0x01, 0x5550),
+            (0x02, 0x9990),
+            (0x06, 0x5560),
+        ]);
+        drivers::SimulatedJTAGAdapter::new(vec![Box::new(SimXadc { regs, result: 0 })])
+    }
+
+    #[test]
+    fn test_xadc_read() {
+        let mut adapter = sim_xadc();
+        let xadc = Xadc::new(XadcKind::Xadc);
+        let readings = xadc.read_all(&mut adapter).unwrap();
+        assert!((readings.temperature - 30.39).abs() < 0.01);
+        assert!((readings.vccint - 0.9998).abs() < 0.001);
+        assert!((readings.vccaux - 1.7996).abs() < 0.001);
+        assert!((readings.vccbram - 1.0005).abs() < 0.001);
+
+        xadc.write_drp(&mut adapter, 0x41, 0x2000).unwrap();
+        assert_eq!(xadc.read_drp(&mut adapter, 0x41).unwrap(), 0x2000);
+    }
+
+    #[test]
+    fn test_xadc_poll() {
+        let mut adapter = sim_xadc();
+        let xadc = Xadc::new(XadcKind::SysmonE4);
+        let mut count = 0;
+        xadc.poll(&mut adapter, 1_000_000, |readings| {
+            assert!((readings.temperature - 26.53).abs() < 0.01);
+            count += 1;
+            count < 3
+        })
+        .unwrap();
+        adapter.flush().unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(adapter.delayed_ns(), 2_000_000);
+    }
+}
@@ -0,0 +1,664 @@
+use crate::*;
+
+// Physical layout of the CoolRunner-II fuse arrays, following the layout
+// documented by the xc2bit project (part of openfpga), as of xc2bit 0.0.4
+// (openfpga commit 4d62eceb64a306908a07210f304bc2c0af75a7c7, src/xc2bit).
+// Coordinates are (column, row) in the fuse array.
+
+/// Number of inputs to each function block from the ZIA
+const INPUTS: usize = 40;
+/// Number of product terms in each function block
+const TERMS: usize = 56;
+/// Number of macrocells in each function block
+const MACROCELLS: usize = 16;
+
+/// Physical position of each bit of the configuration of a macrocell (and
+/// its IOB, if any), indexed by its offset in the JED file
+const MC_32: [(usize, usize); 27] = [
+    (0, 0),
+    (1, 0),
+    (2, 0),
+    (3, 0),
+    (4, 0),
+    (5, 0),
+    (6, 0),
+    (7, 0),
+    (8, 0),
+    (0, 1),
+    (1, 1),
+    (2, 1),
+    (3, 1),
+    (4, 1),
+    (5, 1),
+    (6, 1),
+    (7, 1),
+    (8, 1),
+    (0, 2),
+    (1, 2),
+    (2, 2),
+    (3, 2),
+    (4, 2),
+    (5, 2),
+    (6, 2),
+    (7, 2),
+    (8, 2),
+];
+const MC_64: [(usize, usize); 27] = [
+    (8, 0),
+    (7, 0),
+    (5, 0),
+    (6, 0),
+    (4, 0),
+    (2, 0),
+    (3, 0),
+    (0, 0),
+    (1, 0),
+    (7, 1),
+    (8, 1),
+    (5, 1),
+    (6, 1),
+    (3, 1),
+    (4, 1),
+    (2, 1),
+    (1, 1),
+    (7, 2),
+    (8, 2),
+    (0, 1),
+    (3, 2),
+    (4, 2),
+    (5, 2),
+    (6, 2),
+    (2, 2),
+    (1, 2),
+    (0, 2),
+];
+const MC_256: [(usize, usize); 29] = [
+    (9, 0),
+    (7, 0),
+    (8, 0),
+    (6, 0),
+    (5, 0),
+    (4, 0),
+    (2, 0),
+    (3, 0),
+    (0, 0),
+    (1, 0),
+    (9, 1),
+    (7, 1),
+    (8, 1),
+    (3, 1),
+    (4, 1),
+    (5, 1),
+    (6, 1),
+    (1, 1),
+    (2, 1),
+    (0, 1),
+    (8, 2),
+    (6, 2),
+    (7, 2),
+    (4, 2),
+    (5, 2),
+    (3, 2),
+    (2, 2),
+    (0, 2),
+    (1, 2),
+];
+const MC_256_BURIED: [(usize, usize); 16] = [
+    (9, 0),
+    (7, 0),
+    (8, 0),
+    (6, 0),
+    (5, 0),
+    (2, 0),
+    (3, 0),
+    (1, 1),
+    (2, 1),
+    (0, 1),
+    (6, 2),
+    (7, 2),
+    (4, 2),
+    (5, 2),
+    (0, 2),
+    (1, 2),
+];
+const MC_LARGE: [(usize, usize); 29] = [
+    (8, 0),
+    (9, 0),
+    (10, 0),
+    (11, 0),
+    (12, 0),
+    (4, 0),
+    (2, 0),
+    (3, 0),
+    (5, 0),
+    (6, 0),
+    (13, 0),
+    (0, 0),
+    (1, 0),
+    (2, 1),
+    (3, 1),
+    (4, 1),
+    (5, 1),
+    (13, 1),
+    (14, 1),
+    (14, 0),
+    (8, 1),
+    (9, 1),
+    (10, 1),
+    (11, 1),
+    (12, 1),
+    (6, 1),
+    (7, 0),
+    (0, 1),
+    (1, 1),
+];
+const MC_LARGE_BURIED: [(usize, usize); 16] = [
+    (8, 0),
+    (9, 0),
+    (10, 0),
+    (11, 0),
+    (12, 0),
+    (2, 0),
+    (3, 0),
+    (13, 1),
+    (14, 1),
+    (14, 0),
+    (9, 1),
+    (10, 1),
+    (11, 1),
+    (12, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// First row of each macrocell on devices where they are 2 rows high
+const MC_ROWS_LARGE: [usize; MACROCELLS] =
+    [0, 3, 5, 8, 10, 13, 15, 18, 20, 23, 25, 28, 30, 33, 35, 38];
+
+/// Product term stored in each column pair of the AND array on devices
+/// with the OR array beside it
+const AND_TERMS_SIDE: [usize; TERMS] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 55, 54, 53, 11, 12, 13, 52, 51, 50, 14, 15, 16, 49, 48, 47,
+    17, 18, 19, 46, 45, 44, 20, 21, 22, 43, 42, 41, 23, 24, 25, 40, 39, 38, 26, 27, 28, 37, 36, 35,
+    29, 30, 31, 34, 33, 32,
+];
+/// Row of the OR array holding each pair of product terms on devices with
+/// the OR array beside the AND array
+const OR_ROWS_SIDE: [usize; TERMS / 2] = [
+    17, 19, 22, 20, 0, 1, 3, 4, 5, 7, 8, 11, 12, 13, 15, 16, 23, 24, 26, 27, 28, 31, 32, 34, 35,
+    36, 38, 39,
+];
+
+struct Layout {
+    /// Number of bits selecting each ZIA input
+    zia_width: usize,
+    /// Whether the OR array sits between the two halves of the AND array
+    /// (rather than beside it) and the macrocells are 3 rows high
+    or_in_middle: bool,
+    /// Column of the first AND array column of the left and right function
+    /// block of each group. Right function blocks are mirrored.
+    groups: &'static [(usize, usize)],
+    /// Number of function blocks in each group
+    group_fbs: usize,
+    /// Configuration of each macrocell with and without an IOB
+    mc: &'static [(usize, usize)],
+    mc_buried: &'static [(usize, usize)],
+    /// Columns between the first AND array column and the first macrocell
+    /// column
+    mc_offset: usize,
+    /// Range of macrocells without an IOB in each function block
+    buried: &'static [(usize, usize)],
+    /// Position of each fuse following the function blocks
+    globals: &'static [(usize, usize)],
+    /// Column of the DONE bit in the first row after the fuses
+    done: usize,
+}
+
+fn layout(device: Xc2cDevice) -> Layout {
+    const GLOBALS_32: [(usize, usize); 18] = [
+        (126, 23),
+        (127, 23),
+        (128, 23),
+        (129, 23),
+        (130, 23),
+        (126, 24),
+        (127, 24),
+        (128, 24),
+        (129, 24),
+        (126, 25),
+        (127, 25),
+        (128, 25),
+        (129, 25),
+        (131, 23),
+        (130, 24),
+        (130, 25),
+        (131, 24),
+        (132, 24),
+    ];
+    const GLOBALS_32A: [(usize, usize); 22] = [
+        (126, 23),
+        (127, 23),
+        (128, 23),
+        (129, 23),
+        (130, 23),
+        (126, 24),
+        (127, 24),
+        (128, 24),
+        (129, 24),
+        (126, 25),
+        (127, 25),
+        (128, 25),
+        (129, 25),
+        (131, 23),
+        (130, 24),
+        (130, 25),
+        (131, 24),
+        (132, 24),
+        (131, 25),
+        (132, 25),
+        (133, 25),
+        (134, 25),
+    ];
+    const GLOBALS_64: [(usize, usize); 16] = [
+        (133, 23),
+        (134, 23),
+        (135, 23),
+        (135, 73),
+        (136, 73),
+        (133, 24),
+        (134, 24),
+        (135, 24),
+        (136, 24),
+        (137, 73),
+        (138, 73),
+        (137, 24),
+        (138, 24),
+        (136, 23),
+        (137, 23),
+        (138, 23),
+    ];
+    const GLOBALS_64A: [(usize, usize); 20] = [
+        (133, 23),
+        (134, 23),
+        (135, 23),
+        (135, 73),
+        (136, 73),
+        (133, 24),
+        (134, 24),
+        (135, 24),
+        (136, 24),
+        (137, 73),
+        (138, 73),
+        (137, 24),
+        (138, 24),
+        (136, 23),
+        (137, 23),
+        (138, 23),
+        (139, 23),
+        (140, 23),
+        (141, 23),
+        (142, 23),
+    ];
+    const GLOBALS_128: [(usize, usize); 25] = [
+        (365, 67),
+        (366, 67),
+        (367, 67),
+        (364, 67),
+        (363, 67),
+        (362, 67),
+        (361, 67),
+        (360, 67),
+        (1, 67),
+        (2, 67),
+        (4, 27),
+        (5, 27),
+        (6, 27),
+        (7, 27),
+        (4, 67),
+        (5, 67),
+        (6, 67),
+        (7, 67),
+        (370, 67),
+        (371, 67),
+        (8, 67),
+        (368, 67),
+        (9, 67),
+        (369, 67),
+        (10, 67),
+    ];
+    const GLOBALS_256: [(usize, usize); 25] = [
+        (519, 23),
+        (520, 23),
+        (521, 23),
+        (519, 24),
+        (518, 24),
+        (517, 24),
+        (516, 24),
+        (515, 24),
+        (178, 23),
+        (179, 23),
+        (181, 23),
+        (182, 23),
+        (176, 24),
+        (177, 24),
+        (178, 24),
+        (179, 24),
+        (181, 24),
+        (182, 24),
+        (517, 23),
+        (518, 23),
+        (175, 23),
+        (515, 23),
+        (176, 23),
+        (516, 23),
+        (177, 23),
+    ];
+    const GLOBALS_384: [(usize, usize); 29] = [
+        (467, 102),
+        (468, 102),
+        (469, 102),
+        (471, 107),
+        (470, 107),
+        (469, 107),
+        (468, 107),
+        (467, 107),
+        (1, 97),
+        (2, 97),
+        (463, 102),
+        (463, 107),
+        (464, 102),
+        (464, 107),
+        (465, 102),
+        (465, 107),
+        (466, 102),
+        (466, 107),
+        (931, 17),
+        (932, 17),
+        (936, 17),
+        (1864, 17),
+        (1, 17),
+        (929, 17),
+        (937, 17),
+        (1865, 17),
+        (2, 17),
+        (930, 17),
+        (3, 17),
+    ];
+    const GLOBALS_512: [(usize, usize); 29] = [
+        (979, 147),
+        (980, 147),
+        (981, 147),
+        (978, 147),
+        (977, 147),
+        (976, 147),
+        (975, 147),
+        (974, 147),
+        (1, 27),
+        (2, 27),
+        (3, 27),
+        (4, 27),
+        (480, 27),
+        (481, 27),
+        (5, 27),
+        (6, 27),
+        (7, 27),
+        (8, 27),
+        (983, 147),
+        (982, 147),
+        (992, 147),
+        (1965, 147),
+        (3, 147),
+        (985, 147),
+        (991, 147),
+        (1964, 147),
+        (2, 147),
+        (984, 147),
+        (1, 147),
+    ];
+
+    let small = |globals: &'static [(usize, usize)]| Layout {
+        zia_width: 8,
+        or_in_middle: true,
+        groups: &[(10, 249)],
+        group_fbs: 2,
+        mc: &MC_32,
+        mc_buried: &MC_32,
+        mc_offset: 9,
+        buried: &[(0, 0); 2],
+        globals,
+        done: 9,
+    };
+    let medium = |globals: &'static [(usize, usize)]| Layout {
+        zia_width: 16,
+        or_in_middle: true,
+        groups: &[(9, 264)],
+        group_fbs: 4,
+        mc: &MC_64,
+        mc_buried: &MC_64,
+        mc_offset: 9,
+        buried: &[(0, 0); 4],
+        globals,
+        done: 8,
+    };
+    match device {
+        Xc2cDevice::XC2C32 => small(&GLOBALS_32),
+        Xc2cDevice::XC2C32A => small(&GLOBALS_32A),
+        Xc2cDevice::XC2C64 => medium(&GLOBALS_64),
+        Xc2cDevice::XC2C64A => medium(&GLOBALS_64A),
+        Xc2cDevice::XC2C128 => Layout {
+            zia_width: 28,
+            or_in_middle: false,
+            groups: &[(48, 327), (424, 703)],
+            group_fbs: 4,
+            mc: &MC_LARGE,
+            mc_buried: &MC_LARGE_BURIED,
+            mc_offset: 47,
+            buried: &[
+                (6, 10),
+                (6, 10),
+                (7, 10),
+                (7, 10),
+                (7, 10),
+                (6, 10),
+                (7, 10),
+                (6, 10),
+            ],
+            globals: &GLOBALS_128,
+            done: 9,
+        },
+        Xc2cDevice::XC2C256 => Layout {
+            zia_width: 48,
+            or_in_middle: true,
+            groups: &[(11, 330), (351, 670), (693, 1012), (1033, 1352)],
+            group_fbs: 4,
+            mc: &MC_256,
+            mc_buried: &MC_256_BURIED,
+            mc_offset: 10,
+            buried: &[
+                (6, 11),
+                (6, 11),
+                (6, 11),
+                (6, 11),
+                (6, 11),
+                (6, 11),
+                (6, 10),
+                (6, 10),
+                (6, 10),
+                (6, 10),
+                (6, 10),
+                (6, 10),
+                (6, 11),
+                (6, 11),
+                (6, 10),
+                (6, 10),
+            ],
+            globals: &GLOBALS_256,
+            done: 9,
+        },
+        Xc2cDevice::XC2C384 => Layout {
+            zia_width: 74,
+            or_in_middle: false,
+            groups: &[(48, 419), (514, 885), (982, 1353), (1448, 1819)],
+            group_fbs: 6,
+            mc: &MC_LARGE,
+            mc_buried: &MC_LARGE_BURIED,
+            mc_offset: 47,
+            buried: &[(5, 11); 24],
+            globals: &GLOBALS_384,
+            done: 9,
+        },
+        Xc2cDevice::XC2C512 => Layout {
+            zia_width: 88,
+            or_in_middle: false,
+            groups: &[(48, 447), (542, 941), (1038, 1437), (1532, 1931)],
+            group_fbs: 8,
+            mc: &MC_LARGE,
+            mc_buried: &MC_LARGE_BURIED,
+            mc_offset: 47,
+            buried: &[
+                (4, 12),
+                (4, 12),
+                (5, 12),
+                (4, 12),
+                (5, 12),
+                (4, 12),
+                (5, 12),
+                (4, 12),
+                (4, 12),
+                (4, 12),
+                (4, 12),
+                (5, 12),
+                (4, 12),
+                (5, 12),
+                (4, 12),
+                (5, 12),
+                (5, 12),
+                (4, 12),
+                (5, 12),
+                (4, 12),
+                (5, 12),
+                (4, 12),
+                (5, 12),
+                (4, 12),
+                (4, 12),
+                (5, 12),
+                (4, 12),
+                (5, 12),
+                (4, 12),
+                (5, 12),
+                (4, 12),
+                (5, 12),
+            ],
+            globals: &GLOBALS_512,
+            done: 9,
+        },
+    }
+}
+
+/// Position of every JED fuse in the fuse array, in JED order
+fn fuse_positions(device: Xc2cDevice) -> Vec<(usize, usize)> {
+    let layout = layout(device);
+    let fb_rows = if layout.or_in_middle { 48 } else { 40 };
+    // rows of the AND array and ZIA skip the OR array in the middle
+    let and_row = |row: usize| {
+        if layout.or_in_middle && row >= INPUTS / 2 {
+            row + 8
+        } else {
+            row
+        }
+    };
+
+    let mut ret = Vec::with_capacity(device.jed_fuses());
+    for fb in 0..layout.groups.len() * layout.group_fbs {
+        let group = layout.groups[fb / layout.group_fbs];
+        let mirror = fb % 2 == 1;
+        let x = if mirror { group.1 } else { group.0 };
+        let y = (fb % layout.group_fbs) / 2 * fb_rows;
+        // columns grow away from the ZIA, which is between the two function
+        // blocks of a pair
+        let col = |x: usize, offset: usize| if mirror { x - offset } else { x + offset };
+
+        // ZIA, with the last bit of each input first
+        let zia_col = group.0 + 112 + fb % 2;
+        for input in 0..INPUTS {
+            for bit in 0..layout.zia_width {
+                ret.push((
+                    zia_col + 2 * (layout.zia_width - 1 - bit),
+                    y + and_row(input),
+                ));
+            }
+        }
+
+        // AND array, with a column pair for each product term
+        for term in 0..TERMS {
+            let term = if layout.or_in_middle {
+                term
+            } else {
+                AND_TERMS_SIDE.iter().position(|&x| x == term).unwrap()
+            };
+            for input in 0..INPUTS {
+                ret.push((col(x, term * 2 + 1), y + and_row(input)));
+                ret.push((col(x, term * 2), y + and_row(input)));
+            }
+        }
+
+        // OR array
+        let or_x = if layout.or_in_middle {
+            x
+        } else {
+            col(x, 0) - 32 + 64 * mirror as usize
+        };
+        for term in 0..TERMS {
+            for mc in 0..MACROCELLS {
+                ret.push(if layout.or_in_middle {
+                    (col(or_x, term * 2 + mc % 2), y + INPUTS / 2 + mc / 2)
+                } else {
+                    let row = OR_ROWS_SIDE[term / 2];
+                    let swap = (row >= 23) == (term % 2 == 0);
+                    (col(or_x, mc * 2 + swap as usize), y + row)
+                });
+            }
+        }
+
+        // macrocells
+        let mc_x = if mirror {
+            x + layout.mc_offset
+        } else {
+            x - layout.mc_offset
+        };
+        let (buried_start, buried_end) = layout.buried[fb];
+        for (mc, &large_row) in MC_ROWS_LARGE.iter().enumerate() {
+            let mc_y = y + if layout.or_in_middle {
+                mc * 3
+            } else {
+                large_row
+            };
+            let bits = if (buried_start..buried_end).contains(&mc) {
+                layout.mc_buried
+            } else {
+                layout.mc
+            };
+            ret.extend(bits.iter().map(|&(dx, dy)| (col(mc_x, dx), mc_y + dy)));
+        }
+    }
+    ret.extend_from_slice(layout.globals);
+    ret
+}
+
+impl Xc2cFuseMap {
+    /// Generate the map of a device from its known layout, without needing
+    /// a Xilinx map file
+    ///
+    /// The layout of the fuses is that documented by the xc2bit project. It
+    /// includes the DONE bit (in the row after the fuses) but not the
+    /// security bits, whose position is not known, so a device programmed
+    /// using a generated map cannot be read protected.
+    pub fn generate(device: Xc2cDevice) -> Self {
+        let mut cells = vec![Xc2cMapCell::Unused; device.rows() * device.row_len()];
+        for (fuse, (col, row)) in fuse_positions(device).into_iter().enumerate() {
+            cells[row * device.row_len() + col] = Xc2cMapCell::Fuse(fuse);
+        }
+        cells[(device.rows() - 2) * device.row_len() + layout(device).done] = Xc2cMapCell::Done;
+        Self::new(device, cells).expect("generated map matches the device")
+    }
+}
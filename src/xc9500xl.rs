@@ -0,0 +1,571 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+const ISPEN: u8 = 0xe8;
+const FERASE: u8 = 0xec;
+const FBULK: u8 = 0xed;
+const FPGM: u8 = 0xea;
+const FVFY: u8 = 0xee;
+const ISPEX: u8 = 0xf0;
+const IDCODE: u8 = 0xfe;
+const BYPASS: u8 = 0xff;
+const IR_LEN: usize = 8;
+
+/// Value shifted into the 6-bit ISP configuration register by ISPEN
+const ISPEN_MODE: u8 = 0x05;
+
+const ENABLE_DELAY_NS: u64 = 1_000;
+const EXIT_DELAY_NS: u64 = 1_000_000;
+const ERASE_DELAY_NS: u64 = 400_000_000;
+const PROGRAM_DELAY_NS: u64 = 10_000_000;
+const VERIFY_DELAY_NS: u64 = 50_000;
+
+/// Number of sectors (address blocks), each of which is programmed as
+/// [COLUMNS] words
+const SECTORS: usize = 108;
+/// Number of words in each sector
+const COLUMNS: usize = 15;
+/// Number of bits in each address
+const ADDRESS_LEN: usize = 16;
+/// Status bits at the start of the ISP data register
+const STATUS_LEN: usize = 2;
+
+/// The first 9 words of each sector hold 8 bits per function block, the
+/// rest only 6
+fn column_width(col: usize) -> usize {
+    if col < 9 {
+        8
+    } else {
+        6
+    }
+}
+
+/// Address of a word, made of the sector and the column split into groups
+/// of 5
+fn address(sector: usize, col: usize) -> BitVec {
+    let address = sector * 0x20 + (col / 5) * 0x08 + col % 5;
+    address.view_bits::<Lsb0>()[..ADDRESS_LEN].to_bitvec()
+}
+
+fn erase_status_scan() -> BitVec {
+    let mut dr = bitvec![0; STATUS_LEN + ADDRESS_LEN];
+    dr.set(0, true);
+    dr
+}
+
+/// The original 5V XC9500 densities, which program each function block
+/// separately using a different data register layout
+const XC9500_5V: &[&str] = &[
+    "XC9536", "XC9572", "XC95108", "XC95144", "XC95216", "XC95288",
+];
+
+/// Fail if `name` (a part name, optionally with a speed grade and package
+/// suffix) is one of the original 5V XC9500 parts, which [Xc9500Xl] cannot
+/// program
+pub fn check_xc9500_5v(name: &str) -> Result<(), JTAGError> {
+    let base = name.split('-').next().unwrap_or(name);
+    if XC9500_5V.iter().any(|x| x.eq_ignore_ascii_case(base)) {
+        return Err(JTAGError::InvalidAction(format!(
+            "{name} is a 5V XC9500, which is not supported (only XC9500XL and XC9500XV are)"
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+/// An XC9500XL or XC9500XV density
+pub enum Xc9500XlDevice {
+    XC9536XL,
+    XC9572XL,
+    XC95144XL,
+    XC95288XL,
+    XC9536XV,
+    XC9572XV,
+    XC95144XV,
+    XC95288XV,
+}
+
+impl CpldDevice for Xc9500XlDevice {
+    const ALL: &'static [Self] = &[
+        Self::XC9536XL,
+        Self::XC9572XL,
+        Self::XC95144XL,
+        Self::XC95288XL,
+        Self::XC9536XV,
+        Self::XC9572XV,
+        Self::XC95144XV,
+        Self::XC95288XV,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::XC9536XL => "XC9536XL",
+            Self::XC9572XL => "XC9572XL",
+            Self::XC95144XL => "XC95144XL",
+            Self::XC95288XL => "XC95288XL",
+            Self::XC9536XV => "XC9536XV",
+            Self::XC9572XV => "XC9572XV",
+            Self::XC95144XV => "XC95144XV",
+            Self::XC95288XV => "XC95288XV",
+        }
+    }
+}
+
+impl Xc9500XlDevice {
+    /// Number of function blocks, each of which has 18 macrocells
+    pub fn function_blocks(self) -> usize {
+        match self {
+            Self::XC9536XL | Self::XC9536XV => 2,
+            Self::XC9572XL | Self::XC9572XV => 4,
+            Self::XC95144XL | Self::XC95144XV => 8,
+            Self::XC95288XL | Self::XC95288XV => 16,
+        }
+    }
+
+    /// Number of fuses in a JED file for this device
+    pub fn jed_fuses(self) -> usize {
+        SECTORS * (0..COLUMNS).map(column_width).sum::<usize>() * self.function_blocks()
+    }
+
+    /// Length of the data register used by FPGM and FVFY
+    pub fn data_len(self) -> usize {
+        STATUS_LEN + 8 * self.function_blocks() + ADDRESS_LEN
+    }
+}
+
+/// Programs XC9500XL and XC9500XV CPLDs using their ISP instructions
+///
+/// The fuse array is programmed one word at a time, where each word holds
+/// one byte for every function block. The JED file lists the fuses of each
+/// word in function block order, so fuse `n` of a word is bit `n % width` of
+/// function block `n / width`. Fuses are programmed exactly as they are in
+/// the JED file, with programming only able to clear erased (1) bits.
+///
+/// The original 5V XC9500 parts program each function block separately and
+/// are not supported. They are identified by [KNOWN_PARTS] so that
+/// [check_xc9500_5v] can reject them with a clear error.
+pub struct Xc9500Xl {
+    device: Xc9500XlDevice,
+}
+
+impl Xc9500Xl {
+    pub fn new(device: Xc9500XlDevice) -> Self {
+        Self { device }
+    }
+
+    pub fn device(&self) -> Xc9500XlDevice {
+        self.device
+    }
+
+    /// Index of the first JED fuse of a word
+    fn word_fuse(&self, sector: usize, col: usize) -> usize {
+        let sector_len = (0..COLUMNS).map(column_width).sum::<usize>();
+        (sector * sector_len + (0..col).map(column_width).sum::<usize>())
+            * self.device.function_blocks()
+    }
+
+    /// Arrange the fuses of a JED file into the data of each word, in
+    /// programming order
+    pub fn jed_to_words(&self, jed: &Jed) -> Result<Vec<BitVec>, JTAGError> {
+        if jed.fuses.len() != self.device.jed_fuses() {
+            return Err(JTAGError::InvalidAction(format!(
+                "JED has {} fuses but {} has {}",
+                jed.fuses.len(),
+                self.device.name(),
+                self.device.jed_fuses()
+            )));
+        }
+        let mut words = Vec::with_capacity(SECTORS * COLUMNS);
+        for sector in 0..SECTORS {
+            for col in 0..COLUMNS {
+                let width = column_width(col);
+                let base = self.word_fuse(sector, col);
+                let mut word = bitvec![1; 8 * self.device.function_blocks()];
+                for fb in 0..self.device.function_blocks() {
+                    word[fb * 8..fb * 8 + width]
+                        .copy_from_bitslice(&jed.fuses[base + fb * width..][..width]);
+                }
+                words.push(word);
+            }
+        }
+        Ok(words)
+    }
+
+    /// Convert words read back from a device into a JED file
+    pub fn words_to_jed(&self, words: &[BitVec]) -> Jed {
+        let mut fuses = bitvec![0; self.device.jed_fuses()];
+        for (i, word) in words.iter().enumerate() {
+            let (sector, col) = (i / COLUMNS, i % COLUMNS);
+            let width = column_width(col);
+            let base = self.word_fuse(sector, col);
+            for fb in 0..self.device.function_blocks() {
+                fuses[base + fb * width..][..width].copy_from_bitslice(&word[fb * 8..][..width]);
+            }
+        }
+        let mut jed = Jed::new(fuses);
+        jed.notes.push(format!("DEVICE {}", self.device.name()));
+        jed
+    }
+
+    /// Check that the device is the expected one
+    pub fn check_idcode<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<(), JTAGError> {
+        cpld::check_idcode(adapter, &insn(IDCODE, IR_LEN), self.device)
+    }
+
+    /// Enter ISP mode, which stops the device from running its design. This
+    /// is a buffered action that returns immediately
+    pub fn enable<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) {
+        adapter.shift_ir_out(&insn(ISPEN, IR_LEN), false);
+        adapter.shift_dr_out(&insn(ISPEN_MODE, 6), false);
+        adapter.queue_action(JTAGAction::DelayNS(ENABLE_DELAY_NS));
+    }
+
+    /// Leave ISP mode with ISPEX, which makes the device start running the
+    /// programmed design. This is a buffered action that returns immediately
+    pub fn disable<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) {
+        adapter.shift_ir_out(&insn(ISPEX, IR_LEN), false);
+        adapter.queue_action(JTAGAction::DelayNS(EXIT_DELAY_NS));
+        adapter.shift_ir_out(&insn(BYPASS, IR_LEN), false);
+    }
+
+    // Start erasing while in ISP mode. The next scan returns the status
+    fn start_erase<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T, bulk: bool) {
+        adapter.shift_ir_out(&insn(if bulk { FBULK } else { FERASE }, IR_LEN), false);
+        adapter.shift_dr_out(&bitvec![1; STATUS_LEN + ADDRESS_LEN], false);
+        adapter.queue_action(JTAGAction::DelayNS(ERASE_DELAY_NS));
+    }
+
+    // Erase while in ISP mode and check the status bits of the result
+    fn erase_enabled<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        bulk: bool,
+    ) -> Result<(), JTAGError> {
+        self.start_erase(adapter, bulk);
+        let status = adapter.shift_dr_inout(&erase_status_scan(), false)?;
+        if status[..STATUS_LEN] != bits![1, 0] {
+            return Err(JTAGError::VerifyFailed("erase failed".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Erase the whole device with FERASE. If `bulk` is set FBULK is used
+    /// instead, which also clears the read and write protection.
+    pub fn erase<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        bulk: bool,
+    ) -> Result<(), JTAGError> {
+        self.enable(adapter);
+        let ret = self.erase_enabled(adapter, bulk);
+        self.disable(adapter);
+        adapter.flush()?;
+        ret
+    }
+
+    fn program_words<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T, words: &[BitVec]) {
+        adapter.shift_ir_out(&insn(FPGM, IR_LEN), false);
+        for (i, word) in words.iter().enumerate() {
+            let mut dr = bitvec![1; STATUS_LEN];
+            dr.extend_from_bitslice(word);
+            dr.extend_from_bitslice(&address(i / COLUMNS, i % COLUMNS));
+            adapter.shift_dr_out(&dr, false);
+            adapter.queue_action(JTAGAction::DelayNS(PROGRAM_DELAY_NS));
+        }
+    }
+
+    // Data shifted in to read a word while in ISP mode, which selects the
+    // word to be shifted out by the next scan
+    fn read_scan(&self, i: usize) -> BitVec {
+        let i = i % (SECTORS * COLUMNS);
+        let mut dr = bitvec![1; STATUS_LEN];
+        dr.resize(STATUS_LEN + 8 * self.device.function_blocks(), false);
+        dr.extend_from_bitslice(&address(i / COLUMNS, i % COLUMNS));
+        dr
+    }
+
+    // Read every word while in ISP mode. Each scan shifts out the word
+    // selected by the previous scan while shifting in the next address.
+    fn read_words<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+    ) -> Result<Vec<BitVec>, JTAGError> {
+        let data_len = 8 * self.device.function_blocks();
+        adapter.shift_ir_out(&insn(FVFY, IR_LEN), false);
+        adapter.shift_dr_out(&self.read_scan(0), false);
+        let mut words = Vec::with_capacity(SECTORS * COLUMNS);
+        for i in 1..=SECTORS * COLUMNS {
+            adapter.queue_action(JTAGAction::DelayNS(VERIFY_DELAY_NS));
+            let out = adapter.shift_dr_inout(&self.read_scan(i), false)?;
+            words.push(out[STATUS_LEN..STATUS_LEN + data_len].to_bitvec());
+        }
+        Ok(words)
+    }
+
+    // Like read_words, but record the words as expected data instead of
+    // reading them. The unused bits of 6-bit words are not checked.
+    fn expect_words(&self, recorder: &mut SvfRecorder, words: &[BitVec]) -> Result<(), JTAGError> {
+        recorder.shift_ir_out(&insn(FVFY, IR_LEN), false);
+        recorder.shift_dr_out(&self.read_scan(0), false);
+        for (i, word) in words.iter().enumerate() {
+            recorder.queue_action(JTAGAction::DelayNS(VERIFY_DELAY_NS));
+            recorder.shift_dr_out(&self.read_scan(i + 1), false);
+            let mut tdo = bitvec![0; STATUS_LEN];
+            tdo.extend_from_bitslice(word);
+            tdo.resize(self.device.data_len(), false);
+            let mut mask = bitvec![0; STATUS_LEN];
+            for _ in 0..self.device.function_blocks() {
+                let width = column_width(i % COLUMNS);
+                mask.extend_from_bitslice(&bitvec![1; width]);
+                mask.resize(mask.len() + 8 - width, false);
+            }
+            mask.resize(self.device.data_len(), false);
+            recorder.expect_tdo(&tdo, &mask)?;
+        }
+        Ok(())
+    }
+
+    /// Read back the whole device as a JED file. This does not work if the
+    /// device has been read protected.
+    pub fn read<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<Jed, JTAGError> {
+        self.enable(adapter);
+        let words = self.read_words(adapter)?;
+        self.disable(adapter);
+        adapter.flush()?;
+        Ok(self.words_to_jed(&words))
+    }
+
+    /// Check whether the whole device is erased
+    pub fn blank_check<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<bool, JTAGError> {
+        let jed = self.read(adapter)?;
+        Ok(jed.fuses.all())
+    }
+
+    fn verify_words(&self, expected: &Jed, actual: &[BitVec]) -> Result<(), JTAGError> {
+        let actual = self.words_to_jed(actual);
+        match (expected.fuses.iter().by_vals())
+            .zip(actual.fuses.iter().by_vals())
+            .position(|(a, b)| a != b)
+        {
+            Some(fuse) => Err(JTAGError::VerifyFailed(format!(
+                "fuse {fuse} is {}",
+                actual.fuses[fuse] as u8
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that the device contains the fuses from `jed`
+    pub fn verify<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        jed: &Jed,
+    ) -> Result<(), JTAGError> {
+        self.jed_to_words(jed)?;
+        self.enable(adapter);
+        let actual = self.read_words(adapter)?;
+        self.disable(adapter);
+        adapter.flush()?;
+        self.verify_words(jed, &actual)
+    }
+
+    /// Erase the device, then program and verify the fuses from `jed`
+    pub fn program<T: JTAGAdapter + ?Sized>(
+        &self,
+        adapter: &mut T,
+        jed: &Jed,
+    ) -> Result<(), JTAGError> {
+        let words = self.jed_to_words(jed)?;
+        self.check_idcode(adapter)?;
+
+        self.enable(adapter);
+        let ret = self.erase_enabled(adapter, false).and_then(|_| {
+            self.program_words(adapter, &words);
+            let actual = self.read_words(adapter)?;
+            self.verify_words(jed, &actual)
+        });
+        self.disable(adapter);
+        adapter.flush()?;
+        ret
+    }
+
+    /// Generate an SVF file which does the same as [program][Self::program]
+    /// without needing a device attached. Data that would be read back is
+    /// checked using the TDO data of the scans instead.
+    pub fn program_svf(&self, jed: &Jed) -> Result<Svf, JTAGError> {
+        let words = self.jed_to_words(jed)?;
+        let mut recorder = SvfRecorder::new(None);
+        recorder.reset_to_tlr();
+        recorder.go_rti();
+
+        cpld::expect_idcode(&mut recorder, &insn(IDCODE, IR_LEN), self.device)?;
+
+        self.enable(&mut recorder);
+        self.start_erase(&mut recorder, false);
+        recorder.shift_dr_out(&erase_status_scan(), false);
+        // the status is 01 when erasing succeeded, which happens to be the
+        // same as what is shifted in
+        let mut mask = bitvec![0; STATUS_LEN + ADDRESS_LEN];
+        mask[..STATUS_LEN].fill(true);
+        recorder.expect_tdo(&erase_status_scan(), &mask)?;
+        self.program_words(&mut recorder, &words);
+        self.expect_words(&mut recorder, &words)?;
+        self.disable(&mut recorder);
+        recorder.flush()?;
+        Ok(recorder.into_svf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    // Implements enough of the ISP instructions of an XC9536XL to be
+    // programmed
+    struct SimXc9536Xl {
+        words: Vec<BitVec>,
+        address: usize,
+        enabled: bool,
+    }
+
+    fn decode_address(bits: &BitSlice) -> usize {
+        let address = bits.load_le::<usize>();
+        (address >> 5) * 15 + (address >> 3 & 3) * 5 + (address & 7)
+    }
+
+    impl drivers::SimulatedDevice for SimXc9536Xl {
+        fn ir_len(&self) -> usize {
+            8
+        }
+        fn reset_ir(&self) -> BitVec {
+            bitvec![0, 1, 1, 1, 1, 1, 1, 1]
+        }
+        fn update_ir(&mut self, ir: &BitSlice) {
+            match ir.load_le::<u8>() {
+                0xf0 => self.enabled = false,
+                0xff | 0xfe | 0xe8 => {}
+                _ if !self.enabled => panic!("ISP instruction without ISPEN"),
+                _ => {}
+            }
+        }
+        fn capture_dr(&mut self, ir: &BitSlice) -> BitVec {
+            match ir.load_le::<u8>() {
+                0xfe => 0x5960_2093u32
+                    .view_bits::<Lsb0>()
+                    .iter()
+                    .by_vals()
+                    .collect(),
+                0xe8 => bitvec![0; 6],
+                0xec | 0xed => bitvec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                0xea | 0xee => {
+                    let mut ret = bitvec![1, 0];
+                    ret.extend_from_bitslice(&self.words[self.address]);
+                    ret.resize(34, false);
+                    ret
+                }
+                _ => bitvec![0],
+            }
+        }
+        fn update_dr(&mut self, ir: &BitSlice, dr: &BitSlice) {
+            match ir.load_le::<u8>() {
+                0xe8 => self.enabled = dr.load_le::<u8>() == 0x05,
+                0xec | 0xed if dr.all() => self.words.iter_mut().for_each(|x| x.fill(true)),
+                0xea => {
+                    let word = &mut self.words[decode_address(&dr[18..])];
+                    *word &= &dr[2..18];
+                }
+                0xee => self.address = decode_address(&dr[18..]),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_xc9500xl_mapping() {
+        assert_eq!(
+            Xc9500XlDevice::from_idcode(IdCode(0x5960_4093)),
+            Some(Xc9500XlDevice::XC9572XL)
+        );
+        assert_eq!(Xc9500XlDevice::from_idcode(IdCode(0x0950_4093)), None);
+        assert_eq!(IdCode(0x0950_4093).part().unwrap().name, "XC9572");
+        assert!(check_xc9500_5v("XC9572-15-PC84").is_err());
+        assert!(check_xc9500_5v("XC9572XL-10-VQ64").is_ok());
+        assert_eq!(Xc9500XlDevice::XC9536XL.jed_fuses(), 23328);
+        assert_eq!(Xc9500XlDevice::XC95288XV.jed_fuses(), 186624);
+        assert_eq!(Xc9500XlDevice::XC95144XL.data_len(), 82);
+
+        let cpld = Xc9500Xl::new(Xc9500XlDevice::XC9536XL);
+        let mut jed = Jed::new(bitvec![1; 23328]);
+        // second function block, bit 1 of the first word
+        jed.fuses.set(9, false);
+        // first function block, bit 0 of the last word
+        jed.fuses.set(23328 - 12, false);
+        let words = cpld.jed_to_words(&jed).unwrap();
+        assert_eq!(words.len(), 108 * 15);
+        assert!(!words[0][9]);
+        assert!(!words[108 * 15 - 1][0]);
+        // unused bits of 6-bit words are left erased
+        assert!(words[108 * 15 - 1][6..8].all());
+        assert_eq!(words.iter().map(|x| x.count_zeros()).sum::<usize>(), 2);
+        assert_eq!(cpld.words_to_jed(&words).fuses, jed.fuses);
+    }
+
+    #[test]
+    fn test_xc9500xl_program() {
+        let cpld = Xc9500Xl::new(Xc9500XlDevice::XC9536XL);
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(SimXc9536Xl {
+            words: vec![bitvec![0; 16]; 108 * 15],
+            address: 0,
+            enabled: false,
+        })]);
+
+        assert!(!cpld.blank_check(&mut adapter).unwrap());
+        cpld.erase(&mut adapter, true).unwrap();
+        assert!(adapter.delayed_ns() >= 400_000_000);
+        assert!(cpld.blank_check(&mut adapter).unwrap());
+
+        let mut jed = Jed::new(bitvec![1; 23328]);
+        for i in (0..23328).step_by(5) {
+            jed.fuses.set(i, false);
+        }
+        cpld.program(&mut adapter, &jed).unwrap();
+        cpld.verify(&mut adapter, &jed).unwrap();
+        assert_eq!(cpld.read(&mut adapter).unwrap().fuses, jed.fuses);
+
+        jed.fuses.set(1, false);
+        assert!(matches!(
+            cpld.verify(&mut adapter, &jed),
+            Err(JTAGError::VerifyFailed(..))
+        ));
+        assert!(Xc9500Xl::new(Xc9500XlDevice::XC9572XL)
+            .program(&mut adapter, &Jed::new(bitvec![1; 46656]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_xc9500xl_program_svf() {
+        assert_eq!(
+            Xc9500XlDevice::from_name("XC95144XL-10-TQ100"),
+            Some(Xc9500XlDevice::XC95144XL)
+        );
+
+        let cpld = Xc9500Xl::new(Xc9500XlDevice::XC9536XL);
+        let mut jed = Jed::new(bitvec![1; 23328]);
+        for i in (0..23328).step_by(3) {
+            jed.fuses.set(i, false);
+        }
+        let svf = cpld.program_svf(&jed).unwrap();
+        let svf = Svf::parse(&svf.to_string()).unwrap();
+
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(SimXc9536Xl {
+            words: vec![bitvec![0; 16]; 108 * 15],
+            address: 0,
+            enabled: false,
+        })]);
+        svf.play(&mut adapter).unwrap();
+        assert_eq!(cpld.read(&mut adapter).unwrap().fuses, jed.fuses);
+    }
+}
@@ -0,0 +1,982 @@
+use crate::*;
+
+use bitvec::prelude::*;
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+/// A single XSVF command
+///
+/// All data has bit 0 being the first bit shifted. XSIR and XSIR2 are both
+/// represented as [SIR][Self::SIR].
+pub enum XsvfCommand {
+    Complete,
+    TdoMask(BitVec),
+    SIR(BitVec),
+    SDR(BitVec),
+    /// Time to wait in Run-Test/Idle after each scan, in microseconds
+    RunTest(u32),
+    Repeat(u8),
+    SDRSize(u32),
+    SDRTDO {
+        tdi: BitVec,
+        tdo: BitVec,
+    },
+    SetSDRMasks {
+        address_mask: BitVec,
+        data_mask: BitVec,
+    },
+    SDRInc {
+        start: BitVec,
+        data: Vec<BitVec>,
+    },
+    SDRB(BitVec),
+    SDRC(BitVec),
+    SDRE(BitVec),
+    SDRTDOB {
+        tdi: BitVec,
+        tdo: BitVec,
+    },
+    SDRTDOC {
+        tdi: BitVec,
+        tdo: BitVec,
+    },
+    SDRTDOE {
+        tdi: BitVec,
+        tdo: BitVec,
+    },
+    State(JTAGState),
+    EndIR(JTAGState),
+    EndDR(JTAGState),
+    Comment(String),
+    Wait {
+        wait_state: JTAGState,
+        end_state: JTAGState,
+        usecs: u32,
+    },
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash, Default)]
+/// A parsed XSVF file
+pub struct Xsvf {
+    pub commands: Vec<XsvfCommand>,
+}
+
+fn parse_err(offset: usize, msg: impl Into<String>) -> JTAGError {
+    ParseError::new("XSVF", msg).at_offset(offset).into()
+}
+
+const XSTATES: [JTAGState; 16] = [
+    JTAGState::TestLogicReset,
+    JTAGState::RunTestIdle,
+    JTAGState::SelectDR,
+    JTAGState::CaptureDR,
+    JTAGState::ShiftDR,
+    JTAGState::Exit1DR,
+    JTAGState::PauseDR,
+    JTAGState::Exit2DR,
+    JTAGState::UpdateDR,
+    JTAGState::SelectIR,
+    JTAGState::CaptureIR,
+    JTAGState::ShiftIR,
+    JTAGState::Exit1IR,
+    JTAGState::PauseIR,
+    JTAGState::Exit2IR,
+    JTAGState::UpdateIR,
+];
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], JTAGError> {
+        if self.data.len() - self.pos < n {
+            return Err(parse_err(self.pos, "unexpected end of file"));
+        }
+        let ret = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(ret)
+    }
+    fn u8(&mut self) -> Result<u8, JTAGError> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, JTAGError> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+    fn u32(&mut self) -> Result<u32, JTAGError> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    /// Read a big-endian value and return its lowest `len` bits
+    fn bits(&mut self, len: usize) -> Result<BitVec, JTAGError> {
+        let bytes = self.bytes(len.div_ceil(8))?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .flat_map(|byte| byte.view_bits::<Lsb0>().iter().by_vals())
+            .take(len)
+            .collect())
+    }
+    fn state(&mut self) -> Result<JTAGState, JTAGError> {
+        let offset = self.pos;
+        XSTATES
+            .get(self.u8()? as usize)
+            .copied()
+            .ok_or_else(|| parse_err(offset, "invalid state"))
+    }
+}
+
+impl Xsvf {
+    /// Parse the contents of an XSVF file. Anything after XCOMPLETE is
+    /// ignored.
+    pub fn parse(data: &[u8]) -> Result<Self, JTAGError> {
+        let mut reader = Reader { data, pos: 0 };
+        let mut commands = Vec::new();
+        let mut sdr_size = 0;
+        let mut data_mask_len = 0;
+
+        loop {
+            let offset = reader.pos;
+            let command = match reader.u8()? {
+                0x00 => XsvfCommand::Complete,
+                0x01 => XsvfCommand::TdoMask(reader.bits(sdr_size)?),
+                0x02 => {
+                    let len = reader.u8()? as usize;
+                    XsvfCommand::SIR(reader.bits(len)?)
+                }
+                0x03 => XsvfCommand::SDR(reader.bits(sdr_size)?),
+                0x04 => XsvfCommand::RunTest(reader.u32()?),
+                0x07 => XsvfCommand::Repeat(reader.u8()?),
+                0x08 => {
+                    let size = reader.u32()?;
+                    sdr_size = size as usize;
+                    XsvfCommand::SDRSize(size)
+                }
+                0x09 => XsvfCommand::SDRTDO {
+                    tdi: reader.bits(sdr_size)?,
+                    tdo: reader.bits(sdr_size)?,
+                },
+                0x0a => {
+                    let address_mask = reader.bits(sdr_size)?;
+                    let data_mask = reader.bits(sdr_size)?;
+                    data_mask_len = data_mask.count_ones();
+                    XsvfCommand::SetSDRMasks {
+                        address_mask,
+                        data_mask,
+                    }
+                }
+                0x0b => {
+                    let start = reader.bits(sdr_size)?;
+                    let num = reader.u8()?;
+                    let data = (0..num)
+                        .map(|_| reader.bits(data_mask_len))
+                        .collect::<Result<_, _>>()?;
+                    XsvfCommand::SDRInc { start, data }
+                }
+                0x0c => XsvfCommand::SDRB(reader.bits(sdr_size)?),
+                0x0d => XsvfCommand::SDRC(reader.bits(sdr_size)?),
+                0x0e => XsvfCommand::SDRE(reader.bits(sdr_size)?),
+                0x0f => XsvfCommand::SDRTDOB {
+                    tdi: reader.bits(sdr_size)?,
+                    tdo: reader.bits(sdr_size)?,
+                },
+                0x10 => XsvfCommand::SDRTDOC {
+                    tdi: reader.bits(sdr_size)?,
+                    tdo: reader.bits(sdr_size)?,
+                },
+                0x11 => XsvfCommand::SDRTDOE {
+                    tdi: reader.bits(sdr_size)?,
+                    tdo: reader.bits(sdr_size)?,
+                },
+                0x12 => XsvfCommand::State(reader.state()?),
+                0x13 => XsvfCommand::EndIR(match reader.u8()? {
+                    0 => JTAGState::RunTestIdle,
+                    1 => JTAGState::PauseIR,
+                    _ => return Err(parse_err(offset, "invalid XENDIR state")),
+                }),
+                0x14 => XsvfCommand::EndDR(match reader.u8()? {
+                    0 => JTAGState::RunTestIdle,
+                    1 => JTAGState::PauseDR,
+                    _ => return Err(parse_err(offset, "invalid XENDDR state")),
+                }),
+                0x15 => {
+                    let len = reader.u16()? as usize;
+                    XsvfCommand::SIR(reader.bits(len)?)
+                }
+                0x16 => {
+                    let len = data[reader.pos..]
+                        .iter()
+                        .position(|&x| x == 0)
+                        .ok_or_else(|| parse_err(offset, "unterminated XCOMMENT"))?;
+                    let comment = reader.bytes(len + 1)?;
+                    XsvfCommand::Comment(String::from_utf8_lossy(&comment[..len]).into_owned())
+                }
+                0x17 => XsvfCommand::Wait {
+                    wait_state: reader.state()?,
+                    end_state: reader.state()?,
+                    usecs: reader.u32()?,
+                },
+                x => return Err(parse_err(offset, format!("unknown command {x:#04x}"))),
+            };
+
+            let complete = command == XsvfCommand::Complete;
+            commands.push(command);
+            if complete {
+                return Ok(Self { commands });
+            }
+        }
+    }
+
+    /// Play every command using a new [XsvfPlayer]
+    pub fn play<T: JTAGAdapter + ?Sized>(&self, adapter: &mut T) -> Result<(), JTAGError> {
+        XsvfPlayer::new().play(adapter, &self.commands)
+    }
+}
+
+fn write_bits(out: &mut Vec<u8>, bits: &BitSlice) {
+    let len = bits.len().div_ceil(8);
+    let start = out.len();
+    out.resize(start + len, 0);
+    for i in bits.iter_ones() {
+        out[start + len - 1 - i / 8] |= 1 << (i % 8);
+    }
+}
+
+fn check_len(bits: &BitSlice, len: usize) -> Result<(), JTAGError> {
+    if bits.len() != len {
+        return Err(JTAGError::InvalidAction(format!(
+            "XSVF data has {} bits instead of {len}",
+            bits.len()
+        )));
+    }
+    Ok(())
+}
+
+// Convert a RUNTEST into microseconds. XSVF players clock TCK once per
+// microsecond while waiting, so each cycle takes at least a microsecond.
+fn runtest_usecs(runtest: &SvfRunTest, frequency: Option<f64>) -> Result<u32, JTAGError> {
+    let mut usecs = 0f64;
+    if let Some((count, _)) = runtest.run_count {
+        usecs = count as f64;
+        if let Some(hz) = frequency {
+            usecs = usecs.max(count as f64 * 1e6 / hz);
+        }
+    }
+    if let Some(min_time) = runtest.min_time {
+        usecs = usecs.max(min_time * 1e6);
+    }
+    let usecs = usecs.ceil();
+    if usecs > u32::MAX as f64 {
+        return Err(JTAGError::InvalidAction(format!(
+            "cannot wait for {usecs} us in XSVF"
+        )));
+    }
+    Ok(usecs as u32)
+}
+
+impl Xsvf {
+    /// Compile an SVF program into XSVF, with up to `repeat` retries for
+    /// each XSDRTDO that has a wait time.
+    ///
+    /// A RUNTEST in Run-Test/Idle immediately following an SIR or SDR is
+    /// turned into XRUNTEST for that scan, while any other RUNTEST becomes
+    /// XWAIT. XSVF cannot check IR data, so TDO data for SIR is ignored.
+    pub fn from_svf(svf: &Svf, repeat: u8) -> Result<Self, JTAGError> {
+        // only used to track the state persisting between statements
+        let mut player = SvfPlayer::new();
+        let mut commands = vec![XsvfCommand::Repeat(repeat)];
+
+        let mut frequency = None;
+        let mut sdr_size = None;
+        let mut tdo_mask = None;
+        let mut run_test = 0;
+        let mut endir = JTAGState::RunTestIdle;
+        let mut enddr = JTAGState::RunTestIdle;
+
+        let mut i = 0;
+        while i < svf.commands.len() {
+            let command = &svf.commands[i];
+            player.update(command)?;
+            match command {
+                SvfCommand::Frequency(hz) => frequency = *hz,
+                SvfCommand::SIR(..) | SvfCommand::SDR(..) => {
+                    let ir = matches!(command, SvfCommand::SIR(..));
+                    let (tdi, expected) = player.scan_data(ir);
+                    if tdi.is_empty() {
+                        i += 1;
+                        continue;
+                    }
+
+                    // XENDIR/XENDDR can only select Run-Test/Idle or Pause
+                    let (end_state, xend_state) = match (ir, player.endir, player.enddr) {
+                        (true, JTAGState::RunTestIdle, _) | (false, _, JTAGState::RunTestIdle) => {
+                            (JTAGState::RunTestIdle, JTAGState::RunTestIdle)
+                        }
+                        (true, end_state, _) => (end_state, JTAGState::PauseIR),
+                        (false, _, end_state) => (end_state, JTAGState::PauseDR),
+                    };
+
+                    let mut wait = 0;
+                    if let Some(SvfCommand::RunTest(runtest)) = svf.commands.get(i + 1) {
+                        let mut next = player.clone();
+                        next.update(&svf.commands[i + 1])?;
+                        if end_state == xend_state
+                            && next.run_state == JTAGState::RunTestIdle
+                            && next.run_end_state == JTAGState::RunTestIdle
+                        {
+                            wait = runtest_usecs(runtest, frequency)?;
+                            player = next;
+                            i += 1;
+                        }
+                    }
+                    if run_test != wait {
+                        run_test = wait;
+                        commands.push(XsvfCommand::RunTest(wait));
+                    }
+
+                    if ir {
+                        if endir != xend_state {
+                            endir = xend_state;
+                            commands.push(XsvfCommand::EndIR(xend_state));
+                        }
+                        commands.push(XsvfCommand::SIR(tdi));
+                    } else {
+                        if enddr != xend_state {
+                            enddr = xend_state;
+                            commands.push(XsvfCommand::EndDR(xend_state));
+                        }
+                        if sdr_size != Some(tdi.len()) {
+                            sdr_size = Some(tdi.len());
+                            commands.push(XsvfCommand::SDRSize(tdi.len() as u32));
+                        }
+                        let (tdo, mask) = expected.unwrap_or_else(|| {
+                            (
+                                BitVec::repeat(false, tdi.len()),
+                                BitVec::repeat(false, tdi.len()),
+                            )
+                        });
+                        if tdo_mask.as_ref() != Some(&mask) {
+                            commands.push(XsvfCommand::TdoMask(mask.clone()));
+                            tdo_mask = Some(mask.clone());
+                        }
+                        if mask.any() {
+                            commands.push(XsvfCommand::SDRTDO { tdi, tdo });
+                        } else {
+                            commands.push(XsvfCommand::SDR(tdi));
+                        }
+                    }
+
+                    if end_state != xend_state {
+                        commands.push(XsvfCommand::State(end_state));
+                    }
+                }
+                SvfCommand::RunTest(runtest) => commands.push(XsvfCommand::Wait {
+                    wait_state: player.run_state,
+                    end_state: player.run_end_state,
+                    usecs: runtest_usecs(runtest, frequency)?,
+                }),
+                SvfCommand::State(states) => {
+                    commands.extend(states.iter().map(|&state| XsvfCommand::State(state)))
+                }
+                SvfCommand::Trst(SvfTrstMode::On) => {
+                    commands.push(XsvfCommand::State(JTAGState::TestLogicReset))
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        commands.push(XsvfCommand::Complete);
+        Ok(Self { commands })
+    }
+
+    /// Encode as an XSVF file
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JTAGError> {
+        let mut out = Vec::new();
+        let mut sdr_size = 0;
+        let mut data_mask_len = 0;
+
+        for command in &self.commands {
+            match command {
+                XsvfCommand::Complete => out.push(0x00),
+                XsvfCommand::TdoMask(mask) => {
+                    check_len(mask, sdr_size)?;
+                    out.push(0x01);
+                    write_bits(&mut out, mask);
+                }
+                XsvfCommand::SIR(tdi) => {
+                    if let Ok(len) = u8::try_from(tdi.len()) {
+                        out.extend_from_slice(&[0x02, len]);
+                    } else if let Ok(len) = u16::try_from(tdi.len()) {
+                        out.push(0x15);
+                        out.extend_from_slice(&len.to_be_bytes());
+                    } else {
+                        return Err(JTAGError::InvalidAction(format!(
+                            "XSIR cannot shift {} bits",
+                            tdi.len()
+                        )));
+                    }
+                    write_bits(&mut out, tdi);
+                }
+                XsvfCommand::SDR(tdi) => {
+                    check_len(tdi, sdr_size)?;
+                    out.push(0x03);
+                    write_bits(&mut out, tdi);
+                }
+                XsvfCommand::RunTest(usecs) => {
+                    out.push(0x04);
+                    out.extend_from_slice(&usecs.to_be_bytes());
+                }
+                XsvfCommand::Repeat(repeat) => out.extend_from_slice(&[0x07, *repeat]),
+                XsvfCommand::SDRSize(size) => {
+                    sdr_size = *size as usize;
+                    out.push(0x08);
+                    out.extend_from_slice(&size.to_be_bytes());
+                }
+                XsvfCommand::SetSDRMasks {
+                    address_mask,
+                    data_mask,
+                } => {
+                    check_len(address_mask, sdr_size)?;
+                    check_len(data_mask, sdr_size)?;
+                    data_mask_len = data_mask.count_ones();
+                    out.push(0x0a);
+                    write_bits(&mut out, address_mask);
+                    write_bits(&mut out, data_mask);
+                }
+                XsvfCommand::SDRInc { start, data } => {
+                    check_len(start, sdr_size)?;
+                    let num = u8::try_from(data.len()).map_err(|_| {
+                        JTAGError::InvalidAction("XSDRINC takes at most 255 values".to_string())
+                    })?;
+                    out.push(0x0b);
+                    write_bits(&mut out, start);
+                    out.push(num);
+                    for data in data {
+                        check_len(data, data_mask_len)?;
+                        write_bits(&mut out, data);
+                    }
+                }
+                XsvfCommand::SDRB(tdi) | XsvfCommand::SDRC(tdi) | XsvfCommand::SDRE(tdi) => {
+                    check_len(tdi, sdr_size)?;
+                    out.push(match command {
+                        XsvfCommand::SDRB(..) => 0x0c,
+                        XsvfCommand::SDRC(..) => 0x0d,
+                        _ => 0x0e,
+                    });
+                    write_bits(&mut out, tdi);
+                }
+                XsvfCommand::SDRTDO { tdi, tdo }
+                | XsvfCommand::SDRTDOB { tdi, tdo }
+                | XsvfCommand::SDRTDOC { tdi, tdo }
+                | XsvfCommand::SDRTDOE { tdi, tdo } => {
+                    check_len(tdi, sdr_size)?;
+                    check_len(tdo, sdr_size)?;
+                    out.push(match command {
+                        XsvfCommand::SDRTDO { .. } => 0x09,
+                        XsvfCommand::SDRTDOB { .. } => 0x0f,
+                        XsvfCommand::SDRTDOC { .. } => 0x10,
+                        _ => 0x11,
+                    });
+                    write_bits(&mut out, tdi);
+                    write_bits(&mut out, tdo);
+                }
+                XsvfCommand::State(state) => out.extend_from_slice(&[0x12, xstate(*state)]),
+                XsvfCommand::EndIR(state) | XsvfCommand::EndDR(state) => {
+                    let (opcode, pause_state) = match command {
+                        XsvfCommand::EndIR(..) => (0x13, JTAGState::PauseIR),
+                        _ => (0x14, JTAGState::PauseDR),
+                    };
+                    let value = match *state {
+                        JTAGState::RunTestIdle => 0,
+                        x if x == pause_state => 1,
+                        x => {
+                            return Err(JTAGError::InvalidAction(format!(
+                                "{x:?} cannot be an XSVF end state"
+                            )))
+                        }
+                    };
+                    out.extend_from_slice(&[opcode, value]);
+                }
+                XsvfCommand::Comment(comment) => {
+                    if comment.contains('\0') {
+                        return Err(JTAGError::InvalidAction(
+                            "XCOMMENT cannot contain NUL".to_string(),
+                        ));
+                    }
+                    out.push(0x16);
+                    out.extend_from_slice(comment.as_bytes());
+                    out.push(0x00);
+                }
+                XsvfCommand::Wait {
+                    wait_state,
+                    end_state,
+                    usecs,
+                } => {
+                    out.extend_from_slice(&[0x17, xstate(*wait_state), xstate(*end_state)]);
+                    out.extend_from_slice(&usecs.to_be_bytes());
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn xstate(state: JTAGState) -> u8 {
+    XSTATES.iter().position(|&x| x == state).unwrap() as u8
+}
+
+#[derive(Clone, Debug)]
+/// Executes XSVF commands on a [JTAGAdapter]
+///
+/// This follows the behavior of the reference player from Xilinx XAPP503,
+/// including retrying (with the wait time increased by 25% each time) when
+/// XSDR or XSDRTDO see mismatching TDO data while XRUNTEST is nonzero.
+///
+/// Waits clock TCK once per microsecond (like the reference player does) in
+/// addition to delaying for the requested time, so that devices which
+/// count clocks rather than time are also satisfied.
+pub struct XsvfPlayer {
+    tdo_mask: BitVec,
+    tdo_expected: Option<BitVec>,
+    address_mask: BitVec,
+    data_mask: BitVec,
+    run_test: u32,
+    repeat: u8,
+    endir: JTAGState,
+    enddr: JTAGState,
+    tap: svf::PauseTracker,
+}
+
+impl Default for XsvfPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn wait<T: JTAGAdapter + ?Sized>(adapter: &mut T, state: JTAGState, usecs: u32) {
+    if usecs == 0 {
+        return;
+    }
+    if svf::is_stable_state(state) && state != JTAGState::TestLogicReset {
+        adapter.shift_bits_out(&bitvec![0; usecs as usize], false);
+    }
+    adapter.queue_action(JTAGAction::DelayNS(usecs as u64 * 1000));
+}
+
+impl XsvfPlayer {
+    pub fn new() -> Self {
+        Self {
+            tdo_mask: BitVec::new(),
+            tdo_expected: None,
+            address_mask: BitVec::new(),
+            data_mask: BitVec::new(),
+            run_test: 0,
+            repeat: 32,
+            endir: JTAGState::RunTestIdle,
+            enddr: JTAGState::RunTestIdle,
+            tap: svf::PauseTracker::new(),
+        }
+    }
+
+    /// Execute `commands` until XCOMPLETE (or the end) and then flush the
+    /// adapter.
+    ///
+    /// If TDO data still does not match after retrying,
+    /// [JTAGError::VerifyFailed] is returned.
+    pub fn play<T: JTAGAdapter + ?Sized>(
+        &mut self,
+        adapter: &mut T,
+        commands: &[XsvfCommand],
+    ) -> Result<(), JTAGError> {
+        for (i, command) in commands.iter().enumerate() {
+            if *command == XsvfCommand::Complete {
+                break;
+            }
+            self.play_command(adapter, command).map_err(|e| match e {
+                JTAGError::VerifyFailed(msg) => {
+                    JTAGError::VerifyFailed(format!("command {i}: {msg}"))
+                }
+                e => e,
+            })?;
+        }
+        adapter.flush()?;
+        Ok(())
+    }
+
+    /// Execute a single command. This may leave buffered actions queued on
+    /// the adapter.
+    pub fn play_command<T: JTAGAdapter + ?Sized>(
+        &mut self,
+        adapter: &mut T,
+        command: &XsvfCommand,
+    ) -> Result<(), JTAGError> {
+        match command {
+            XsvfCommand::Complete | XsvfCommand::Comment(..) | XsvfCommand::SDRSize(..) => {}
+            XsvfCommand::TdoMask(mask) => self.tdo_mask = mask.clone(),
+            XsvfCommand::SIR(tdi) => self.shift(adapter, true, tdi, None, 0)?,
+            XsvfCommand::SDR(tdi) => {
+                let expected = self.tdo_expected.clone();
+                self.shift(adapter, false, tdi, expected.as_deref(), self.repeat)?;
+            }
+            XsvfCommand::RunTest(usecs) => self.run_test = *usecs,
+            XsvfCommand::Repeat(repeat) => self.repeat = *repeat,
+            XsvfCommand::SDRTDO { tdi, tdo } => {
+                self.tdo_expected = Some(tdo.clone());
+                self.shift(adapter, false, tdi, Some(tdo), self.repeat)?;
+            }
+            XsvfCommand::SetSDRMasks {
+                address_mask,
+                data_mask,
+            } => {
+                self.address_mask = address_mask.clone();
+                self.data_mask = data_mask.clone();
+            }
+            XsvfCommand::SDRInc { start, data } => {
+                let expected = self.tdo_expected.clone();
+                let mut tdi = start.clone();
+                self.shift(adapter, false, &tdi, expected.as_deref(), self.repeat)?;
+                for data in data {
+                    self.increment(&mut tdi, data);
+                    self.shift(adapter, false, &tdi, expected.as_deref(), self.repeat)?;
+                }
+            }
+            XsvfCommand::SDRB(tdi) | XsvfCommand::SDRC(tdi) => {
+                self.shift_partial(adapter, tdi, None, false)?
+            }
+            XsvfCommand::SDRE(tdi) => self.shift_partial(adapter, tdi, None, true)?,
+            XsvfCommand::SDRTDOB { tdi, tdo } | XsvfCommand::SDRTDOC { tdi, tdo } => {
+                self.shift_partial(adapter, tdi, Some(tdo), false)?
+            }
+            XsvfCommand::SDRTDOE { tdi, tdo } => {
+                self.shift_partial(adapter, tdi, Some(tdo), true)?
+            }
+            XsvfCommand::State(state) => self.tap.go(adapter, *state),
+            XsvfCommand::EndIR(state) => self.endir = *state,
+            XsvfCommand::EndDR(state) => self.enddr = *state,
+            XsvfCommand::Wait {
+                wait_state,
+                end_state,
+                usecs,
+            } => {
+                self.tap.go(adapter, *wait_state);
+                wait(adapter, *wait_state, *usecs);
+                self.tap.go(adapter, *end_state);
+            }
+        }
+        Ok(())
+    }
+
+    // Increment the address field and insert the next data (XSDRINC)
+    fn increment(&self, tdi: &mut BitSlice, data: &BitSlice) {
+        for i in self.address_mask.iter_ones() {
+            let bit = tdi[i];
+            tdi.set(i, !bit);
+            if !bit {
+                break;
+            }
+        }
+        for (i, bit) in self.data_mask.iter_ones().zip(data.iter().by_vals()) {
+            tdi.set(i, bit);
+        }
+    }
+
+    fn check(&self, ir: bool, out: &BitSlice, expected: &BitSlice) -> Result<(), JTAGError> {
+        let mask = |i: usize| self.tdo_mask.get(i).as_deref().copied().unwrap_or(true);
+        // XSDR reuses the last XSDRTDO data even if the length has changed
+        // since, which is fine as long as the mask ignores every bit
+        if let Some(bit) =
+            (0..out.len()).find(|&i| mask(i) && expected.get(i).as_deref() != Some(&out[i]))
+        {
+            let mask = (0..out.len()).map(mask).collect::<BitVec>();
+            return Err(JTAGError::VerifyFailed(format!(
+                "{} bit {bit} is {}: expected ({}) mask ({}), got ({})",
+                if ir { "XSIR" } else { "XSDR" },
+                out[bit] as u8,
+                svf::format_hex(expected),
+                svf::format_hex(&mask),
+                svf::format_hex(out)
+            )));
+        }
+        Ok(())
+    }
+
+    fn shift<T: JTAGAdapter + ?Sized>(
+        &mut self,
+        adapter: &mut T,
+        ir: bool,
+        tdi: &BitSlice,
+        expected: Option<&BitSlice>,
+        max_repeat: u8,
+    ) -> Result<(), JTAGError> {
+        let end_state = if ir { self.endir } else { self.enddr };
+        let mut run_test = self.run_test;
+
+        if tdi.is_empty() {
+            if run_test > 0 {
+                adapter.go_rti();
+                wait(adapter, JTAGState::RunTestIdle, run_test);
+                self.tap.set(JTAGState::RunTestIdle);
+            }
+            return Ok(());
+        }
+
+        self.tap.resume(adapter, ir);
+        self.tap.set(if run_test > 0 {
+            JTAGState::RunTestIdle
+        } else {
+            end_state
+        });
+
+        let Some(expected) = expected else {
+            let pause = end_state != JTAGState::RunTestIdle;
+            if ir {
+                adapter.shift_ir_out(tdi, pause);
+            } else {
+                adapter.shift_dr_out(tdi, pause);
+            }
+            if run_test > 0 {
+                adapter.go_rti();
+                wait(adapter, JTAGState::RunTestIdle, run_test);
+            }
+            return Ok(());
+        };
+
+        let mut attempt = 0;
+        loop {
+            // Always stop in Pause so that a retry can go back to Shift
+            let out = if ir {
+                adapter.shift_ir_inout(tdi, true)?
+            } else {
+                adapter.shift_dr_inout(tdi, true)?
+            };
+            let ret = self.check(ir, &out, expected);
+
+            let retry = ret.is_err() && run_test > 0 && attempt < max_repeat;
+            if retry {
+                // Shift one extra bit and then update
+                adapter.go_to_state(if ir {
+                    JTAGState::ShiftIR
+                } else {
+                    JTAGState::ShiftDR
+                });
+            } else {
+                adapter.go_to_state(end_state);
+            }
+            if run_test > 0 {
+                adapter.go_rti();
+                wait(adapter, JTAGState::RunTestIdle, run_test);
+            }
+
+            if !retry {
+                return ret;
+            }
+            run_test += run_test >> 2;
+            attempt += 1;
+        }
+    }
+
+    fn shift_partial<T: JTAGAdapter + ?Sized>(
+        &mut self,
+        adapter: &mut T,
+        tdi: &BitSlice,
+        expected: Option<&BitSlice>,
+        exit: bool,
+    ) -> Result<(), JTAGError> {
+        adapter.go_shiftdr();
+        if !tdi.is_empty() {
+            match expected {
+                Some(expected) => {
+                    let out = adapter.shift_bits_inout(tdi, exit)?;
+                    self.check(false, &out, expected)?;
+                }
+                None => adapter.shift_bits_out(tdi, exit),
+            }
+        }
+        self.tap.set(JTAGState::ShiftDR);
+        if exit {
+            adapter.go_to_state(self.enddr);
+            self.tap.set(self.enddr);
+            if self.run_test > 0 {
+                adapter.go_rti();
+                wait(adapter, JTAGState::RunTestIdle, self.run_test);
+                self.tap.set(JTAGState::RunTestIdle);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    use bitvec::prelude::*;
+
+    // Read the IDCODE of the test device
+    const TEST_XSVF: &[u8] = &[
+        0x13, 0x00, // XENDIR Run-Test/Idle
+        0x14, 0x00, // XENDDR Run-Test/Idle
+        0x12, 0x00, // XSTATE Test-Logic-Reset
+        0x12, 0x01, // XSTATE Run-Test/Idle
+        0x07, 0x00, // XREPEAT 0
+        0x02, 0x04, 0x06, // XSIR 4 bits 0x6
+        0x08, 0x00, 0x00, 0x00, 0x20, // XSDRSIZE 32
+        0x01, 0x0f, 0xff, 0xff, 0xff, // XTDOMASK
+        0x09, 0x00, 0x00, 0x00, 0x00, 0x01, 0x23, 0x40, 0x93, // XSDRTDO
+        0x16, b'h', b'i', 0x00, // XCOMMENT
+        0x00, // XCOMPLETE
+    ];
+
+    #[test]
+    fn test_xsvf_parse() {
+        let xsvf = Xsvf::parse(TEST_XSVF).unwrap();
+        assert_eq!(xsvf.commands.len(), 11);
+        assert_eq!(xsvf.commands[3], XsvfCommand::State(JTAGState::RunTestIdle));
+        assert_eq!(xsvf.commands[5], XsvfCommand::SIR(bitvec![0, 1, 1, 0]));
+        let mut tdo = bitvec![0; 32];
+        tdo.store_le(0x0123_4093u32);
+        assert_eq!(
+            xsvf.commands[8],
+            XsvfCommand::SDRTDO {
+                tdi: bitvec![0; 32],
+                tdo
+            }
+        );
+        assert_eq!(xsvf.commands[9], XsvfCommand::Comment("hi".to_string()));
+
+        assert!(Xsvf::parse(&TEST_XSVF[..TEST_XSVF.len() - 1]).is_err());
+        assert!(Xsvf::parse(&[0x05]).is_err());
+    }
+
+    #[test]
+    fn test_xsvf_play() {
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(
+            drivers::GenericSimulatedDevice::new(4).with_idcode(0x0123_4093, bits![0, 1, 1, 0]),
+        )]);
+        Xsvf::parse(TEST_XSVF).unwrap().play(&mut adapter).unwrap();
+        assert_eq!(adapter.tap_state(), JTAGState::RunTestIdle);
+
+        let mut xsvf = TEST_XSVF.to_vec();
+        xsvf[31] = 0x50;
+        let ret = Xsvf::parse(&xsvf).unwrap().play(&mut adapter);
+        assert!(matches!(ret, Err(JTAGError::VerifyFailed(..))));
+    }
+
+    // Reports busy in its status register a given number of times
+    struct BusyDevice {
+        busy_count: usize,
+    }
+    impl drivers::SimulatedDevice for BusyDevice {
+        fn ir_len(&self) -> usize {
+            2
+        }
+        fn reset_ir(&self) -> BitVec {
+            bitvec![1, 1]
+        }
+        fn capture_dr(&mut self, ir: &BitSlice) -> BitVec {
+            if ir == bits![0, 1] {
+                let busy = self.busy_count > 0;
+                self.busy_count = self.busy_count.saturating_sub(1);
+                let mut ret = bitvec![0; 8];
+                ret.set(0, !busy);
+                ret
+            } else {
+                bitvec![0]
+            }
+        }
+        fn update_dr(&mut self, _ir: &BitSlice, _dr: &BitSlice) {}
+    }
+
+    #[test]
+    fn test_xsvf_retry() {
+        let xsvf = |repeat| {
+            Xsvf::parse(&[
+                0x12, 0x00, // XSTATE Test-Logic-Reset
+                0x04, 0x00, 0x00, 0x00, 0x0a, // XRUNTEST 10us
+                0x07, repeat, // XREPEAT
+                0x02, 0x02, 0x02, // XSIR 2 bits 0x2
+                0x08, 0x00, 0x00, 0x00, 0x08, // XSDRSIZE 8
+                0x01, 0x01, // XTDOMASK
+                0x09, 0x00, 0x01, // XSDRTDO
+                0x00, // XCOMPLETE
+            ])
+            .unwrap()
+        };
+
+        let mut adapter =
+            drivers::SimulatedJTAGAdapter::new(vec![Box::new(BusyDevice { busy_count: 3 })]);
+        xsvf(3).play(&mut adapter).unwrap();
+        assert_eq!(adapter.tap_state(), JTAGState::RunTestIdle);
+
+        let mut adapter =
+            drivers::SimulatedJTAGAdapter::new(vec![Box::new(BusyDevice { busy_count: 3 })]);
+        let ret = xsvf(2).play(&mut adapter);
+        assert!(matches!(ret, Err(JTAGError::VerifyFailed(..))));
+    }
+
+    #[test]
+    fn test_xsvf_from_svf() {
+        let svf = Svf::parse(
+            "
+            STATE RESET;
+            ENDDR DRPAUSE;
+            SIR 4 TDI (6);
+            SDR 32 TDI (0) TDO (01234093) MASK (0FFFFFFF);
+            SDR 32 TDI (0) TDO (01234093);
+            SIR 4 TDI (8);
+            SDR 8 TDI (A5);
+            RUNTEST 100 TCK;
+            STATE IDLE;
+            RUNTEST 1E-3 SEC;
+            ",
+        )
+        .unwrap();
+        let xsvf = Xsvf::from_svf(&svf, 0).unwrap();
+
+        let mut idcode = bitvec![0; 32];
+        idcode.store_le(0x0123_4093u32);
+        let mut mask = bitvec![1; 28];
+        mask.resize(32, false);
+        let sdrtdo = XsvfCommand::SDRTDO {
+            tdi: bitvec![0; 32],
+            tdo: idcode,
+        };
+        assert_eq!(
+            xsvf.commands,
+            vec![
+                XsvfCommand::Repeat(0),
+                XsvfCommand::State(JTAGState::TestLogicReset),
+                XsvfCommand::SIR(bitvec![0, 1, 1, 0]),
+                XsvfCommand::EndDR(JTAGState::PauseDR),
+                XsvfCommand::SDRSize(32),
+                XsvfCommand::TdoMask(mask),
+                sdrtdo.clone(),
+                sdrtdo,
+                XsvfCommand::SIR(bitvec![0, 0, 0, 1]),
+                XsvfCommand::RunTest(100),
+                XsvfCommand::SDRSize(8),
+                XsvfCommand::TdoMask(bitvec![0; 8]),
+                XsvfCommand::SDR(bitvec![1, 0, 1, 0, 0, 1, 0, 1]),
+                XsvfCommand::State(JTAGState::RunTestIdle),
+                XsvfCommand::Wait {
+                    wait_state: JTAGState::RunTestIdle,
+                    end_state: JTAGState::RunTestIdle,
+                    usecs: 1000,
+                },
+                XsvfCommand::Complete,
+            ]
+        );
+
+        let bytes = xsvf.to_bytes().unwrap();
+        assert_eq!(Xsvf::parse(&bytes).unwrap(), xsvf);
+
+        let mut adapter = drivers::SimulatedJTAGAdapter::new(vec![Box::new(
+            drivers::GenericSimulatedDevice::new(4)
+                .with_idcode(0x0123_4093, bits![0, 1, 1, 0])
+                .with_register(bits![0, 0, 0, 1], bits![0; 8]),
+        )]);
+        xsvf.play(&mut adapter).unwrap();
+        let reg = adapter.read_reg(bits![0, 0, 0, 1], 8).unwrap();
+        assert_eq!(reg, bits![1, 0, 1, 0, 0, 1, 0, 1]);
+    }
+}